@@ -0,0 +1,288 @@
+//! Compares loading large XML maps with `Map::from_xml` against the previous approach of converting the document to a `serde_json::Value` first.
+//!
+//! Peak memory is printed before the timings, measured with a counting allocator, along with that of `Map::from_xml_reader`, which is checked not to hold on to the elements it has read.
+
+use criterion::{criterion_group, BenchmarkId, Criterion, Throughput};
+use quick_xml::{events::Event, Reader};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let ptr = System.realloc(ptr, layout, new_size);
+
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(new_size, Ordering::Relaxed) + new_size;
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+
+        ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Returns the most memory allocated at once while running `f`, and how much of it its result holds, beyond what was already allocated.
+fn memory<T, F: FnOnce() -> T>(f: F) -> (usize, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+
+    let value = f();
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    let held = ALLOCATED.load(Ordering::Relaxed) - before;
+    drop(value);
+
+    (peak, held)
+}
+
+/// A fixed-size map with `tilesets` external tilesets followed by `layers` layers of `size` × `size` tiles, stored as CSV or as `<tile>` elements.
+fn fixed_map(size: usize, layers: usize, tilesets: usize, csv: bool) -> String {
+    let data = if csv {
+        let row = vec!["1"; size].join(",");
+        format!(
+            "<data encoding=\"csv\">\n{}\n</data>",
+            vec![row; size].join(",\n")
+        )
+    } else {
+        format!(
+            "<data>\n{}</data>",
+            "   <tile gid=\"1\"/>\n".repeat(size * size)
+        )
+    };
+    let layers = (1..=layers)
+        .map(|id| {
+            format!(
+                r#" <layer id="{}" name="Layer {}" width="{}" height="{}">
+  {}
+ </layer>
+"#,
+                id, id, size, size, data
+            )
+        })
+        .collect::<String>();
+    let tilesets = (0..tilesets)
+        .map(|i| {
+            format!(
+                r#" <tileset firstgid="{}" source="terrain{}.tsx"/>
+"#,
+                i * 256 + 1,
+                i
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="16" tileheight="16" infinite="0" nextlayerid="{}" nextobjectid="1">
+{}{}</map>
+"##,
+        size,
+        size,
+        layers.len() + 1,
+        tilesets,
+        layers
+    )
+}
+
+/// An infinite map with a layer of `chunks` × `chunks` base64 encoded chunks of 16 × 16 tiles.
+fn chunked_map(chunks: usize) -> String {
+    let data = base64::encode([1, 0, 0, 0].repeat(16 * 16));
+    let chunks = (0..chunks * chunks)
+        .map(|i| {
+            format!(
+                r#"   <chunk x="{}" y="{}" width="16" height="16">{}</chunk>
+"#,
+                i % chunks * 16,
+                i / chunks * 16,
+                data
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="16" height="16" tilewidth="16" tileheight="16" infinite="1" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground" width="16" height="16">
+  <data encoding="base64">
+{}  </data>
+ </layer>
+</map>
+"##,
+        chunks
+    )
+}
+
+/// The previous approach: the whole document as a `Value`, with every child element in an array and text under `_`.
+fn to_json(reader: &mut Reader<&[u8]>, buf: &mut Vec<u8>) -> Map<String, Value> {
+    let mut children = Map::new();
+
+    loop {
+        let (name, map) = match reader.read_event(buf).unwrap() {
+            Event::Start(e) => {
+                let mut map = to_json(reader, &mut Vec::new());
+                map.extend(attributes(&e));
+                (e.name().to_vec(), map)
+            }
+            Event::Empty(e) => (e.name().to_vec(), attributes(&e)),
+            Event::Text(e) => {
+                let text = e.unescape_and_decode(reader).unwrap();
+
+                if !text.trim().is_empty() {
+                    children.insert("_".to_string(), Value::String(text));
+                }
+
+                buf.clear();
+                continue;
+            }
+            Event::End(_) | Event::Eof => break,
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+
+        let name = String::from_utf8(name).unwrap();
+        children
+            .entry(name)
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .unwrap()
+            .push(Value::Object(map));
+        buf.clear();
+    }
+
+    children
+}
+
+fn attributes(e: &quick_xml::events::BytesStart) -> Map<String, Value> {
+    e.attributes()
+        .map(|attribute| {
+            let attribute = attribute.unwrap();
+            let key = String::from_utf8(attribute.key.to_vec()).unwrap();
+            let value = String::from_utf8(attribute.unescaped_value().unwrap().to_vec()).unwrap();
+
+            (key, Value::String(value))
+        })
+        .collect()
+}
+
+fn via_json(xml: &str) -> tmx::Map {
+    #[derive(Deserialize)]
+    struct Doc {
+        map: Vec<tmx::Map>,
+    }
+
+    let json = to_json(&mut Reader::from_str(xml), &mut Vec::new());
+    let doc: Doc = serde_json::from_value(Value::Object(json)).unwrap();
+    doc.map.into_iter().next().unwrap()
+}
+
+fn native(xml: &str) -> tmx::Map {
+    tmx::Map::from_xml(xml).unwrap()
+}
+
+fn streamed(xml: &str) -> tmx::Map {
+    tmx::Map::from_xml_reader(xml.as_bytes()).unwrap()
+}
+
+fn maps() -> Vec<(&'static str, String)> {
+    vec![
+        ("csv 512x512x4", fixed_map(512, 4, 1, true)),
+        ("tile elements 256x256x2", fixed_map(256, 2, 1, false)),
+        (
+            "tile elements 256x256x4 after 8 tilesets",
+            fixed_map(256, 4, 8, false),
+        ),
+        ("base64 chunks 64x64", chunked_map(64)),
+    ]
+}
+
+fn load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load");
+    group.sample_size(10);
+
+    for (name, xml) in maps() {
+        assert_eq!(native(&xml), via_json(&xml));
+        assert_eq!(streamed(&xml), native(&xml));
+
+        group.throughput(Throughput::Bytes(xml.len() as u64));
+        group.bench_with_input(BenchmarkId::new("native", name), &xml, |b, xml| {
+            b.iter(|| native(xml))
+        });
+        group.bench_with_input(BenchmarkId::new("streamed", name), &xml, |b, xml| {
+            b.iter(|| streamed(xml))
+        });
+        group.bench_with_input(BenchmarkId::new("via JSON", name), &xml, |b, xml| {
+            b.iter(|| via_json(xml))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, load);
+
+fn main() {
+    let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+
+    for (name, xml) in maps() {
+        println!(
+            "{} ({:.1} MiB): peak memory {:.1} MiB native, {:.1} MiB streamed, {:.1} MiB via JSON",
+            name,
+            mib(xml.len()),
+            mib(memory(|| native(&xml)).0),
+            mib(memory(|| streamed(&xml)).0),
+            mib(memory(|| via_json(&xml)).0),
+        );
+    }
+
+    // Elements are deserialized as they are read, so the memory used beyond what the map holds doesn't grow with the number of layers after the tilesets. `<tile>` elements take far more memory as XML events than as tiles, so keeping the layers that follow the tilesets while looking for more tilesets would show here.
+    let overhead = |layers| {
+        let xml = fixed_map(256, layers, 8, false);
+        let (peak, held) = memory(|| streamed(&xml));
+        peak - held
+    };
+    let (one, four) = (overhead(1), overhead(4));
+    println!(
+        "streamed overhead: {:.1} MiB with 1 layer, {:.1} MiB with 4",
+        mib(one),
+        mib(four)
+    );
+    assert!(
+        four < one + one / 2,
+        "reading 4 layers from a reader used {} bytes beyond the map, against {} for 1",
+        four,
+        one
+    );
+
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
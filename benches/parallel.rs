@@ -0,0 +1,84 @@
+//! Compares decoding the tile data of large infinite maps in parallel against decoding it sequentially.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::Write;
+use tmx::ParseOptions;
+
+/// An infinite map with `layers` layers of `chunks` × `chunks` zlib compressed chunks of 16 × 16 tiles.
+fn chunked_map(layers: usize, chunks: usize) -> String {
+    let chunk = |seed: usize| {
+        // Varying gids, so that the chunks don't all compress to almost nothing.
+        let tiles = (0..16 * 16)
+            .flat_map(|i| (((seed * 31 + i * 7) % 97) as u32 + 1).to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut encoder = libflate::zlib::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(&tiles).unwrap();
+
+        base64::encode(encoder.finish().into_result().unwrap())
+    };
+
+    let layers = (1..=layers)
+        .map(|id| {
+            let chunks = (0..chunks * chunks)
+                .map(|i| {
+                    format!(
+                        "   <chunk x=\"{}\" y=\"{}\" width=\"16\" height=\"16\">{}</chunk>\n",
+                        i % chunks * 16,
+                        i / chunks * 16,
+                        chunk(id * chunks * chunks + i)
+                    )
+                })
+                .collect::<String>();
+
+            format!(
+                " <layer id=\"{}\" name=\"Layer {}\" width=\"16\" height=\"16\">\n  <data encoding=\"base64\" compression=\"zlib\">\n{}  </data>\n </layer>\n",
+                id, id, chunks
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="16" height="16" tilewidth="16" tileheight="16" infinite="1" nextlayerid="{}" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+{}</map>
+"##,
+        layers.len() + 1,
+        layers
+    )
+}
+
+fn load(xml: &str, options: &ParseOptions) -> tmx::Map {
+    tmx::Map::from_data_with_options(xml.as_bytes(), options).unwrap()
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.sample_size(10);
+
+    let parallel = ParseOptions::default();
+    let sequential = ParseOptions {
+        sequential: true,
+        ..ParseOptions::default()
+    };
+
+    for (name, xml) in [
+        ("1 layer 64x64 chunks", chunked_map(1, 64)),
+        ("8 layers 16x16 chunks", chunked_map(8, 16)),
+    ] {
+        assert_eq!(load(&xml, &parallel), load(&xml, &sequential));
+
+        group.throughput(Throughput::Bytes(xml.len() as u64));
+        group.bench_with_input(BenchmarkId::new("parallel", name), &xml, |b, xml| {
+            b.iter(|| load(xml, &parallel))
+        });
+        group.bench_with_input(BenchmarkId::new("sequential", name), &xml, |b, xml| {
+            b.iter(|| load(xml, &sequential))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tmx::{layer::LayerData, Map, ParseOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Map::from_data(data);
+    let _ = Map::from_reader(data);
+    let _ = Map::peek_header(data);
+
+    let options = ParseOptions {
+        lazy_chunks: Some(1),
+        ..ParseOptions::default()
+    };
+
+    if let Ok(map) = Map::from_data_with_options(data, &options) {
+        for layer in &map.layers {
+            if let LayerData::LazyChunks(chunks) = &layer.data {
+                let _ = chunks.to_chunks();
+            }
+        }
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tmx::{options::ParseLimits, Map, ParseOptions};
+
+fuzz_target!(|data: &[u8]| {
+    // Padding allocates every cell of a layer, so keep sizes within what the fuzzer can handle.
+    let options = ParseOptions {
+        lenient: true,
+        limits: ParseLimits {
+            max_decompressed_bytes: Some(1 << 20),
+            max_tiles: Some(1 << 18),
+            ..ParseLimits::default()
+        },
+        ..ParseOptions::default()
+    };
+
+    let _ = Map::from_data_with_warnings(data, &options);
+});
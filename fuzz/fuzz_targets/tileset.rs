@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tmx::Tileset;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Tileset::from_data(data);
+    let _ = Tileset::from_reader(data);
+});
@@ -0,0 +1,1090 @@
+//! A compact binary encoding of maps and tilesets, for baking them at build time and loading them quickly at runtime (see `Map::to_cache_bytes`).
+//!
+//! The encoding starts with a header: the magic bytes `TMXC`, the format `VERSION` (`u16`), what is encoded (`u8`, a map or a tileset), a reserved byte, the length of the payload (`u32`) and its CRC-32 checksum (`u32`), all little-endian. The payload holds the fields of each type in declaration order, with tile data as plain 8, 16 or 32 bit cells.
+//!
+//! Bytes written by another version of the format are rejected rather than converted, so caches should be rebuilt whenever the crate is updated. Attributes kept with the `preserve-unknown` feature are always written, and skipped when loading without it.
+
+#[cfg(feature = "std")]
+use crate::layer::{EncodedChunk, LazyChunks};
+use crate::{
+    error::{CacheError, Error},
+    layer::{
+        Cells, Chunk, CompactTiles, Compression, DataFormat, Encoding, Layer, LayerData, Payload,
+        Tile,
+    },
+    map::{self, Map, Orientation, RenderOrder, StaggerAxis, StaggerIndex, TilesetKind},
+    metadata::Metadata,
+    options::ParseLimits,
+    tileset::{Frame, Image, Tileset},
+};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{
+    convert::{TryFrom, TryInto},
+    time::Duration,
+};
+use serde_json::{Number, Value};
+
+/// The version of the format, incremented whenever the encoding of any type changes.
+pub const VERSION: u16 = 1;
+
+const MAGIC: &[u8; 4] = b"TMXC";
+const HEADER_LEN: usize = 16;
+/// How deeply nested preserved values may be, to keep decoding corrupt bytes from overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/// What the payload holds, stored in the header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Kind {
+    Map = 1,
+    Tileset = 2,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Map => "map",
+            Kind::Tileset => "tileset",
+        }
+    }
+}
+
+/// Encodes `value` as a payload of the given kind, preceded by the header.
+///
+/// Fails if the payload doesn't fit the length in the header (4 GiB).
+pub(crate) fn to_bytes<T: Encode>(kind: Kind, value: &T) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.push(kind as u8);
+    bytes.push(0);
+    bytes.extend_from_slice(&[0; 8]);
+
+    value.encode(&mut bytes);
+
+    let payload = &bytes[HEADER_LEN..];
+    let len = u32::try_from(payload.len()).map_err(|_| CacheError::TooLarge(payload.len()))?;
+    let checksum = crc32fast::hash(payload);
+    bytes[8..12].copy_from_slice(&len.to_le_bytes());
+    bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+
+    Ok(bytes)
+}
+
+/// Checks the header and checksum of `bytes` and decodes the payload, which must be of the given kind.
+pub(crate) fn from_bytes<T: Decode>(kind: Kind, bytes: &[u8]) -> Result<T, Error> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err(CacheError::InvalidMagic.into());
+    }
+
+    let mut header = Input::new(&bytes[4..HEADER_LEN]);
+    let version = header.u16()?;
+    let found = header.u8()?;
+    header.u8()?;
+    let len = header.u32()? as usize;
+    let checksum = header.u32()?;
+
+    if version != VERSION {
+        return Err(CacheError::UnsupportedVersion(version).into());
+    }
+
+    if found != kind as u8 {
+        return Err(CacheError::WrongKind {
+            expected: kind.name(),
+        }
+        .into());
+    }
+
+    let payload = &bytes[HEADER_LEN..];
+
+    if payload.len() != len {
+        return Err(CacheError::Length {
+            expected: len,
+            found: payload.len(),
+        }
+        .into());
+    }
+
+    let found = crc32fast::hash(payload);
+
+    if found != checksum {
+        return Err(CacheError::Checksum {
+            expected: checksum,
+            found,
+        }
+        .into());
+    }
+
+    let mut input = Input::new(payload);
+    let value = T::decode(&mut input)?;
+
+    if !input.bytes.is_empty() {
+        return Err(CacheError::TrailingBytes(input.bytes.len()).into());
+    }
+
+    Ok(value)
+}
+
+/// The bytes left to decode.
+pub(crate) struct Input<'a> {
+    bytes: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Input<'a> {
+    fn new(bytes: &'a [u8]) -> Input<'a> {
+        Input { bytes, depth: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CacheError> {
+        if self.bytes.len() < len {
+            return Err(CacheError::UnexpectedEof);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CacheError> {
+        // `take` returns exactly `N` bytes.
+        Ok(self.take(N)?.try_into().unwrap_or([0; N]))
+    }
+
+    fn u8(&mut self) -> Result<u8, CacheError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, CacheError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, CacheError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, CacheError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    /// Reads the length of a sequence whose elements take at least `size` bytes each, checking that they can be there before anything is allocated for them.
+    fn len(&mut self, size: usize) -> Result<usize, CacheError> {
+        let len = self.u64()?;
+
+        match usize::try_from(len) {
+            Ok(len) if len.saturating_mul(size) <= self.bytes.len() => Ok(len),
+            _ => Err(CacheError::UnexpectedEof),
+        }
+    }
+
+    fn tag(&mut self, name: &'static str, max: u8) -> Result<u8, CacheError> {
+        match self.u8()? {
+            tag if tag <= max => Ok(tag),
+            _ => Err(CacheError::Invalid(name)),
+        }
+    }
+}
+
+/// Writes a value to the payload.
+pub(crate) trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// Reads a value written by `Encode` from the payload.
+pub(crate) trait Decode: Sized {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError>;
+}
+
+macro_rules! number {
+    ($($ty:ty),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+                    input.array().map(<$ty>::from_le_bytes)
+                }
+            }
+        )*
+    };
+}
+
+number!(u8, u16, u32, u64, i32, i64);
+
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl Decode for usize {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        usize::try_from(input.u64()?).map_err(|_| CacheError::Invalid("size"))
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.to_bits().encode(out);
+    }
+}
+
+impl Decode for f64 {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        input.u64().map(f64::from_bits)
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        Ok(input.tag("bool", 1)? == 1)
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_str().encode(out);
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let len = input.len(1)?;
+        let bytes = input.take(len)?;
+
+        core::str::from_utf8(bytes)
+            .map(String::from)
+            .map_err(|_| CacheError::Invalid("string"))
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        match input.tag("option", 1)? {
+            0 => Ok(None),
+            _ => T::decode(input).map(Some),
+        }
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+
+        for value in self {
+            value.encode(out);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode(out);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        // Every value takes at least a byte.
+        let len = input.len(1)?;
+        (0..len).map(|_| T::decode(input)).collect()
+    }
+}
+
+impl Encode for Duration {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_secs().encode(out);
+        self.subsec_nanos().encode(out);
+    }
+}
+
+impl Decode for Duration {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let secs = input.u64()?;
+        let nanos = input.u32()?;
+
+        match nanos {
+            0..=999_999_999 => Ok(Duration::new(secs, nanos)),
+            _ => Err(CacheError::Invalid("duration")),
+        }
+    }
+}
+
+impl Encode for Value {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Null => out.push(0),
+            Value::Bool(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+            Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+                (Some(value), _) => {
+                    out.push(2);
+                    value.encode(out);
+                }
+                (None, Some(value)) => {
+                    out.push(3);
+                    value.encode(out);
+                }
+                (None, None) => {
+                    out.push(4);
+                    number.as_f64().unwrap_or_default().encode(out);
+                }
+            },
+            Value::String(value) => {
+                out.push(5);
+                value.encode(out);
+            }
+            Value::Array(values) => {
+                out.push(6);
+                values.encode(out);
+            }
+            Value::Object(entries) => {
+                out.push(7);
+                entries.len().encode(out);
+
+                for (key, value) in entries {
+                    key.encode(out);
+                    value.encode(out);
+                }
+            }
+        }
+    }
+}
+
+impl Decode for Value {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        if input.depth >= MAX_DEPTH {
+            return Err(CacheError::Invalid("value"));
+        }
+
+        input.depth += 1;
+
+        let value = match input.tag("value", 7)? {
+            0 => Value::Null,
+            1 => Value::Bool(bool::decode(input)?),
+            2 => Value::from(input.u64()?),
+            3 => Value::from(i64::decode(input)?),
+            4 => Number::from_f64(f64::decode(input)?)
+                .map(Value::Number)
+                .ok_or(CacheError::Invalid("number"))?,
+            5 => Value::String(String::decode(input)?),
+            6 => Value::Array(Vec::decode(input)?),
+            _ => {
+                let len = input.len(9)?;
+                let mut entries = serde_json::Map::new();
+
+                for _ in 0..len {
+                    let key = String::decode(input)?;
+                    entries.insert(key, Value::decode(input)?);
+                }
+
+                Value::Object(entries)
+            }
+        };
+
+        input.depth -= 1;
+
+        Ok(value)
+    }
+}
+
+/// The attributes and child elements kept with the `preserve-unknown` feature, encoded as an object and a list of children (name, element and position).
+///
+/// They are written empty without the feature, and skipped when reading, so that the format doesn't depend on it.
+#[cfg(feature = "preserve-unknown")]
+impl Encode for crate::extra::Extra {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.attributes.len().encode(out);
+
+        for (key, value) in &self.attributes {
+            key.encode(out);
+            value.encode(out);
+        }
+
+        self.children.len().encode(out);
+
+        for child in &self.children {
+            child.name.encode(out);
+            child.element.encode(out);
+            child.position.encode(out);
+        }
+    }
+}
+
+#[cfg(feature = "preserve-unknown")]
+impl Decode for crate::extra::Extra {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let len = input.len(9)?;
+        let attributes = (0..len)
+            .map(|_| Ok((String::decode(input)?, Value::decode(input)?)))
+            .collect::<Result<_, _>>()?;
+
+        let len = input.len(17)?;
+        let children = (0..len)
+            .map(|_| {
+                Ok(crate::extra::Child {
+                    name: String::decode(input)?,
+                    element: Value::decode(input)?,
+                    position: usize::decode(input)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(crate::extra::Extra {
+            attributes,
+            children,
+        })
+    }
+}
+
+/// Writes no attributes or children in place of those that would be kept with `preserve-unknown`.
+#[cfg(not(feature = "preserve-unknown"))]
+fn encode_no_extra(out: &mut Vec<u8>) {
+    0usize.encode(out);
+    0usize.encode(out);
+}
+
+/// Skips the attributes and children that would be kept with `preserve-unknown`.
+#[cfg(not(feature = "preserve-unknown"))]
+fn skip_extra(input: &mut Input<'_>) -> Result<(), CacheError> {
+    let len = input.len(9)?;
+
+    for _ in 0..len {
+        String::decode(input)?;
+        Value::decode(input)?;
+    }
+
+    let len = input.len(17)?;
+
+    for _ in 0..len {
+        String::decode(input)?;
+        Value::decode(input)?;
+        usize::decode(input)?;
+    }
+
+    Ok(())
+}
+
+impl Encode for Metadata {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.version.encode(out);
+        self.tiled_version.encode(out);
+    }
+}
+
+impl Decode for Metadata {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        Ok(Metadata {
+            version: String::decode(input)?,
+            tiled_version: String::decode(input)?,
+        })
+    }
+}
+
+impl Encode for Image {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.source.encode(out);
+        self.transparent_color.encode(out);
+        self.width.encode(out);
+        self.height.encode(out);
+    }
+}
+
+impl Decode for Image {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        Ok(Image {
+            source: String::decode(input)?,
+            transparent_color: Option::decode(input)?,
+            width: input.u32()?,
+            height: input.u32()?,
+        })
+    }
+}
+
+impl Encode for Frame {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.tileid.encode(out);
+        self.duration.encode(out);
+    }
+}
+
+impl Decode for Frame {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        Ok(Frame {
+            tileid: input.u32()?,
+            duration: Duration::decode(input)?,
+        })
+    }
+}
+
+impl Encode for crate::tileset::Tile {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.id.encode(out);
+        self.r#type.encode(out);
+        self.animation.encode(out);
+        #[cfg(feature = "preserve-unknown")]
+        self.extra.encode(out);
+        #[cfg(not(feature = "preserve-unknown"))]
+        encode_no_extra(out);
+    }
+}
+
+impl Decode for crate::tileset::Tile {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let tile = crate::tileset::Tile {
+            id: input.u32()?,
+            r#type: String::decode(input)?,
+            animation: Vec::decode(input)?,
+            #[cfg(feature = "preserve-unknown")]
+            extra: Decode::decode(input)?,
+        };
+        #[cfg(not(feature = "preserve-unknown"))]
+        skip_extra(input)?;
+
+        Ok(tile)
+    }
+}
+
+impl Encode for Tileset {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.metadata.encode(out);
+        self.name.encode(out);
+        self.tile_width.encode(out);
+        self.tile_height.encode(out);
+        self.spacing.encode(out);
+        self.margin.encode(out);
+        self.tile_count.encode(out);
+        self.columns.encode(out);
+        self.background_color.encode(out);
+        self.image.encode(out);
+        self.tiles.encode(out);
+        #[cfg(feature = "preserve-unknown")]
+        self.extra.encode(out);
+        #[cfg(not(feature = "preserve-unknown"))]
+        encode_no_extra(out);
+    }
+}
+
+impl Decode for Tileset {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let tileset = Tileset {
+            metadata: Option::decode(input)?,
+            name: String::decode(input)?,
+            tile_width: input.u32()?,
+            tile_height: input.u32()?,
+            spacing: input.u32()?,
+            margin: input.u32()?,
+            tile_count: usize::decode(input)?,
+            columns: input.u32()?,
+            background_color: Option::decode(input)?,
+            image: Image::decode(input)?,
+            tiles: Vec::decode(input)?,
+            #[cfg(feature = "preserve-unknown")]
+            extra: Decode::decode(input)?,
+        };
+        #[cfg(not(feature = "preserve-unknown"))]
+        skip_extra(input)?;
+
+        Ok(tileset)
+    }
+}
+
+/// Tiles are written as plain cells, rather than through `Vec<Tile>`, so that they can be read back in bulk.
+fn encode_tiles(tiles: &[Tile], out: &mut Vec<u8>) {
+    tiles.len().encode(out);
+    out.reserve(tiles.len() * 4);
+
+    for tile in tiles {
+        out.extend_from_slice(&tile.gid.to_le_bytes());
+    }
+}
+
+fn decode_tiles(input: &mut Input<'_>) -> Result<Vec<Tile>, CacheError> {
+    let len = input.len(4)?;
+
+    Ok(input
+        .take(len * 4)?
+        .chunks_exact(4)
+        .map(|cell| Tile {
+            gid: u32::from_le_bytes([cell[0], cell[1], cell[2], cell[3]]),
+        })
+        .collect())
+}
+
+impl Encode for CompactTiles {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.flagged.encode(out);
+
+        match &self.cells {
+            Cells::U8(cells) => {
+                out.push(1);
+                cells.len().encode(out);
+                out.extend_from_slice(cells);
+            }
+            Cells::U16(cells) => {
+                out.push(2);
+                cells.len().encode(out);
+                cells.iter().for_each(|cell| cell.encode(out));
+            }
+            Cells::U32(cells) => {
+                out.push(4);
+                cells.len().encode(out);
+                cells.iter().for_each(|cell| cell.encode(out));
+            }
+        }
+    }
+}
+
+impl Decode for CompactTiles {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let flagged = bool::decode(input)?;
+        let size = match input.u8()? {
+            size @ (1 | 2 | 4) => usize::from(size),
+            _ => return Err(CacheError::Invalid("cell size")),
+        };
+        let len = input.len(size)?;
+        let bytes = input.take(len * size)?;
+
+        let cells = match size {
+            1 => Cells::U8(bytes.to_vec()),
+            2 => Cells::U16(
+                bytes
+                    .chunks_exact(2)
+                    .map(|cell| u16::from_le_bytes([cell[0], cell[1]]))
+                    .collect(),
+            ),
+            _ => Cells::U32(
+                bytes
+                    .chunks_exact(4)
+                    .map(|cell| u32::from_le_bytes([cell[0], cell[1], cell[2], cell[3]]))
+                    .collect(),
+            ),
+        };
+
+        Ok(CompactTiles { cells, flagged })
+    }
+}
+
+impl Encode for Chunk {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.x.encode(out);
+        self.y.encode(out);
+        self.width.encode(out);
+        self.height.encode(out);
+        encode_tiles(&self.data, out);
+    }
+}
+
+impl Decode for Chunk {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        Ok(Chunk {
+            x: input.u32()?,
+            y: input.u32()?,
+            width: input.u32()?,
+            height: input.u32()?,
+            data: decode_tiles(input)?,
+        })
+    }
+}
+
+impl Encode for ParseLimits {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.max_decompressed_bytes.encode(out);
+        self.max_tiles.encode(out);
+        self.max_layers.encode(out);
+        self.max_depth.encode(out);
+    }
+}
+
+impl Decode for ParseLimits {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        Ok(ParseLimits {
+            max_decompressed_bytes: Option::decode(input)?,
+            max_tiles: Option::decode(input)?,
+            max_layers: Option::decode(input)?,
+            max_depth: Option::decode(input)?,
+        })
+    }
+}
+
+impl Encode for LayerData {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            LayerData::Tiles(tiles) => {
+                out.push(0);
+                encode_tiles(tiles, out);
+            }
+            LayerData::Chunks(chunks) => {
+                out.push(1);
+                chunks.encode(out);
+            }
+            LayerData::LazyChunks(chunks) => {
+                out.push(2);
+                chunks.encoding.encode(out);
+                chunks.compression.encode(out);
+                chunks.capacity.encode(out);
+                chunks.limits.encode(out);
+                chunks.chunks.len().encode(out);
+
+                for chunk in &chunks.chunks {
+                    chunk.x.encode(out);
+                    chunk.y.encode(out);
+                    chunk.width.encode(out);
+                    chunk.height.encode(out);
+
+                    match &chunk.payload {
+                        Payload::Encoded(data) => {
+                            out.push(0);
+                            data.encode(out);
+                        }
+                        Payload::Tiles(tiles) => {
+                            out.push(1);
+                            encode_tiles(tiles, out);
+                        }
+                    }
+                }
+            }
+            LayerData::CompactTiles(tiles) => {
+                out.push(3);
+                tiles.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for LayerData {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        match input.tag("layer data", 3)? {
+            0 => decode_tiles(input).map(LayerData::Tiles),
+            1 => Vec::decode(input).map(LayerData::Chunks),
+            #[cfg(feature = "std")]
+            2 => {
+                let encoding = Option::decode(input)?;
+                let compression = Option::decode(input)?;
+                let capacity = usize::decode(input)?;
+                let limits = ParseLimits::decode(input)?;
+                let len = input.len(17)?;
+                let chunks = (0..len)
+                    .map(|_| {
+                        Ok(EncodedChunk {
+                            x: input.u32()?,
+                            y: input.u32()?,
+                            width: input.u32()?,
+                            height: input.u32()?,
+                            payload: match input.tag("chunk payload", 1)? {
+                                0 => Payload::Encoded(String::decode(input)?),
+                                _ => Payload::Tiles(Arc::new(decode_tiles(input)?)),
+                            },
+                        })
+                    })
+                    .collect::<Result<_, CacheError>>()?;
+
+                Ok(LayerData::LazyChunks(LazyChunks::new(
+                    encoding,
+                    compression,
+                    chunks,
+                    capacity,
+                    limits,
+                )))
+            }
+            #[cfg(not(feature = "std"))]
+            2 => Err(CacheError::RequiresStd("lazily decoded chunks")),
+            _ => CompactTiles::decode(input).map(LayerData::CompactTiles),
+        }
+    }
+}
+
+/// The encoding and compression are written as one byte each, 0 for none.
+impl Encode for DataFormat {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(match self.encoding {
+            None => 0,
+            Some(Encoding::Csv) => 1,
+            Some(Encoding::Base64) => 2,
+        });
+        out.push(match self.compression {
+            None => 0,
+            Some(Compression::Gzip) => 1,
+            Some(Compression::Zlib) => 2,
+            Some(Compression::Zstd) => 3,
+        });
+    }
+}
+
+impl Decode for DataFormat {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let encoding = match input.tag("encoding", 2)? {
+            0 => None,
+            1 => Some(Encoding::Csv),
+            _ => Some(Encoding::Base64),
+        };
+        let compression = match input.tag("compression", 3)? {
+            0 => None,
+            1 => Some(Compression::Gzip),
+            2 => Some(Compression::Zlib),
+            _ => Some(Compression::Zstd),
+        };
+
+        Ok(DataFormat {
+            encoding,
+            compression,
+        })
+    }
+}
+
+impl Encode for Layer {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.id.encode(out);
+        self.name.encode(out);
+        self.x.encode(out);
+        self.y.encode(out);
+        self.width.encode(out);
+        self.height.encode(out);
+        self.visible.encode(out);
+        self.locked.encode(out);
+        self.opacity.encode(out);
+        self.offset_x.encode(out);
+        self.offset_y.encode(out);
+        self.data.encode(out);
+        self.format.encode(out);
+        #[cfg(feature = "preserve-unknown")]
+        self.extra.encode(out);
+        #[cfg(not(feature = "preserve-unknown"))]
+        encode_no_extra(out);
+    }
+}
+
+impl Decode for Layer {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let layer = Layer {
+            id: input.u32()?,
+            name: String::decode(input)?,
+            x: i32::decode(input)?,
+            y: i32::decode(input)?,
+            width: i32::decode(input)?,
+            height: i32::decode(input)?,
+            visible: bool::decode(input)?,
+            locked: bool::decode(input)?,
+            opacity: f64::decode(input)?,
+            offset_x: f64::decode(input)?,
+            offset_y: f64::decode(input)?,
+            data: LayerData::decode(input)?,
+            format: DataFormat::decode(input)?,
+            #[cfg(feature = "preserve-unknown")]
+            extra: Decode::decode(input)?,
+        };
+        #[cfg(not(feature = "preserve-unknown"))]
+        skip_extra(input)?;
+
+        Ok(layer)
+    }
+}
+
+impl Encode for Orientation {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let stagger = |axis: &StaggerAxis, index: &StaggerIndex, out: &mut Vec<u8>| {
+            out.push(*axis as u8);
+            out.push(*index as u8);
+        };
+
+        match self {
+            Orientation::Orthogonal => out.push(0),
+            Orientation::Isometric => out.push(1),
+            Orientation::Staggered {
+                stagger_axis,
+                stagger_index,
+            } => {
+                out.push(2);
+                stagger(stagger_axis, stagger_index, out);
+            }
+            Orientation::Hexagonal {
+                hexside_length,
+                stagger_axis,
+                stagger_index,
+            } => {
+                out.push(3);
+                hexside_length.encode(out);
+                stagger(stagger_axis, stagger_index, out);
+            }
+        }
+    }
+}
+
+impl Decode for Orientation {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        fn stagger(input: &mut Input<'_>) -> Result<(StaggerAxis, StaggerIndex), CacheError> {
+            let axis = match input.tag("stagger axis", 1)? {
+                0 => StaggerAxis::X,
+                _ => StaggerAxis::Y,
+            };
+            let index = match input.tag("stagger index", 1)? {
+                0 => StaggerIndex::Odd,
+                _ => StaggerIndex::Even,
+            };
+
+            Ok((axis, index))
+        }
+
+        match input.tag("orientation", 3)? {
+            0 => Ok(Orientation::Orthogonal),
+            1 => Ok(Orientation::Isometric),
+            2 => {
+                let (stagger_axis, stagger_index) = stagger(input)?;
+
+                Ok(Orientation::Staggered {
+                    stagger_axis,
+                    stagger_index,
+                })
+            }
+            _ => {
+                let hexside_length = i32::decode(input)?;
+                let (stagger_axis, stagger_index) = stagger(input)?;
+
+                Ok(Orientation::Hexagonal {
+                    hexside_length,
+                    stagger_axis,
+                    stagger_index,
+                })
+            }
+        }
+    }
+}
+
+impl Encode for RenderOrder {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Decode for RenderOrder {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        match input.tag("render order", 3)? {
+            0 => Ok(RenderOrder::RightDown),
+            1 => Ok(RenderOrder::RightUp),
+            2 => Ok(RenderOrder::LeftDown),
+            _ => Ok(RenderOrder::LeftUp),
+        }
+    }
+}
+
+/// External tilesets are written along with their contents if they have been loaded, so that they don't need to be loaded again.
+impl Encode for map::Tileset {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.first_gid.encode(out);
+
+        match &self.kind {
+            TilesetKind::Embedded(tileset) => {
+                out.push(0);
+                tileset.encode(out);
+            }
+            TilesetKind::External { source, tileset } => {
+                out.push(1);
+                source.encode(out);
+                tileset.as_deref().encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for map::Tileset {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let first_gid = input.u32()?;
+        let kind = match input.tag("tileset kind", 1)? {
+            0 => TilesetKind::Embedded(Tileset::decode(input)?),
+            _ => TilesetKind::External {
+                source: String::decode(input)?,
+                tileset: Option::<Tileset>::decode(input)?.map(Arc::new),
+            },
+        };
+
+        Ok(map::Tileset { first_gid, kind })
+    }
+}
+
+impl Encode for Map {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.metadata.encode(out);
+        self.orientation.encode(out);
+        self.render_order.encode(out);
+        self.compression_level.encode(out);
+        self.width.encode(out);
+        self.height.encode(out);
+        self.tile_width.encode(out);
+        self.tile_height.encode(out);
+        self.infinite.encode(out);
+        self.background_color.encode(out);
+        self.next_layer_id.encode(out);
+        self.next_object_id.encode(out);
+        self.layers.encode(out);
+        self.tilesets.encode(out);
+        #[cfg(feature = "preserve-unknown")]
+        self.extra.encode(out);
+        #[cfg(not(feature = "preserve-unknown"))]
+        encode_no_extra(out);
+    }
+}
+
+impl Decode for Map {
+    fn decode(input: &mut Input<'_>) -> Result<Self, CacheError> {
+        let map = Map {
+            metadata: Metadata::decode(input)?,
+            orientation: Orientation::decode(input)?,
+            render_order: RenderOrder::decode(input)?,
+            compression_level: i32::decode(input)?,
+            width: i32::decode(input)?,
+            height: i32::decode(input)?,
+            tile_width: i32::decode(input)?,
+            tile_height: i32::decode(input)?,
+            infinite: bool::decode(input)?,
+            background_color: Option::decode(input)?,
+            next_layer_id: input.u32()?,
+            next_object_id: input.u32()?,
+            layers: Vec::decode(input)?,
+            tilesets: Vec::decode(input)?,
+            #[cfg(feature = "preserve-unknown")]
+            extra: Decode::decode(input)?,
+        };
+        #[cfg(not(feature = "preserve-unknown"))]
+        skip_extra(input)?;
+
+        Ok(map)
+    }
+}
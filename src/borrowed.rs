@@ -0,0 +1,506 @@
+//! A read-only variant of the data model whose strings borrow from the input.
+//!
+//! Names, paths and other strings are `Cow::Borrowed` slices of the document wherever they appear in it verbatim, and only owned when they had to be unescaped (e.g. `&amp;` in XML or `\n` in JSON). Tile data is decoded as usual and always owned, as is everything when parsing leniently.
+//!
+//! Attributes and child elements that are not modelled are ignored, even with the `preserve-unknown` feature.
+
+use crate::{
+    context,
+    de::{deserialize_bool_from_anything, deserialize_number_from_string, Intercept},
+    error::Error,
+    layer::LayerData,
+    map::{self, Orientation, RenderOrder, TileLayer},
+    options,
+    tileset::{self, Frame},
+};
+
+use alloc::{borrow::Cow, string::ToString, vec::Vec};
+use core::{fmt, marker::PhantomData};
+use serde::{
+    de::{Deserializer, Error as _, MapAccess, Visitor},
+    Deserialize,
+};
+
+/// Deserializes an optional string, borrowing it from the input if possible.
+fn deserialize_optional<'de: 'a, 'a, D>(deserializer: D) -> Result<Option<Cow<'a, str>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(transparent)]
+    struct Str<'a>(#[serde(borrow)] Cow<'a, str>);
+
+    Ok(Option::<Str>::deserialize(deserializer)?.map(|s| s.0))
+}
+
+fn deserialize_version<'de: 'a, 'a, D>(deserializer: D) -> Result<Cow<'a, str>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrFloat<'a> {
+        String(#[serde(borrow)] Cow<'a, str>),
+        Float(f64),
+    }
+
+    match StringOrFloat::deserialize(deserializer)? {
+        StringOrFloat::String(s) => Ok(s),
+        StringOrFloat::Float(f) => Ok(Cow::Owned(f.to_string())),
+    }
+}
+
+/// See `metadata::Metadata`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+pub struct Metadata<'a> {
+    #[serde(borrow, deserialize_with = "deserialize_version")]
+    pub version: Cow<'a, str>,
+    #[serde(borrow, rename = "tiledversion")]
+    pub tiled_version: Cow<'a, str>,
+}
+
+/// See `tileset::Image`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Image<'a> {
+    /// The reference to the tileset image file.
+    pub source: Cow<'a, str>,
+    /// Defines a specific color that is treated as transparent.
+    pub transparent_color: Option<Cow<'a, str>>,
+    /// The image width in pixels.
+    pub width: u32,
+    /// The image height in pixels.
+    pub height: u32,
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Image<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct XMLImage<'a> {
+            #[serde(borrow)]
+            source: Cow<'a, str>,
+            #[serde(borrow, default, deserialize_with = "deserialize_optional")]
+            trans: Option<Cow<'a, str>>,
+            #[serde(deserialize_with = "deserialize_number_from_string")]
+            width: u32,
+            #[serde(deserialize_with = "deserialize_number_from_string")]
+            height: u32,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum ImageData<'a> {
+            Xml {
+                #[serde(borrow)]
+                image: Vec<XMLImage<'a>>,
+            },
+            Json {
+                #[serde(borrow)]
+                image: Cow<'a, str>,
+                imageheight: u32,
+                imagewidth: u32,
+                #[serde(borrow, default, deserialize_with = "deserialize_optional")]
+                transparentcolor: Option<Cow<'a, str>>,
+            },
+        }
+
+        match ImageData::deserialize(deserializer)? {
+            ImageData::Xml { image } => {
+                let image = image
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| serde::de::Error::custom("missing image element"))?;
+
+                Ok(Image {
+                    source: image.source,
+                    transparent_color: image.trans,
+                    width: image.width,
+                    height: image.height,
+                })
+            }
+            ImageData::Json {
+                image,
+                imageheight,
+                imagewidth,
+                transparentcolor,
+            } => Ok(Image {
+                source: image,
+                transparent_color: transparentcolor,
+                width: imagewidth,
+                height: imageheight,
+            }),
+        }
+    }
+}
+
+/// See `tileset::Tile`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+pub struct Tile<'a> {
+    /// The local tile ID within its tileset.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub id: u32,
+    /// The type of the tile.
+    #[serde(borrow, default)]
+    pub r#type: Cow<'a, str>,
+    /// Contains a list of animation frames.
+    #[serde(deserialize_with = "tileset::deserialize_animation", default)]
+    pub animation: Vec<Frame>,
+}
+
+/// See `tileset::Tileset`.
+/// The fields of a `Tileset` other than its tiles, which `Tileset` reads itself.
+#[derive(Deserialize)]
+struct TilesetFields<'a> {
+    #[serde(borrow, flatten)]
+    metadata: Option<Metadata<'a>>,
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(
+        deserialize_with = "deserialize_number_from_string",
+        rename = "tilewidth"
+    )]
+    tile_width: u32,
+    #[serde(
+        deserialize_with = "deserialize_number_from_string",
+        rename = "tileheight"
+    )]
+    tile_height: u32,
+    #[serde(default, deserialize_with = "deserialize_number_from_string")]
+    spacing: u32,
+    #[serde(default, deserialize_with = "deserialize_number_from_string")]
+    margin: u32,
+    #[serde(
+        deserialize_with = "deserialize_number_from_string",
+        rename = "tilecount"
+    )]
+    tile_count: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    columns: u32,
+    #[serde(
+        borrow,
+        default,
+        rename = "backgroundcolor",
+        deserialize_with = "deserialize_optional"
+    )]
+    background_color: Option<Cow<'a, str>>,
+    #[serde(borrow, flatten)]
+    image: Image<'a>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Tileset<'a> {
+    pub metadata: Option<Metadata<'a>>,
+    /// The name of this tileset.
+    pub name: Cow<'a, str>,
+    /// The (maximum) width of the tiles in this tileset.
+    pub tile_width: u32,
+    /// The (maximum) height of the tiles in this tileset.
+    pub tile_height: u32,
+    /// The spacing in pixels between the tiles in this tileset.
+    pub spacing: u32,
+    /// The margin around the tiles in this tileset.
+    pub margin: u32,
+    /// The number of tiles in this tileset.
+    pub tile_count: usize,
+    /// The number of tile columns in the tileset.
+    pub columns: u32,
+    pub background_color: Option<Cow<'a, str>>,
+    pub image: Image<'a>,
+    pub tiles: Vec<Tile<'a>>,
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Tileset<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TilesetVisitor<'a>(PhantomData<Tileset<'a>>);
+
+        impl<'de: 'a, 'a> Visitor<'de> for TilesetVisitor<'a> {
+            type Value = Tileset<'a>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a tileset")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Tileset<'a>, A::Error> {
+                let mut tiles = Vec::new();
+
+                let fields =
+                    TilesetFields::deserialize(Intercept::new(map, |key, map| match key {
+                        "tiles" | "tile" => map
+                            .next_value_seed(context::Append {
+                                name: None,
+                                elements: &mut tiles,
+                            })
+                            .map(|()| true),
+                        _ => Ok(false),
+                    }))?;
+
+                Ok(Tileset {
+                    metadata: fields.metadata,
+                    name: fields.name,
+                    tile_width: fields.tile_width,
+                    tile_height: fields.tile_height,
+                    spacing: fields.spacing,
+                    margin: fields.margin,
+                    tile_count: fields.tile_count,
+                    columns: fields.columns,
+                    background_color: fields.background_color,
+                    image: fields.image,
+                    tiles,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(TilesetVisitor(PhantomData))
+    }
+}
+
+impl<'a> Tileset<'a> {
+    /// Parses a JSON tileset, borrowing its strings from `s`.
+    pub fn from_json(s: &'a str) -> Result<Tileset<'a>, Error> {
+        context::parse("tileset", Some(s), || {
+            options::current().limits.check_json_depth(s)?;
+            serde_json::from_str(s).map_err(From::from)
+        })
+    }
+
+    /// Parses a TSX tileset, borrowing its strings from `s`.
+    #[cfg(feature = "xml")]
+    pub fn from_xml(s: &'a str) -> Result<Tileset<'a>, Error> {
+        #[derive(Deserialize)]
+        struct Doc<'a> {
+            #[serde(borrow)]
+            tileset: Option<Tileset<'a>>,
+        }
+
+        context::parse("tileset", Some(s), || {
+            let doc: Doc = crate::xml::from_str(s).map_err(Error::Conversion)?;
+            doc.tileset.ok_or(Error::MissingElement("tileset"))
+        })
+    }
+}
+
+/// See `map::Tileset`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+pub struct MapTileset<'a> {
+    /// The first global tile ID of this tileset.
+    #[serde(
+        deserialize_with = "deserialize_number_from_string",
+        rename = "firstgid"
+    )]
+    pub first_gid: u32,
+    #[serde(borrow, flatten)]
+    pub kind: TilesetKind<'a>,
+}
+
+/// See `map::TilesetKind`. External tilesets are only referenced, loading them is left to the caller.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
+pub enum TilesetKind<'a> {
+    Embedded(#[serde(borrow)] Tileset<'a>),
+    External {
+        /// The file the tileset is stored in.
+        #[serde(borrow)]
+        source: Cow<'a, str>,
+    },
+}
+
+/// See `layer::Layer`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Layer<'a> {
+    /// Unique ID of the layer.
+    #[serde(deserialize_with = "crate::layer::deserialize_id")]
+    pub id: u32,
+    /// The name of the layer.
+    #[serde(borrow, default)]
+    pub name: Cow<'a, str>,
+    /// The x coordinate of the layer in tiles.
+    #[serde(default)]
+    pub x: i32,
+    /// The y coordinate of the layer in tiles.
+    #[serde(default)]
+    pub y: i32,
+    /// The width of the layer in tiles.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub width: i32,
+    /// The height of the layer in tiles.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub height: i32,
+    #[serde(default = "crate::layer::default_visible")]
+    pub visible: bool,
+    #[serde(default)]
+    pub locked: bool,
+    /// The opacity of the layer as a value from 0 to 1.
+    #[serde(default = "crate::layer::default_opacity")]
+    pub opacity: f64,
+    /// Rendering offset for this layer in pixels.
+    #[serde(default, rename = "offsetx")]
+    pub offset_x: f64,
+    /// Rendering offset for this layer in pixels.
+    #[serde(default, rename = "offsety")]
+    pub offset_y: f64,
+    /// The tile data, which is always owned.
+    #[serde(flatten)]
+    pub data: LayerData,
+}
+
+impl TileLayer for Layer<'_> {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    fn data_mut(&mut self) -> &mut LayerData {
+        &mut self.data
+    }
+}
+
+/// The fields of a `Map` other than its layers and tilesets, which `Map` reads itself.
+#[derive(Deserialize)]
+struct MapFields<'a> {
+    #[serde(borrow, flatten)]
+    metadata: Metadata<'a>,
+    #[serde(flatten, deserialize_with = "map::deserialize_orientation")]
+    orientation: Orientation,
+    #[serde(
+        rename = "renderorder",
+        deserialize_with = "map::deserialize_render_order"
+    )]
+    render_order: RenderOrder,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    width: i32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    height: i32,
+    #[serde(
+        rename = "tilewidth",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    tile_width: i32,
+    #[serde(
+        rename = "tileheight",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    tile_height: i32,
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    infinite: bool,
+    #[serde(
+        borrow,
+        default,
+        rename = "backgroundcolor",
+        deserialize_with = "deserialize_optional"
+    )]
+    background_color: Option<Cow<'a, str>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Map<'a> {
+    pub metadata: Metadata<'a>,
+    /// Map orientation.
+    pub orientation: Orientation,
+    /// The order in which tiles on tile layers are rendered.
+    pub render_order: RenderOrder,
+    /// The map width in tiles.
+    pub width: i32,
+    /// The map height in tiles.
+    pub height: i32,
+    /// The width of a tile.
+    pub tile_width: i32,
+    /// The height of a tile.
+    pub tile_height: i32,
+    pub infinite: bool,
+    /// The background color of the map.
+    pub background_color: Option<Cow<'a, str>>,
+    pub layers: Vec<Layer<'a>>,
+    pub tilesets: Vec<MapTileset<'a>>,
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Map<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MapVisitor<'a>(PhantomData<Map<'a>>);
+
+        impl<'de: 'a, 'a> Visitor<'de> for MapVisitor<'a> {
+            type Value = Map<'a>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Map<'a>, A::Error> {
+                let mut layers = map::Layers::new();
+                let mut tilesets = Vec::new();
+                let (mut has_layers, mut has_tilesets) = (false, false);
+
+                let fields = MapFields::deserialize(Intercept::new(map, |key, map| match key {
+                    "layers" | "layer" => {
+                        has_layers = true;
+                        map.next_value_seed(&mut layers).map(|()| true)
+                    }
+                    "tilesets" | "tileset" => {
+                        has_tilesets = true;
+                        map.next_value_seed(context::Append {
+                            name: Some("tileset"),
+                            elements: &mut tilesets,
+                        })
+                        .map(|()| true)
+                    }
+                    _ => Ok(false),
+                }))
+                .map_err(|e| layers.fail(e))?;
+
+                if !has_layers {
+                    return Err(layers.fail(A::Error::missing_field("layers")));
+                }
+
+                if !has_tilesets {
+                    return Err(layers.fail(A::Error::missing_field("tilesets")));
+                }
+
+                Ok(Map {
+                    metadata: fields.metadata,
+                    orientation: fields.orientation,
+                    render_order: fields.render_order,
+                    width: fields.width,
+                    height: fields.height,
+                    tile_width: fields.tile_width,
+                    tile_height: fields.tile_height,
+                    infinite: fields.infinite,
+                    background_color: fields.background_color,
+                    layers: layers.finish()?,
+                    tilesets,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+}
+
+impl<'a> Map<'a> {
+    /// Parses a JSON map, borrowing its strings from `s`.
+    pub fn from_json(s: &'a str) -> Result<Map<'a>, Error> {
+        context::parse_parallel("map", Some(s), || {
+            options::current().limits.check_json_depth(s)?;
+            serde_json::from_str(s).map_err(From::from)
+        })
+    }
+
+    /// Parses a TMX map, borrowing its strings from `s`.
+    #[cfg(feature = "xml")]
+    pub fn from_xml(s: &'a str) -> Result<Map<'a>, Error> {
+        #[derive(Deserialize)]
+        struct Doc<'a> {
+            #[serde(borrow)]
+            map: Option<Map<'a>>,
+        }
+
+        context::parse_parallel("map", Some(s), || {
+            let doc: Doc = crate::xml::from_str(s).map_err(Error::Conversion)?;
+            doc.map.ok_or(Error::MissingElement("map"))
+        })
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{error::Error, loader::ResourceLoader, tileset::Tileset};
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// A cache of external tilesets shared between maps, keyed by the canonical path of the tileset file.
+///
+/// Pass the same cache to `Map::load_with_cache` for every map so that each external tileset is only parsed once and all maps reference the same `Arc<Tileset>`.
+#[derive(Debug, Default)]
+pub struct TilesetCache {
+    tilesets: Mutex<HashMap<PathBuf, Arc<Tileset>>>,
+}
+
+impl TilesetCache {
+    pub fn new() -> TilesetCache {
+        TilesetCache::default()
+    }
+
+    fn tilesets(&self) -> MutexGuard<'_, HashMap<PathBuf, Arc<Tileset>>> {
+        // The map is never left in an inconsistent state, so a panic while holding the lock can be ignored.
+        self.tilesets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns the cached tileset with the given canonical path.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<Arc<Tileset>> {
+        self.tilesets().get(path.as_ref()).cloned()
+    }
+
+    /// Adds a tileset under the given canonical path, returning the tileset that ends up cached.
+    ///
+    /// If another tileset was cached under the same path in the meantime, that one is kept so that all maps keep sharing a single instance.
+    pub fn insert(&self, path: PathBuf, tileset: Arc<Tileset>) -> Arc<Tileset> {
+        self.tilesets().entry(path).or_insert(tileset).clone()
+    }
+
+    /// Returns the tileset at `path` (as resolved by `loader`), loading and caching it if needed.
+    pub fn get_or_load<L: ResourceLoader + ?Sized>(
+        &self,
+        loader: &L,
+        path: &Path,
+    ) -> Result<Arc<Tileset>, Error> {
+        let key = loader.canonicalize(path)?;
+
+        if let Some(tileset) = self.get(&key) {
+            return Ok(tileset);
+        }
+
+        // The lock isn't held while loading, two maps loading the same tileset at once may both parse it.
+        let buf = loader.load(path)?;
+        let tileset = Tileset::from_source_data(path, &buf)?;
+
+        Ok(self.insert(key, Arc::new(tileset)))
+    }
+
+    /// Removes the tileset cached under the canonical path `key` from the cache, so that it is loaded again the next time a map references it (e.g. after the file has changed).
+    ///
+    /// `key` is the path as canonicalized by the loader the tileset was loaded with (see `ResourceLoader::canonicalize`). Returns whether a tileset was removed. Maps that have already been loaded keep the old tileset.
+    pub fn invalidate<P: AsRef<Path>>(&self, key: P) -> bool {
+        self.tilesets().remove(key.as_ref()).is_some()
+    }
+
+    /// Removes all tilesets from the cache.
+    pub fn clear(&self) {
+        self.tilesets().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.tilesets().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tilesets().is_empty()
+    }
+}
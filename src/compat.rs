@@ -0,0 +1,277 @@
+//! Which features of the TMX format a map or tileset uses, and the version of Tiled each of them needs.
+//!
+//! Parallax factors and classes are only kept with the `preserve-unknown` feature, so they are only reported with it.
+
+use crate::{
+    error::Error,
+    layer::{Compression, LayerData, Tile},
+    map::{Map, Orientation},
+    metadata::Version,
+    tileset::Tileset,
+};
+
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "preserve-unknown")]
+use serde_json::Value;
+
+/// The flag marking tiles on hexagonal maps that are rotated by 120 degrees.
+const ROTATED_HEXAGONAL_120_FLAG: u32 = 0x1000_0000;
+
+/// A feature of the TMX format that older versions of Tiled don't support, ordered by the version that added it.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Feature {
+    /// The `staggered` orientation.
+    StaggeredMaps,
+    /// Tiles with an `<animation>`.
+    TileAnimations,
+    /// The `hexagonal` orientation.
+    HexagonalMaps,
+    /// Layers with an `offsetx` or `offsety`.
+    LayerOffsets,
+    /// Tiles on hexagonal maps rotated by 120 degrees.
+    HexagonalRotation,
+    /// Maps made of chunks rather than a fixed number of tiles.
+    InfiniteMaps,
+    /// A `compressionlevel` other than the default.
+    CompressionLevel,
+    /// Tile data compressed with Zstandard.
+    ZstdCompression,
+    /// Layers with a `parallaxx` or `parallaxy` factor.
+    Parallax,
+    /// Properties whose value is a custom class.
+    ClassProperties,
+    /// Maps with a `parallaxoriginx` or `parallaxoriginy`.
+    ParallaxOrigin,
+    /// Maps, layers, tilesets and tiles with a `class`.
+    ClassAttribute,
+}
+
+impl Feature {
+    /// The first version of Tiled that supports the feature.
+    pub fn tiled_version(self) -> Version {
+        match self {
+            Feature::StaggeredMaps => Version::new(0, 9, 0),
+            Feature::TileAnimations => Version::new(0, 10, 0),
+            Feature::HexagonalMaps => Version::new(0, 11, 0),
+            Feature::LayerOffsets => Version::new(0, 14, 0),
+            Feature::HexagonalRotation => Version::new(0, 15, 0),
+            Feature::InfiniteMaps => Version::new(1, 1, 0),
+            Feature::CompressionLevel | Feature::ZstdCompression => Version::new(1, 3, 0),
+            Feature::Parallax => Version::new(1, 5, 0),
+            Feature::ClassProperties | Feature::ParallaxOrigin => Version::new(1, 8, 0),
+            Feature::ClassAttribute => Version::new(1, 9, 0),
+        }
+    }
+
+    /// The first TMX format version that supports the feature.
+    pub fn format_version(self) -> Version {
+        self.tiled_version().format_version()
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Feature::StaggeredMaps => "staggered maps",
+            Feature::TileAnimations => "tile animations",
+            Feature::HexagonalMaps => "hexagonal maps",
+            Feature::LayerOffsets => "layer offsets",
+            Feature::HexagonalRotation => "120° rotation of hexagonal tiles",
+            Feature::InfiniteMaps => "infinite maps",
+            Feature::CompressionLevel => "compression levels",
+            Feature::ZstdCompression => "Zstandard compression",
+            Feature::Parallax => "parallax scrolling",
+            Feature::ClassProperties => "class properties",
+            Feature::ParallaxOrigin => "parallax origins",
+            Feature::ClassAttribute => "classes",
+        })
+    }
+}
+
+/// The features a map or tileset uses, see `Map::compatibility` and `Tileset::compatibility`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Compatibility {
+    /// The features used, sorted and without duplicates.
+    pub features: Vec<Feature>,
+}
+
+impl Compatibility {
+    fn new(mut features: Vec<Feature>) -> Compatibility {
+        features.sort();
+        features.dedup();
+
+        Compatibility { features }
+    }
+
+    /// The first version of Tiled that supports all the features used, if any are.
+    pub fn tiled_version(&self) -> Option<Version> {
+        self.features
+            .iter()
+            .map(|feature| feature.tiled_version())
+            .max()
+    }
+
+    /// The first TMX format version that supports all the features used, if any are.
+    pub fn format_version(&self) -> Option<Version> {
+        self.tiled_version().map(Version::format_version)
+    }
+
+    /// The features used that `tiled_version` doesn't support.
+    pub fn unsupported(&self, tiled_version: Version) -> impl Iterator<Item = Feature> + '_ {
+        self.features
+            .iter()
+            .copied()
+            .filter(move |feature| feature.tiled_version() > tiled_version)
+    }
+
+    /// Checks that `tiled_version` supports all the features used, failing with the first one it doesn't.
+    pub fn check(&self, tiled_version: Version) -> Result<(), Error> {
+        match self.unsupported(tiled_version).next() {
+            Some(feature) => Err(Error::UnsupportedFeature {
+                feature,
+                required: feature.tiled_version(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Map {
+    /// Lists the features the map uses, along with those of its embedded and loaded external tilesets.
+    ///
+    /// On hexagonal maps the tiles are checked for 120 degree rotation, which decodes all lazily decoded chunks.
+    pub fn compatibility(&self) -> Compatibility {
+        let mut features = Vec::new();
+
+        match self.orientation {
+            Orientation::Staggered { .. } => features.push(Feature::StaggeredMaps),
+            Orientation::Hexagonal { .. } => features.push(Feature::HexagonalMaps),
+            _ => {}
+        }
+
+        if self.infinite {
+            features.push(Feature::InfiniteMaps);
+        }
+
+        if self.compression_level != -1 {
+            features.push(Feature::CompressionLevel);
+        }
+
+        #[cfg(feature = "preserve-unknown")]
+        {
+            if has_any(&self.extra, &["parallaxoriginx", "parallaxoriginy"]) {
+                features.push(Feature::ParallaxOrigin);
+            }
+
+            extra_features(&self.extra, &mut features);
+        }
+
+        for layer in &self.layers {
+            if layer.offset_x != 0.0 || layer.offset_y != 0.0 {
+                features.push(Feature::LayerOffsets);
+            }
+
+            if layer.format.compression == Some(Compression::Zstd) {
+                features.push(Feature::ZstdCompression);
+            }
+
+            if let Orientation::Hexagonal { .. } = self.orientation {
+                if rotated_hexagonal(&layer.data) {
+                    features.push(Feature::HexagonalRotation);
+                }
+            }
+
+            #[cfg(feature = "preserve-unknown")]
+            {
+                if has_any(&layer.extra, &["parallaxx", "parallaxy"]) {
+                    features.push(Feature::Parallax);
+                }
+
+                extra_features(&layer.extra, &mut features);
+            }
+        }
+
+        for tileset in self.tilesets.iter().filter_map(|tileset| tileset.tileset()) {
+            features.extend(tileset.compatibility().features);
+        }
+
+        Compatibility::new(features)
+    }
+}
+
+impl Tileset {
+    /// Lists the features the tileset uses.
+    pub fn compatibility(&self) -> Compatibility {
+        let mut features = Vec::new();
+
+        #[cfg(feature = "preserve-unknown")]
+        extra_features(&self.extra, &mut features);
+
+        for tile in &self.tiles {
+            if !tile.animation.is_empty() {
+                features.push(Feature::TileAnimations);
+            }
+
+            #[cfg(feature = "preserve-unknown")]
+            extra_features(&tile.extra, &mut features);
+        }
+
+        Compatibility::new(features)
+    }
+}
+
+fn rotated_hexagonal(data: &LayerData) -> bool {
+    let rotated = |tile: Tile| tile.gid & ROTATED_HEXAGONAL_120_FLAG != 0;
+
+    match data {
+        LayerData::Tiles(tiles) => tiles.iter().copied().any(rotated),
+        LayerData::CompactTiles(tiles) => tiles.iter().any(rotated),
+        LayerData::Chunks(chunks) => chunks
+            .iter()
+            .any(|chunk| chunk.data.iter().copied().any(rotated)),
+        // Chunks that fail to decode are reported when they are used.
+        LayerData::LazyChunks(chunks) => match chunks.to_chunks() {
+            Ok(chunks) => chunks
+                .iter()
+                .any(|chunk| chunk.data.iter().copied().any(rotated)),
+            Err(_) => false,
+        },
+    }
+}
+
+#[cfg(feature = "preserve-unknown")]
+fn has_any(extra: &crate::extra::Extra, keys: &[&str]) -> bool {
+    keys.iter().any(|key| extra.get(key).is_some())
+}
+
+/// Adds the features found in attributes and child elements that are not otherwise modelled.
+#[cfg(feature = "preserve-unknown")]
+fn extra_features(extra: &crate::extra::Extra, features: &mut Vec<Feature>) {
+    if extra.get("class").is_some() {
+        features.push(Feature::ClassAttribute);
+    }
+
+    // Properties are a child element in XML and an array of properties in JSON and Lua.
+    if extra
+        .get("properties")
+        .into_iter()
+        .chain(extra.elements("properties"))
+        .any(has_class_property)
+    {
+        features.push(Feature::ClassProperties);
+    }
+}
+
+/// Whether the properties, as kept in `Extra`, include one whose type is `class` (at any depth, as class members are nested properties).
+#[cfg(feature = "preserve-unknown")]
+fn has_class_property(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => {
+            object.get("type").and_then(Value::as_str) == Some("class")
+                || object.values().any(has_class_property)
+        }
+        Value::Array(values) => values.iter().any(has_class_property),
+        _ => false,
+    }
+}
@@ -0,0 +1,401 @@
+use crate::error::{Error, Location};
+
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as HashMap;
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+#[cfg(feature = "std")]
+use std::{cell::RefCell, collections::HashMap};
+
+/// An element on the path to the part of the document being parsed, e.g. `layer[id=3]`.
+#[derive(Clone, Debug)]
+struct Segment {
+    name: &'static str,
+    index: Option<usize>,
+    id: Option<u32>,
+}
+
+/// What is known about the document currently being parsed on this thread.
+#[derive(Debug, Default)]
+struct State {
+    /// The number of nested `parse` calls.
+    depth: usize,
+    path: Vec<Segment>,
+    /// The first error recorded with `fail`, which is more specific than the message serde is left with.
+    failure: Option<Error>,
+    /// The byte offsets of XML elements, keyed by the path of `name[index]` segments leading to them.
+    offsets: HashMap<String, usize>,
+    /// The byte offset of an XML syntax error.
+    error_offset: Option<usize>,
+    /// The width and height of the map, as they are read.
+    map_size: (Option<i32>, Option<i32>),
+    /// Whether the document is parsed with `parse_parallel`, so that decoding tile data can be deferred.
+    #[cfg(feature = "rayon")]
+    parallel: bool,
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+#[cfg(feature = "std")]
+fn with_state<T, F: FnOnce(&mut State) -> T>(f: F) -> T {
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+/// Without `std` there are no thread locals to keep the state in, so every call starts afresh: errors are reported without the path to where they occurred, and errors recorded with `fail` only by their message.
+#[cfg(not(feature = "std"))]
+fn with_state<T, F: FnOnce(&mut State) -> T>(f: F) -> T {
+    f(&mut State::default())
+}
+
+/// Leaves the document when parsing finishes, even by unwinding.
+struct Leave;
+
+impl Drop for Leave {
+    fn drop(&mut self) {
+        with_state(|state| {
+            // Saturating, as the state doesn't persist between calls without `std`.
+            state.depth = state.depth.saturating_sub(1);
+
+            if state.depth == 0 {
+                *state = State::default();
+            }
+        });
+    }
+}
+
+/// Parses a document whose root element is `root`, adding the location of the failure to any error.
+///
+/// `text` is the source of the document, if available, and is used to turn byte offsets into lines and columns. Nested calls (e.g. a map parsed through `Map::from_data`) are only tracked by the outermost one.
+pub(crate) fn parse<T, F>(root: &'static str, text: Option<&str>, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    let outermost = with_state(|state| {
+        state.depth += 1;
+
+        if state.depth == 1 {
+            state.path.push(Segment {
+                name: root,
+                index: None,
+                id: None,
+            });
+        }
+
+        state.depth == 1
+    });
+    let _leave = Leave;
+
+    let error = match f() {
+        Ok(value) => return Ok(value),
+        Err(error) if !outermost => return Err(error),
+        Err(error) => error,
+    };
+
+    let (failure, location) = with_state(|state| {
+        let location = state.location(&error, text);
+        (state.failure.take(), location)
+    });
+
+    // Errors that were only reported to serde as a message are replaced with the original.
+    let error = match (error, failure) {
+        (Error::Deserialization(_), Some(failure)) => failure,
+        #[cfg(feature = "xml")]
+        (Error::Conversion(_), Some(failure)) => failure,
+        #[cfg(feature = "lua")]
+        (Error::LuaConversion(_), Some(failure)) => failure,
+        (error, _) => error,
+    };
+
+    match location {
+        Some(location) => Err(Error::At {
+            location,
+            source: Box::new(error),
+        }),
+        None => Err(error),
+    }
+}
+
+/// Parses a document like `parse`, allowing the tile data of its layers to be decoded in parallel once they have all been read (see `defer`).
+///
+/// Errors are the same as without the `rayon` feature: decoding errors are reported where the tile data was read (see `fail_at`), and before any error found after it.
+#[cfg(feature = "rayon")]
+pub(crate) fn parse_parallel<T, F>(root: &'static str, text: Option<&str>, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    parse(root, text, || {
+        with_state(|state| state.parallel |= state.depth == 1);
+        f()
+    })
+}
+
+#[cfg(not(feature = "rayon"))]
+pub(crate) fn parse_parallel<T, F>(root: &'static str, text: Option<&str>, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    parse(root, text, f)
+}
+
+/// Whether decoding tile data can be deferred until all layers are read.
+#[cfg(feature = "rayon")]
+pub(crate) fn defer() -> bool {
+    with_state(|state| state.parallel)
+}
+
+impl State {
+    fn location(&self, error: &Error, text: Option<&str>) -> Option<Location> {
+        let mut path = String::new();
+
+        // The root element on its own isn't worth reporting.
+        if self.path.len() > 1 {
+            for (i, segment) in self.path.iter().enumerate() {
+                if i > 0 {
+                    path.push('/');
+                }
+
+                path.push_str(segment.name);
+
+                let _ = match (segment.id, segment.index) {
+                    (Some(id), _) => write!(path, "[id={}]", id),
+                    (None, Some(index)) => write!(path, "[{}]", index),
+                    (None, None) => Ok(()),
+                };
+            }
+        }
+
+        let position = match error {
+            // The position of serde_json is where the error was noticed, which for errors recorded with `fail` may be well past their cause (e.g. tile data decoded once all layers are read), so those are located by their path.
+            Error::Deserialization(error) if error.line() > 0 && self.failure.is_none() => {
+                Some((error.line(), error.column()))
+            }
+            _ => {
+                let offset = self.error_offset.or_else(|| self.element_offset());
+                text.zip(offset)
+                    .map(|(text, offset)| line_column(text, offset))
+            }
+        };
+
+        if path.is_empty() && position.is_none() {
+            return None;
+        }
+
+        Some(Location {
+            path,
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+        })
+    }
+
+    /// The offset of the innermost element on the path that was recorded with `element`.
+    fn element_offset(&self) -> Option<usize> {
+        let mut key = String::new();
+        let mut offset = None;
+
+        for segment in &self.path {
+            if !key.is_empty() {
+                key.push('/');
+            }
+
+            let _ = write!(key, "{}[{}]", segment.name, segment.index.unwrap_or(0));
+
+            match self.offsets.get(&key) {
+                Some(element) => offset = Some(*element),
+                None => break,
+            }
+        }
+
+        offset
+    }
+}
+
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map_or(before, |newline| &before[newline + 1..])
+        .chars()
+        .count()
+        + 1;
+
+    (line, column)
+}
+
+/// Records `error` as the cause of the failure, returning the message to fail with.
+///
+/// The `Deserialize` implementations can only fail with a message, so errors that should reach the caller intact (such as exceeded limits or invalid tile data) are recorded here and put back by `parse`.
+pub(crate) fn fail(error: Error) -> String {
+    let message = error.to_string();
+
+    with_state(|state| {
+        if state.depth > 0 && state.failure.is_none() {
+            state.failure = Some(error);
+        }
+    });
+
+    message
+}
+
+/// The path to the part of the document being parsed, to report an error found later as if it had occurred there.
+#[cfg(feature = "rayon")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Mark(Vec<Segment>);
+
+#[cfg(feature = "rayon")]
+impl Mark {
+    /// Adds a segment to the marked path, like `enter`.
+    pub(crate) fn enter(&mut self, name: &'static str, index: Option<usize>) {
+        self.0.push(Segment {
+            name,
+            index,
+            id: None,
+        });
+    }
+}
+
+/// Marks the current path, see `fail_at`.
+#[cfg(feature = "rayon")]
+pub(crate) fn mark() -> Mark {
+    with_state(|state| Mark(state.path.clone()))
+}
+
+/// Records `error` as the cause of the failure at `mark`, in place of any failure recorded so far, returning the message to fail with.
+#[cfg(feature = "rayon")]
+pub(crate) fn fail_at(mark: Mark, error: Error) -> String {
+    with_state(|state| {
+        if state.depth > 0 {
+            state.path = mark.0;
+            state.failure = None;
+            state.error_offset = None;
+        }
+    });
+
+    fail(error)
+}
+
+/// Returns and clears the error recorded by `fail`, e.g. to recover from it.
+pub(crate) fn take_failure() -> Option<Error> {
+    with_state(|state| state.failure.take())
+}
+
+/// A segment of the path that was entered, see `enter`.
+#[must_use]
+pub(crate) struct Scope(usize);
+
+impl Scope {
+    /// Leaves the segment after it was parsed successfully (or recovered from), along with anything entered since.
+    ///
+    /// Scopes that aren't left, because parsing failed, stay on the path to report where it failed.
+    pub(crate) fn exit(self) {
+        with_state(|state| state.path.truncate(self.0));
+    }
+}
+
+/// Adds a segment to the path of the element being parsed, if a document is being parsed with `parse`.
+pub(crate) fn enter(name: &'static str, index: Option<usize>) -> Scope {
+    with_state(|state| {
+        let depth = state.path.len();
+
+        if state.depth > 0 {
+            state.path.push(Segment {
+                name,
+                index,
+                id: None,
+            });
+        }
+
+        Scope(depth)
+    })
+}
+
+/// Identifies the innermost segment by its ID once it is known.
+pub(crate) fn set_id(id: u32) {
+    with_state(|state| {
+        if let Some(segment) = state.path.last_mut() {
+            segment.id = Some(id);
+        }
+    });
+}
+
+/// Records the width or height of the map being parsed, returning both once they have been read.
+///
+/// Nothing is recorded outside of `parse` (e.g. when peeking at a header), as the state is only reset once a parse ends.
+pub(crate) fn map_size(width: Option<i32>, height: Option<i32>) -> Option<(i32, i32)> {
+    with_state(|state| {
+        if state.depth == 0 {
+            return None;
+        }
+
+        let size = &mut state.map_size;
+        size.0 = width.or(size.0);
+        size.1 = height.or(size.1);
+        size.0.zip(size.1)
+    })
+}
+
+/// Records the byte offset of an XML element, identified by its `name[index]` path from the root.
+#[cfg(feature = "xml")]
+pub(crate) fn element(key: String, offset: usize) {
+    with_state(|state| {
+        if state.depth > 0 {
+            state.offsets.insert(key, offset);
+        }
+    });
+}
+
+/// Records the byte offset of an XML syntax error.
+#[cfg(feature = "xml")]
+pub(crate) fn error_offset(offset: usize) {
+    with_state(|state| state.error_offset = Some(offset));
+}
+
+/// Deserializes the elements of a sequence, adding them to `elements`.
+///
+/// Entering `name[index]` for each element if a name is given, with the index counting on from the elements added before.
+pub(crate) struct Append<'a, T> {
+    pub(crate) name: Option<&'static str>,
+    pub(crate) elements: &'a mut Vec<T>,
+}
+
+impl<'de, 'a, T: serde::de::Deserialize<'de>> serde::de::DeserializeSeed<'de> for Append<'a, T> {
+    type Value = ();
+
+    fn deserialize<D: serde::de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a, T: serde::de::Deserialize<'de>> serde::de::Visitor<'de> for Append<'a, T> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.name {
+            Some(name) => write!(formatter, "a sequence of {}s", name),
+            None => formatter.write_str("a sequence"),
+        }
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        loop {
+            let scope = self.name.map(|name| enter(name, Some(self.elements.len())));
+            let element = seq.next_element()?;
+
+            if let Some(scope) = scope {
+                scope.exit();
+            }
+
+            match element {
+                Some(element) => self.elements.push(element),
+                None => return Ok(()),
+            }
+        }
+    }
+}
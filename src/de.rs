@@ -0,0 +1,239 @@
+//! Deserializers shared by the data model: for values that XML stores as strings, and for the sequences that XML splits up.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+use serde::{
+    de::{
+        value::{BorrowedStrDeserializer, MapAccessDeserializer, StringDeserializer},
+        DeserializeSeed, Deserializer, Error, MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize,
+};
+use serde_json::Value;
+
+/// Deserializes a number, or a string containing one.
+pub(crate) fn deserialize_number_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    <T as FromStr>::Err: Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber<T> {
+        String(String),
+        Number(T),
+    }
+
+    match StringOrNumber::<T>::deserialize(deserializer)? {
+        StringOrNumber::String(s) => s.parse::<T>().map_err(serde::de::Error::custom),
+        StringOrNumber::Number(n) => Ok(n),
+    }
+}
+
+/// Deserializes a boolean from a boolean, `1`/`0` (as a number or string) or `"true"`/`"false"`.
+pub(crate) fn deserialize_bool_from_anything<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AnythingOrBool {
+        String(String),
+        Int(i64),
+        Float(f64),
+        Boolean(bool),
+    }
+
+    let number = |n: f64| {
+        if n == 1.0 {
+            Ok(true)
+        } else if n == 0.0 {
+            Ok(false)
+        } else {
+            Err(serde::de::Error::custom("The number is neither 1 nor 0"))
+        }
+    };
+
+    match AnythingOrBool::deserialize(deserializer)? {
+        AnythingOrBool::Boolean(b) => Ok(b),
+        AnythingOrBool::Int(i) => number(i as f64),
+        AnythingOrBool::Float(f) => number(f),
+        AnythingOrBool::String(s) => match (s.parse::<bool>(), s.parse::<f64>()) {
+            (Ok(b), _) => Ok(b),
+            (_, Ok(n)) => number(n),
+            _ => Err(serde::de::Error::custom(
+                "Could not parse boolean from a string",
+            )),
+        },
+    }
+}
+
+/// A map key, borrowed from the input where possible.
+struct Key<'de>(Cow<'de, str>);
+
+impl<'de> Deserialize<'de> for Key<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = Key<'de>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string")
+            }
+
+            fn visit_borrowed_str<E: Error>(self, key: &'de str) -> Result<Key<'de>, E> {
+                Ok(Key(Cow::Borrowed(key)))
+            }
+
+            fn visit_str<E: Error>(self, key: &str) -> Result<Key<'de>, E> {
+                Ok(Key(Cow::Owned(key.to_string())))
+            }
+
+            fn visit_string<E: Error>(self, key: String) -> Result<Key<'de>, E> {
+                Ok(Key(Cow::Owned(key)))
+            }
+        }
+
+        deserializer.deserialize_str(KeyVisitor)
+    }
+}
+
+/// Passes the entries of a map on to a visitor, except those that `take` deserializes itself, given their key and the map to read the value from.
+///
+/// XML children of the same name that are split up by other elements come as an entry per run (see `crate::xml`), so the types holding sequences of them take those entries and add each run as it is read, while the other entries go to a derived visitor for the rest of their fields.
+pub(crate) struct Intercept<A, F> {
+    map: A,
+    take: F,
+}
+
+impl<'de, A, F> Intercept<A, F>
+where
+    A: MapAccess<'de>,
+    F: FnMut(&str, &mut A) -> Result<bool, A::Error>,
+{
+    pub(crate) fn new(map: A, take: F) -> MapAccessDeserializer<Intercept<A, F>> {
+        MapAccessDeserializer::new(Intercept { map, take })
+    }
+}
+
+impl<'de, A, F> MapAccess<'de> for Intercept<A, F>
+where
+    A: MapAccess<'de>,
+    F: FnMut(&str, &mut A) -> Result<bool, A::Error>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        while let Some(Key(key)) = self.map.next_key()? {
+            if (self.take)(&key, &mut self.map)? {
+                continue;
+            }
+
+            return match key {
+                Cow::Borrowed(key) => seed.deserialize(BorrowedStrDeserializer::new(key)),
+                Cow::Owned(key) => seed.deserialize(StringDeserializer::new(key)),
+            }
+            .map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+}
+
+/// A JSON value in which entries of a map with the same key are merged, concatenating their arrays.
+///
+/// XML children of the same name that are split up by other elements come as an entry per run, which this puts back together.
+pub(crate) struct Merged(pub(crate) Value);
+
+impl<'de> Deserialize<'de> for Merged {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MergedVisitor;
+
+        impl<'de> Visitor<'de> for MergedVisitor {
+            type Value = Merged;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("any valid JSON value")
+            }
+
+            fn visit_bool<E: Error>(self, value: bool) -> Result<Merged, E> {
+                Ok(Merged(Value::Bool(value)))
+            }
+
+            fn visit_i64<E: Error>(self, value: i64) -> Result<Merged, E> {
+                Ok(Merged(Value::from(value)))
+            }
+
+            fn visit_u64<E: Error>(self, value: u64) -> Result<Merged, E> {
+                Ok(Merged(Value::from(value)))
+            }
+
+            fn visit_f64<E: Error>(self, value: f64) -> Result<Merged, E> {
+                Ok(Merged(Value::from(value)))
+            }
+
+            fn visit_str<E: Error>(self, value: &str) -> Result<Merged, E> {
+                Ok(Merged(Value::from(value)))
+            }
+
+            fn visit_string<E: Error>(self, value: String) -> Result<Merged, E> {
+                Ok(Merged(Value::from(value)))
+            }
+
+            fn visit_none<E: Error>(self) -> Result<Merged, E> {
+                Ok(Merged(Value::Null))
+            }
+
+            fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Merged, D::Error> {
+                Merged::deserialize(deserializer)
+            }
+
+            fn visit_unit<E: Error>(self) -> Result<Merged, E> {
+                Ok(Merged(Value::Null))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Merged, A::Error> {
+                let mut values = Vec::new();
+
+                while let Some(Merged(value)) = seq.next_element()? {
+                    values.push(value);
+                }
+
+                Ok(Merged(Value::Array(values)))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Merged, A::Error> {
+                let mut object = serde_json::Map::new();
+
+                while let Some((key, Merged(value))) = map.next_entry::<String, Merged>()? {
+                    match (object.get_mut(&key), value) {
+                        (Some(Value::Array(values)), Value::Array(more)) => values.extend(more),
+                        (_, value) => {
+                            object.insert(key, value);
+                        }
+                    }
+                }
+
+                Ok(Merged(Value::Object(object)))
+            }
+        }
+
+        deserializer.deserialize_any(MergedVisitor)
+    }
+}
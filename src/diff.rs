@@ -0,0 +1,276 @@
+use crate::{
+    layer::{Chunk, Layer, LayerData, Tile},
+    map::Map,
+};
+
+use alloc::{borrow::Cow, vec, vec::Vec};
+use core::convert::TryFrom;
+
+/// A rectangle of tiles, in tile coordinates.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The smallest region containing both, computed without overflowing and saturating sizes that don't fit in a `u32`.
+    fn union(self, other: Region) -> Region {
+        let end = |start: i32, len: u32| i64::from(start) + i64::from(len);
+        let len = |start: i32, end: i64| u32::try_from(end - i64::from(start)).unwrap_or(u32::MAX);
+
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = end(self.x, self.width).max(end(other.x, other.width));
+        let bottom = end(self.y, self.height).max(end(other.y, other.height));
+
+        Region {
+            x,
+            y,
+            width: len(x, right),
+            height: len(y, bottom),
+        }
+    }
+
+    /// Moves the region by `x` and `y`, saturating at the bounds of `i32`.
+    fn offset(self, x: i32, y: i32) -> Region {
+        Region {
+            x: self.x.saturating_add(x),
+            y: self.y.saturating_add(y),
+            ..self
+        }
+    }
+}
+
+/// The changes to a layer that exists in both versions of a map.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LayerChange {
+    /// The ID of the layer.
+    pub id: u32,
+    /// Whether any of the layer's attributes (name, visibility, opacity, offset, tile layer format, ...) changed.
+    pub attributes_changed: bool,
+    /// The regions containing tiles that changed, empty if only attributes changed.
+    pub regions: Vec<Region>,
+}
+
+/// What changed between two versions of a map, so that a running game can patch its state instead of reloading the whole level.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ChangeSet {
+    /// Whether any of the map's own attributes (size, orientation, background color, ...) changed.
+    pub map_changed: bool,
+    /// The IDs of layers that were added.
+    pub layers_added: Vec<u32>,
+    /// The IDs of layers that were removed.
+    pub layers_removed: Vec<u32>,
+    /// Layers that exist in both versions but changed.
+    pub layers_modified: Vec<LayerChange>,
+    /// Whether the layers were reordered.
+    pub layers_reordered: bool,
+    /// The first global tile IDs of tilesets that were added, removed or changed, including external tilesets whose file changed.
+    pub tilesets_updated: Vec<u32>,
+}
+
+impl ChangeSet {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        *self == ChangeSet::default()
+    }
+}
+
+fn same_map_attributes(old: &Map, new: &Map) -> bool {
+    #[cfg(feature = "preserve-unknown")]
+    if old.extra != new.extra {
+        return false;
+    }
+
+    old.metadata == new.metadata
+        && old.orientation == new.orientation
+        && old.render_order == new.render_order
+        && old.compression_level == new.compression_level
+        && old.width == new.width
+        && old.height == new.height
+        && old.tile_width == new.tile_width
+        && old.tile_height == new.tile_height
+        && old.infinite == new.infinite
+        && old.background_color == new.background_color
+        && old.next_layer_id == new.next_layer_id
+        && old.next_object_id == new.next_object_id
+}
+
+fn same_layer_attributes(old: &Layer, new: &Layer) -> bool {
+    #[cfg(feature = "preserve-unknown")]
+    if old.extra != new.extra {
+        return false;
+    }
+
+    old.id == new.id
+        && old.name == new.name
+        && old.x == new.x
+        && old.y == new.y
+        && old.width == new.width
+        && old.height == new.height
+        && old.visible == new.visible
+        && old.locked == new.locked
+        && old.opacity == new.opacity
+        && old.offset_x == new.offset_x
+        && old.offset_y == new.offset_y
+        && old.format == new.format
+}
+
+/// The bounding box of the tiles that differ between two grids of the same size, relative to the grid.
+fn changed_region(old: &[Tile], new: &[Tile], width: u32) -> Option<Region> {
+    if width == 0 {
+        return None;
+    }
+
+    old.iter()
+        .zip(new)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(i, _)| Region {
+            x: (i as u32 % width) as i32,
+            y: (i as u32 / width) as i32,
+            width: 1,
+            height: 1,
+        })
+        .fold(None, |region: Option<Region>, tile| {
+            Some(region.map_or(tile, |region| region.union(tile)))
+        })
+}
+
+fn chunk_region(chunk: &Chunk) -> Region {
+    Region {
+        x: chunk.x as i32,
+        y: chunk.y as i32,
+        width: chunk.width,
+        height: chunk.height,
+    }
+}
+
+fn changed_chunks(old: &[Chunk], new: &[Chunk]) -> Vec<Region> {
+    fn find<'a>(chunks: &'a [Chunk], chunk: &Chunk) -> Option<&'a Chunk> {
+        chunks
+            .iter()
+            .find(|other| chunk_region(other) == chunk_region(chunk))
+    }
+
+    let mut regions = Vec::new();
+
+    for chunk in new {
+        match find(old, chunk) {
+            Some(old) => {
+                if let Some(region) = changed_region(&old.data, &chunk.data, chunk.width) {
+                    regions.push(region.offset(chunk.x as i32, chunk.y as i32));
+                }
+            }
+            None => regions.push(chunk_region(chunk)),
+        }
+    }
+
+    for chunk in old {
+        if find(new, chunk).is_none() {
+            regions.push(chunk_region(chunk));
+        }
+    }
+
+    regions
+}
+
+/// The tiles of a fixed-size layer, so that they are compared the same way however they are stored.
+fn tiles(data: &LayerData) -> Option<Cow<'_, [Tile]>> {
+    match data {
+        LayerData::Tiles(tiles) => Some(Cow::Borrowed(tiles)),
+        LayerData::CompactTiles(tiles) => Some(Cow::Owned(tiles.to_vec())),
+        _ => None,
+    }
+}
+
+fn changed_tiles(old: &Layer, new: &Layer) -> Vec<Region> {
+    let whole = || Region {
+        x: new.x,
+        y: new.y,
+        width: new.width.max(old.width).max(0) as u32,
+        height: new.height.max(old.height).max(0) as u32,
+    };
+
+    match (tiles(&old.data), tiles(&new.data)) {
+        (Some(old_tiles), Some(new_tiles))
+            if old.width == new.width && old_tiles.len() == new_tiles.len() =>
+        {
+            return changed_region(&old_tiles, &new_tiles, new.width as u32)
+                .map(|region| region.offset(new.x, new.y))
+                .into_iter()
+                .collect();
+        }
+        (Some(_), Some(_)) => return vec![whole()],
+        _ => {}
+    }
+
+    match (&old.data, &new.data) {
+        (LayerData::Chunks(old), LayerData::Chunks(new)) => changed_chunks(old, new),
+        (old_data, new_data) if old_data == new_data => Vec::new(),
+        _ => vec![whole()],
+    }
+}
+
+/// Compares two versions of a map.
+///
+/// Layers are matched by ID and tilesets by their first global tile ID.
+pub fn diff(old: &Map, new: &Map) -> ChangeSet {
+    let mut changes = ChangeSet {
+        map_changed: !same_map_attributes(old, new),
+        ..ChangeSet::default()
+    };
+
+    for layer in &new.layers {
+        match old.layers.iter().find(|old| old.id == layer.id) {
+            Some(old) => {
+                let change = LayerChange {
+                    id: layer.id,
+                    attributes_changed: !same_layer_attributes(old, layer),
+                    regions: changed_tiles(old, layer),
+                };
+
+                if change.attributes_changed || !change.regions.is_empty() {
+                    changes.layers_modified.push(change);
+                }
+            }
+            None => changes.layers_added.push(layer.id),
+        }
+    }
+
+    for layer in &old.layers {
+        if new.layers.iter().all(|new| new.id != layer.id) {
+            changes.layers_removed.push(layer.id);
+        }
+    }
+
+    let common_order = |a: &Map, b: &Map| {
+        a.layers
+            .iter()
+            .map(|layer| layer.id)
+            .filter(|id| b.layers.iter().any(|layer| layer.id == *id))
+            .collect::<Vec<_>>()
+    };
+    changes.layers_reordered = common_order(old, new) != common_order(new, old);
+
+    for tileset in &new.tilesets {
+        if !old.tilesets.contains(tileset) {
+            changes.tilesets_updated.push(tileset.first_gid);
+        }
+    }
+
+    for tileset in &old.tilesets {
+        if new
+            .tilesets
+            .iter()
+            .all(|new| new.first_gid != tileset.first_gid)
+        {
+            changes.tilesets_updated.push(tileset.first_gid);
+        }
+    }
+
+    changes
+}
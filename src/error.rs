@@ -1,11 +1,159 @@
-use crate::to_json;
+#[cfg(feature = "lua")]
+use crate::lua;
+#[cfg(feature = "xml")]
+use crate::xml;
+use crate::{
+    compat::Feature, format::Format, layer::DataFormat, metadata::Version, options::Limit,
+};
+use alloc::{boxed::Box, string::String};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+/// Where in a document parsing failed.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Location {
+    /// The logical path to the element that failed to parse, e.g. `map/layer[id=3]/data/chunk[2]`. Elements with an ID are identified by it, others by their position among their siblings.
+    pub path: String,
+    /// The line in the source, starting at 1, if known.
+    pub line: Option<usize>,
+    /// The column in the source, starting at 1, if known.
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)?;
+
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            if !self.path.is_empty() {
+                f.write_str(" ")?;
+            }
+
+            write!(f, "(line {}, column {})", line, column)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Why the tile data of a layer or chunk couldn't be decoded (or encoded).
+#[derive(Debug, thiserror::Error)]
+pub enum DataError {
+    #[error("missing encoding")]
+    MissingEncoding,
+    #[error("invalid encoding: {0:?}")]
+    InvalidEncoding(String),
+    #[error("invalid compression: {0:?}")]
+    InvalidCompression(String),
+    #[error("invalid CSV: {0}")]
+    Csv(#[from] core::num::ParseIntError),
+    #[cfg(feature = "base64-data")]
+    #[error("invalid base64: {0}")]
+    Base64(#[cfg_attr(feature = "std", from)] base64::DecodeError),
+    #[cfg(feature = "std")]
+    #[error("error decompressing: {0}")]
+    Decompression(#[from] std::io::Error),
+    #[cfg(feature = "std")]
+    #[error("error compressing: {0}")]
+    Compression(std::io::Error),
+    #[error("{0} bytes is not a whole number of tiles")]
+    Length(usize),
+}
+
+/// Why bytes written by `Map::to_cache_bytes` or `Tileset::to_cache_bytes` couldn't be loaded.
+#[cfg(feature = "binary")]
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    #[error("missing TMXC header")]
+    InvalidMagic,
+    #[error("unsupported format version {0}, expected {expected}", expected = crate::binary::VERSION)]
+    UnsupportedVersion(u16),
+    #[error("expected a cached {expected}")]
+    WrongKind { expected: &'static str },
+    #[error("payload is {found} bytes instead of {expected}")]
+    Length { expected: usize, found: usize },
+    #[error("payload of {0} bytes is too large to cache")]
+    TooLarge(usize),
+    #[error("checksum mismatch: expected {expected:#010x}, found {found:#010x}")]
+    Checksum { expected: u32, found: u32 },
+    #[error("unexpected end of data")]
+    UnexpectedEof,
+    #[error("{0} bytes left over")]
+    TrailingBytes(usize),
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+    #[error("{0} require the std feature")]
+    RequiresStd(&'static str),
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("error deserializing")]
     Deserialization(#[from] serde_json::Error),
-    #[error("error converting to JSON")]
-    Conversion(#[from] to_json::Error),
+    #[cfg(feature = "xml")]
+    #[error("error parsing XML")]
+    Conversion(#[from] xml::Error),
+    #[cfg(feature = "lua")]
+    #[error("error converting Lua to JSON")]
+    LuaConversion(#[from] lua::Error),
     #[error("error converting to UTF8")]
-    Utf8Error(#[from] std::str::Utf8Error),
+    Utf8Error(#[from] core::str::Utf8Error),
+    #[cfg(feature = "std")]
+    #[error("error reading {}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[cfg(feature = "std")]
+    #[error("{} is outside of the sandbox root", path.display())]
+    PathEscape { path: PathBuf },
+    #[cfg(feature = "watch")]
+    #[error("error watching files")]
+    Watch(#[from] notify::Error),
+    #[error("parse limit exceeded: {0}")]
+    LimitExceeded(Limit),
+    #[error("missing <{0}> element")]
+    MissingElement(&'static str),
+    #[error("invalid tile data: {0}")]
+    TileData(#[from] DataError),
+    #[error("{location}: {source}")]
+    At {
+        location: Location,
+        source: Box<Error>,
+    },
+    #[cfg(feature = "std")]
+    #[error("error reading")]
+    Read(#[from] std::io::Error),
+    #[cfg(feature = "binary")]
+    #[error("error loading cached data")]
+    Cache(#[from] CacheError),
+    #[error("invalid version: {0:?}")]
+    InvalidVersion(String),
+    #[error("{feature} requires Tiled {required}")]
+    UnsupportedFeature { feature: Feature, required: Version },
+    #[error("unsupported tile layer format: {0:?}")]
+    UnsupportedDataFormat(DataFormat),
+    #[error("unable to determine file format")]
+    UnknownFormat,
+    #[error("unsupported file format: {0:?}")]
+    UnsupportedFormat(Format),
+}
+
+impl Error {
+    /// Where in the document parsing failed, if known.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::At { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::At { source, .. } => source.kind(),
+            error => error,
+        }
+    }
 }
@@ -0,0 +1,165 @@
+use crate::de::Merged;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::hash::{Hash, Hasher};
+use serde::{
+    de::{Deserializer, Error},
+    Deserialize,
+};
+use serde_json::Value;
+
+/// The entry the XML reader adds to elements with children: their names in document order, as runs of `[name, count]`.
+///
+/// XML names can't start with `@`, so it doesn't clash with an attribute or child.
+pub(crate) const ORDER: &str = "@order";
+
+/// Attributes and child elements that are not otherwise modelled, kept so that they can be written back out.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Extra {
+    /// The attributes, stored as strings (XML), or the entries with their JSON value (JSON and Lua).
+    pub attributes: BTreeMap<String, Value>,
+    /// The child elements (XML), in document order.
+    pub children: Vec<Child>,
+}
+
+/// A child element that is not otherwise modelled.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Child {
+    pub name: String,
+    /// An object holding the attributes, the text (as `_`) and the children of the element, grouped by name into arrays.
+    pub element: Value,
+    /// The number of modelled children before it, e.g. the tilesets and layers of a map, so that it is written back in place.
+    pub position: usize,
+}
+
+impl Extra {
+    /// Returns the unrecognized attribute with the given name.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.attributes.get(key)
+    }
+
+    /// Returns the unrecognized child elements with the given name, in document order.
+    pub fn elements<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.children
+            .iter()
+            .filter(move |child| child.name == name)
+            .map(|child| &child.element)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.children.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.attributes.iter()
+    }
+
+    /// Moves the children out of `attributes`, where they are grouped by name, into `children` in the `order` read.
+    ///
+    /// Names whose number of children doesn't match are left where they are.
+    fn place_children(&mut self, order: &Value) {
+        let runs = match order.as_array() {
+            Some(runs) => runs
+                .iter()
+                .filter_map(|run| match run.as_array()?.as_slice() {
+                    [name, count] => Some((name.as_str()?, count.as_u64()? as usize)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+
+        let mut groups = BTreeMap::new();
+
+        for (name, _) in &runs {
+            let count = runs
+                .iter()
+                .filter(|(other, _)| other == name)
+                .map(|(_, count)| count)
+                .sum::<usize>();
+
+            match self.attributes.get(*name) {
+                Some(Value::Array(elements)) if elements.len() == count => {}
+                _ => continue,
+            }
+
+            if let Some(Value::Array(elements)) = self.attributes.remove(*name) {
+                groups.insert(*name, elements.into_iter());
+            }
+        }
+
+        let mut position = 0;
+
+        for (name, count) in runs {
+            match groups.get_mut(name) {
+                Some(elements) => self
+                    .children
+                    .extend(elements.take(count).map(|element| Child {
+                        name: name.to_string(),
+                        element,
+                        position,
+                    })),
+                None => position += count,
+            }
+        }
+    }
+}
+
+impl Hash for Extra {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for (key, value) in &self.attributes {
+            key.hash(state);
+            value.to_string().hash(state);
+        }
+
+        for child in &self.children {
+            child.name.hash(state);
+            child.element.to_string().hash(state);
+            child.position.hash(state);
+        }
+    }
+}
+
+/// Removes the order of the children of nested elements, which are written back grouped by name.
+fn remove_order(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.remove(ORDER);
+            object.values_mut().for_each(remove_order);
+        }
+        Value::Array(values) => values.iter_mut().for_each(remove_order),
+        _ => {}
+    }
+}
+
+/// Collects every remaining entry whose key is not in `known`.
+///
+/// Flattened fields that are deserialized through `deserialize_any` (enums, untagged data) leave their entries behind, so each type lists all the keys it models. Children whose name isn't known are placed among the modelled ones with the order the XML reader adds.
+pub(crate) fn deserialize<'de, D>(deserializer: D, known: &[&str]) -> Result<Extra, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // Children split up by other elements come as an entry per run.
+    let mut map = match Merged::deserialize(deserializer)? {
+        Merged(Value::Object(object)) => object.into_iter().collect::<BTreeMap<_, _>>(),
+        _ => return Err(D::Error::custom("expected a map")),
+    };
+    let order = map.remove(ORDER);
+    map.retain(|key, _| !known.contains(&key.as_str()));
+    map.values_mut().for_each(remove_order);
+
+    let mut extra = Extra {
+        attributes: map,
+        children: Vec::new(),
+    };
+
+    if let Some(order) = order {
+        extra.place_children(&order);
+    }
+
+    Ok(extra)
+}
@@ -0,0 +1,109 @@
+#[cfg(feature = "std")]
+use std::{io::BufRead, path::Path};
+
+/// The file format of a map or tileset.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Format {
+    /// TMX/TSX (Tiled XML).
+    Xml,
+    /// Tiled JSON (`.tmj`/`.tsj`, or `.json` before Tiled 1.8).
+    Json,
+    /// Tiled Lua export.
+    Lua,
+}
+
+impl Format {
+    /// Determines the format from the extension of a file path, e.g. a tileset `source` attribute.
+    #[cfg(feature = "std")]
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Format> {
+        Format::from_extension_str(path.as_ref().extension()?.to_str()?)
+    }
+
+    /// Determines the format from the extension of a file path, e.g. a tileset `source` attribute.
+    ///
+    /// Without `std` paths are plain strings, whose extension follows the last `.` of the file name.
+    #[cfg(not(feature = "std"))]
+    pub fn from_extension(path: &str) -> Option<Format> {
+        let name = path.rsplit('/').next()?;
+        let (stem, extension) = name.rsplit_once('.')?;
+
+        if stem.is_empty() {
+            return None;
+        }
+
+        Format::from_extension_str(extension)
+    }
+
+    fn from_extension_str(extension: &str) -> Option<Format> {
+        let extension = extension.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "tmx" | "tsx" | "tx" | "xml" => Some(Format::Xml),
+            "tmj" | "tsj" | "tj" | "json" => Some(Format::Json),
+            "lua" => Some(Format::Lua),
+            _ => None,
+        }
+    }
+
+    /// Determines the format by looking at the first significant characters of the content.
+    pub fn sniff(buf: &[u8]) -> Option<Format> {
+        let buf = buf.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(buf);
+        let start = buf.iter().position(|b| !b.is_ascii_whitespace())?;
+        let buf = &buf[start..];
+
+        match buf[0] {
+            b'<' => Some(Format::Xml),
+            b'{' => Some(Format::Json),
+            _ if buf.starts_with(b"return") || buf.starts_with(b"--") => Some(Format::Lua),
+            _ => None,
+        }
+    }
+
+    /// Determines the format by looking at the first significant characters available from `reader`.
+    ///
+    /// A leading UTF-8 byte order mark and whitespace are consumed, everything else is left in the reader.
+    #[cfg(feature = "std")]
+    pub fn sniff_reader<R: BufRead>(reader: &mut R) -> std::io::Result<Option<Format>> {
+        // The byte order mark may be split between reads.
+        let mut bom: &[u8] = b"\xEF\xBB\xBF";
+
+        while !bom.is_empty() {
+            let buf = reader.fill_buf()?;
+            let matched = buf.iter().zip(bom).take_while(|(a, b)| a == b).count();
+
+            match matched {
+                0 if bom.len() == 3 => break,
+                // Part of a byte order mark, which no format starts with.
+                0 => return Ok(None),
+                _ => {
+                    reader.consume(matched);
+                    bom = &bom[matched..];
+                }
+            }
+        }
+
+        loop {
+            let buf = reader.fill_buf()?;
+
+            if buf.is_empty() {
+                return Ok(None);
+            }
+
+            let whitespace = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+
+            if whitespace < buf.len() {
+                let format = Format::sniff(buf);
+                reader.consume(whitespace);
+                return Ok(format);
+            }
+
+            reader.consume(whitespace);
+        }
+    }
+
+    /// Determines the format from the extension of `path`, falling back to sniffing the content.
+    #[cfg(feature = "std")]
+    pub fn detect<P: AsRef<Path>>(path: P, buf: &[u8]) -> Option<Format> {
+        Format::from_extension(path).or_else(|| Format::sniff(buf))
+    }
+}
@@ -1,67 +1,128 @@
+use crate::{
+    context,
+    de::deserialize_number_from_string,
+    error::{DataError, Error},
+    options::{self, Limit, ParseLimits},
+};
+use crate::{diff::Region, error::Location};
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+};
 use serde::{de::Deserializer, Deserialize};
-use serde_aux::field_attributes::deserialize_number_from_string;
-
-fn parse_csv(value: String) -> Result<Vec<Tile>, String> {
-    Ok(value
-        .split('\n')
-        .filter(|s| s.trim() != "")
-        .flat_map(|s| s.split(','))
-        .filter(|s| s.trim() != "")
+#[cfg(feature = "std")]
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+fn parse_csv(value: &str, limits: &ParseLimits) -> Result<Vec<Tile>, Error> {
+    let values = || {
+        value
+            .split('\n')
+            .filter(|s| s.trim() != "")
+            .flat_map(|s| s.split(','))
+            .filter(|s| s.trim() != "")
+    };
+
+    if let Some(max) = limits.max_tiles {
+        if values().count() > max {
+            return Err(Error::LimitExceeded(Limit::Tiles(max)));
+        }
+    }
+
+    Ok(values()
         .map(|gid| gid.trim().parse())
         .collect::<Result<Vec<u32>, _>>()
-        .map_err(|e| e.to_string())?
+        .map_err(DataError::Csv)?
         .into_iter()
         .map(|gid| Tile { gid })
         .collect())
 }
 
 #[cfg(feature = "base64-data")]
-fn decode_base64(value: String) -> Result<Vec<u8>, String> {
-    let data = base64::decode(value.trim().as_bytes()).map_err(|e| e.to_string())?;
+fn decode_base64(value: &str) -> Result<Vec<u8>, Error> {
+    let data = base64::decode(value.trim().as_bytes()).map_err(DataError::Base64)?;
 
     Ok(data)
 }
 
-#[cfg(feature = "gzip-data")]
-fn decode_gzip(data: Vec<u8>) -> Result<Vec<u8>, String> {
-    use libflate::gzip::Decoder;
+/// Reads all of `reader`, failing as soon as more than `max_decompressed_bytes` have been read.
+#[cfg(any(feature = "gzip-data", feature = "zlib-data", feature = "zstd-data"))]
+fn read_limited<R: std::io::Read>(reader: R, limits: &ParseLimits) -> Result<Vec<u8>, Error> {
     use std::io::Read;
 
-    let mut decoder = Decoder::new(data.as_slice()).map_err(|e| e.to_string())?;
     let mut data = Vec::new();
-    decoder.read_to_end(&mut data).map_err(|e| e.to_string())?;
+
+    match limits.max_decompressed_bytes {
+        Some(max) => {
+            reader
+                .take(max as u64 + 1)
+                .read_to_end(&mut data)
+                .map_err(DataError::Decompression)?;
+
+            if data.len() > max {
+                return Err(Error::LimitExceeded(Limit::DecompressedBytes(max)));
+            }
+        }
+        None => {
+            let mut reader = reader;
+            reader
+                .read_to_end(&mut data)
+                .map_err(DataError::Decompression)?;
+        }
+    }
 
     Ok(data)
 }
 
+#[cfg(feature = "gzip-data")]
+fn decode_gzip(data: Vec<u8>, limits: &ParseLimits) -> Result<Vec<u8>, Error> {
+    use libflate::gzip::Decoder;
+
+    let decoder = Decoder::new(data.as_slice()).map_err(DataError::Decompression)?;
+    read_limited(decoder, limits)
+}
+
 #[cfg(feature = "zlib-data")]
-fn decode_zlib(data: Vec<u8>) -> Result<Vec<u8>, String> {
+fn decode_zlib(data: Vec<u8>, limits: &ParseLimits) -> Result<Vec<u8>, Error> {
     use libflate::zlib::Decoder;
-    use std::io::Read;
 
-    let mut decoder = Decoder::new(data.as_slice()).map_err(|e| e.to_string())?;
-    let mut data = Vec::new();
-    decoder.read_to_end(&mut data).map_err(|e| e.to_string())?;
-
-    Ok(data)
+    let decoder = Decoder::new(data.as_slice()).map_err(DataError::Decompression)?;
+    read_limited(decoder, limits)
 }
 
 #[cfg(feature = "zstd-data")]
-fn decode_zstd(data: Vec<u8>) -> Result<Vec<u8>, String> {
-    let data = zstd::stream::decode_all(data.as_slice()).map_err(|e| e.to_string())?;
+fn decode_zstd(data: Vec<u8>, limits: &ParseLimits) -> Result<Vec<u8>, Error> {
+    use zstd::stream::read::Decoder;
 
-    Ok(data)
+    let decoder = Decoder::new(data.as_slice()).map_err(DataError::Decompression)?;
+    read_limited(decoder, limits)
 }
 
 #[cfg(feature = "base64-data")]
-fn parse_base64_data(data: Vec<u8>) -> Result<Vec<Tile>, String> {
-    use std::convert::TryInto;
+fn parse_base64_data(data: Vec<u8>, limits: &ParseLimits) -> Result<Vec<Tile>, Error> {
+    use core::convert::TryInto;
+
+    if let Some(max) = limits.max_tiles {
+        if data.len() / 4 > max {
+            return Err(Error::LimitExceeded(Limit::Tiles(max)));
+        }
+    }
 
     Ok(data
         .chunks(4)
         .map(|chunk| chunk.try_into())
         .collect::<Result<Vec<[u8; 4]>, _>>()
-        .map_err(|e| e.to_string())?
+        .map_err(|_| DataError::Length(data.len()))?
         .into_iter()
         .map(u32::from_le_bytes)
         .map(|gid| Tile { gid })
@@ -69,27 +130,49 @@ fn parse_base64_data(data: Vec<u8>) -> Result<Vec<Tile>, String> {
 }
 
 fn decode_tile_data(
-    encoding: Option<String>,
-    #[allow(unused_variables)] compression: Option<String>,
-    data: String,
-) -> Result<Vec<Tile>, String> {
-    match encoding.as_deref() {
-        Some("csv") => parse_csv(data),
+    encoding: Option<&str>,
+    #[allow(unused_variables)] compression: Option<&str>,
+    data: &str,
+    limits: &ParseLimits,
+) -> Result<Vec<Tile>, Error> {
+    match encoding {
+        Some("csv") => parse_csv(data, limits),
         #[cfg(feature = "base64-data")]
-        Some("base64") => decode_base64(data)
-            .and_then(|data| match compression.as_deref() {
-                None => Ok(data),
-                #[cfg(feature = "gzip-data")]
-                Some("gzip") => decode_gzip(data),
-                #[cfg(feature = "zlib-data")]
-                Some("zlib") => decode_zlib(data),
-                #[cfg(feature = "zstd-data")]
-                Some("zstd") => decode_zstd(data),
-                Some(compression) => Err(format!("invalid compression: {}", compression)),
-            })
-            .and_then(parse_base64_data),
-        None => Err("missing encoding".into()),
-        Some(encoding) => Err(format!("invalid encoding: {:?}", encoding)),
+        Some("base64") => {
+            if let (None, Some(max)) = (compression, limits.max_decompressed_bytes) {
+                let data = data.trim();
+                let padding = data.bytes().rev().take_while(|b| *b == b'=').count();
+
+                if (data.len() / 4 * 3).saturating_sub(padding) > max {
+                    return Err(Error::LimitExceeded(Limit::DecompressedBytes(max)));
+                }
+            }
+
+            decode_base64(data)
+                .and_then(|data| match compression {
+                    None => Ok(data),
+                    #[cfg(feature = "gzip-data")]
+                    Some("gzip") => decode_gzip(data, limits),
+                    #[cfg(feature = "zlib-data")]
+                    Some("zlib") => decode_zlib(data, limits),
+                    #[cfg(feature = "zstd-data")]
+                    Some("zstd") => decode_zstd(data, limits),
+                    Some(compression) => {
+                        Err(DataError::InvalidCompression(compression.to_string()).into())
+                    }
+                })
+                .and_then(|data| parse_base64_data(data, limits))
+        }
+        None => Err(DataError::MissingEncoding.into()),
+        Some(encoding) => Err(DataError::InvalidEncoding(encoding.to_string()).into()),
+    }
+}
+
+/// Checks the number of tiles that were stored unencoded.
+fn check_tiles(tiles: &[Tile], limits: &ParseLimits) -> Result<(), Error> {
+    match limits.tiles_exceeded(tiles.len() as i64, 1) {
+        Some(limit) => Err(Error::LimitExceeded(limit)),
+        None => Ok(()),
     }
 }
 
@@ -97,10 +180,11 @@ const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x8000_0000;
 const FLIPPED_VERTICALLY_FLAG: u32 = 0x4000_0000;
 const FLIPPED_DIAGONALLY_FLAG: u32 = 0x2000_0000;
 
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq)]
 pub struct Tile {
-    #[serde(deserialize_with = "deserialize_number_from_string")]
-    gid: u32,
+    /// Empty tiles are written as `<tile/>`.
+    #[serde(default, deserialize_with = "deserialize_number_from_string")]
+    pub(crate) gid: u32,
 }
 
 impl Tile {
@@ -125,6 +209,129 @@ impl Tile {
     }
 }
 
+/// The cells of `CompactTiles`, of the smallest width that can hold every tile.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Cells {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+/// Tiles packed into 8 or 16 bit cells when their GIDs and flip flags allow, falling back to 32 bits (see `ParseOptions::compact_tiles`).
+///
+/// When any of the tiles is flipped, the three flags are kept in the top bits of each cell, leaving 5 or 13 bits for the GID.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CompactTiles {
+    pub(crate) cells: Cells,
+    pub(crate) flagged: bool,
+}
+
+impl CompactTiles {
+    const FLAGS: u32 =
+        FLIPPED_HORIZONTALLY_FLAG | FLIPPED_VERTICALLY_FLAG | FLIPPED_DIAGONALLY_FLAG;
+
+    /// Packs `tiles` into the smallest cells that can hold all of them.
+    pub fn new(tiles: &[Tile]) -> CompactTiles {
+        let flagged = tiles.iter().any(|tile| tile.gid & Self::FLAGS != 0);
+        let max = tiles.iter().map(|tile| tile.gid()).max().unwrap_or(0);
+        let fits = |bits: u32| {
+            if flagged {
+                max < 1 << (bits - 3)
+            } else {
+                u64::from(max) < 1 << bits
+            }
+        };
+        let pack = |bits: u32, tile: &Tile| {
+            if flagged {
+                (tile.gid >> 29) << (bits - 3) | tile.gid()
+            } else {
+                tile.gid
+            }
+        };
+
+        let cells = if fits(8) {
+            Cells::U8(tiles.iter().map(|tile| pack(8, tile) as u8).collect())
+        } else if fits(16) {
+            Cells::U16(tiles.iter().map(|tile| pack(16, tile) as u16).collect())
+        } else {
+            return CompactTiles {
+                cells: Cells::U32(tiles.iter().map(|tile| tile.gid).collect()),
+                flagged: false,
+            };
+        };
+
+        CompactTiles { cells, flagged }
+    }
+
+    fn unpack(&self, bits: u32, cell: u32) -> Tile {
+        if self.flagged {
+            Tile {
+                gid: (cell >> (bits - 3)) << 29 | cell & ((1 << (bits - 3)) - 1),
+            }
+        } else {
+            Tile { gid: cell }
+        }
+    }
+
+    /// The number of tiles.
+    pub fn len(&self) -> usize {
+        match &self.cells {
+            Cells::U8(cells) => cells.len(),
+            Cells::U16(cells) => cells.len(),
+            Cells::U32(cells) => cells.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The size of each cell in bytes: 1, 2 or 4.
+    pub fn cell_size(&self) -> usize {
+        match &self.cells {
+            Cells::U8(_) => 1,
+            Cells::U16(_) => 2,
+            Cells::U32(_) => 4,
+        }
+    }
+
+    /// Returns the tile at `index`, counting row by row.
+    pub fn get(&self, index: usize) -> Option<Tile> {
+        match &self.cells {
+            Cells::U8(cells) => cells
+                .get(index)
+                .map(|cell| self.unpack(8, u32::from(*cell))),
+            Cells::U16(cells) => cells
+                .get(index)
+                .map(|cell| self.unpack(16, u32::from(*cell))),
+            Cells::U32(cells) => cells.get(index).map(|cell| Tile { gid: *cell }),
+        }
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = Tile> + '_ {
+        (0..self.len()).map(move |index| self.get(index).unwrap_or_default())
+    }
+
+    pub fn to_vec(&self) -> Vec<Tile> {
+        self.iter().collect()
+    }
+
+    /// Pads with empty tiles or truncates to `len` tiles.
+    pub(crate) fn resize(&mut self, len: usize) {
+        match &mut self.cells {
+            Cells::U8(cells) => cells.resize(len, 0),
+            Cells::U16(cells) => cells.resize(len, 0),
+            Cells::U32(cells) => cells.resize(len, 0),
+        }
+    }
+}
+
+impl From<Vec<Tile>> for CompactTiles {
+    fn from(tiles: Vec<Tile>) -> CompactTiles {
+        CompactTiles::new(&tiles)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
 pub struct Chunk {
     /// The x coordinate of the chunk in tiles.
@@ -138,10 +345,706 @@ pub struct Chunk {
     pub data: Vec<Tile>,
 }
 
+/// How the tile data of a layer is encoded, see `DataFormat`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Encoding {
+    Csv,
+    Base64,
+}
+
+impl Encoding {
+    fn from_name(name: &str) -> Option<Encoding> {
+        match name {
+            "csv" => Some(Encoding::Csv),
+            "base64" => Some(Encoding::Base64),
+            _ => None,
+        }
+    }
+}
+
+/// How base64 encoded tile data is compressed, see `DataFormat`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zlib,
+    /// Zstandard compression. (since Tiled 1.3)
+    Zstd,
+}
+
+impl Compression {
+    fn from_name(name: &str) -> Option<Compression> {
+        match name {
+            "gzip" => Some(Compression::Gzip),
+            "zlib" => Some(Compression::Zlib),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// The Tile Layer Format a layer was saved with, e.g. `Base64 (zlib compressed)`.
+///
+/// Without an encoding, tiles are stored as `<tile>` elements, which is deprecated and only supported by XML. The tiles of JSON and Lua layers without an encoding are stored as arrays of GIDs, which is what Tiled calls CSV for these formats.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DataFormat {
+    pub encoding: Option<Encoding>,
+    pub compression: Option<Compression>,
+}
+
+impl<'de> Deserialize<'de> for DataFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Attributes {
+            encoding: Option<String>,
+            compression: Option<String>,
+        }
+
+        /// The `<data>` element (XML) or the tile data itself (JSON), which only has attributes in XML.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Data {
+            Xml(Vec<Attributes>),
+            Json(serde::de::IgnoredAny),
+        }
+
+        #[derive(Deserialize)]
+        struct Layer {
+            #[serde(flatten)]
+            attributes: Attributes,
+            data: Option<Data>,
+        }
+
+        let layer = Layer::deserialize(deserializer)?;
+        let (attributes, xml) = match layer.data {
+            Some(Data::Xml(data)) => (data.into_iter().next().unwrap_or(layer.attributes), true),
+            _ => (layer.attributes, false),
+        };
+        let encoding = attributes.encoding.as_deref().and_then(Encoding::from_name);
+
+        // Invalid names are reported when the data is decoded.
+        Ok(DataFormat {
+            // Arrays of GIDs (JSON) and tables (Lua) are what Tiled calls CSV.
+            encoding: match encoding {
+                None if !xml => Some(Encoding::Csv),
+                encoding => encoding,
+            },
+            compression: attributes
+                .compression
+                .as_deref()
+                .and_then(Compression::from_name),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Payload {
+    /// Tile data as written in the file, still encoded (and compressed).
+    Encoded(String),
+    /// Tile data that was stored unencoded.
+    Tiles(Arc<Vec<Tile>>),
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct EncodedChunk {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) payload: Payload,
+}
+
+impl EncodedChunk {
+    fn region(&self) -> Region {
+        Region {
+            x: self.x as i32,
+            y: self.y as i32,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// The most recently used decoded chunks, keyed by chunk index.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct Decoded {
+    clock: u64,
+    chunks: HashMap<usize, (u64, Arc<Vec<Tile>>)>,
+}
+
+/// The chunks of an infinite map's layer, kept encoded and decoded on demand (see `ParseOptions::lazy_chunks`).
+///
+/// At most `capacity` decoded chunks are kept, the least recently used ones are dropped first.
+///
+/// Only created with the `std` feature, which the decoded chunks are kept with. Without it, the chunks can still be decoded with `to_chunks`.
+pub struct LazyChunks {
+    pub(crate) encoding: Option<String>,
+    pub(crate) compression: Option<String>,
+    pub(crate) chunks: Vec<EncodedChunk>,
+    pub(crate) capacity: usize,
+    pub(crate) limits: ParseLimits,
+    #[cfg(feature = "std")]
+    decoded: Mutex<Decoded>,
+}
+
+impl LazyChunks {
+    #[cfg(feature = "std")]
+    pub(crate) fn new(
+        encoding: Option<String>,
+        compression: Option<String>,
+        chunks: Vec<EncodedChunk>,
+        capacity: usize,
+        limits: ParseLimits,
+    ) -> LazyChunks {
+        LazyChunks {
+            encoding,
+            compression,
+            chunks,
+            capacity,
+            limits,
+            decoded: Mutex::default(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn decoded(&self) -> MutexGuard<'_, Decoded> {
+        // The cache is never left in an inconsistent state, so a panic while holding the lock can be ignored.
+        self.decoded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn decode_chunk(&self, index: usize) -> Result<Arc<Vec<Tile>>, Error> {
+        let chunk = &self.chunks[index];
+
+        match &chunk.payload {
+            Payload::Encoded(data) => decode_tile_data(
+                self.encoding.as_deref(),
+                self.compression.as_deref(),
+                data,
+                &self.limits,
+            )
+            .map(Arc::new)
+            .map_err(|error| Error::At {
+                location: Location {
+                    path: format!("chunk[{}]", index),
+                    ..Location::default()
+                },
+                source: Box::new(error),
+            }),
+            Payload::Tiles(tiles) => Ok(tiles.clone()),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn get(&self, index: usize) -> Result<Arc<Vec<Tile>>, Error> {
+        {
+            let mut decoded = self.decoded();
+            decoded.clock += 1;
+            let clock = decoded.clock;
+
+            if let Some((used, tiles)) = decoded.chunks.get_mut(&index) {
+                *used = clock;
+                return Ok(tiles.clone());
+            }
+        }
+
+        // The lock isn't held while decoding, two threads requesting the same chunk at once may both decode it.
+        let tiles = self.decode_chunk(index)?;
+
+        if self.capacity > 0 {
+            let mut decoded = self.decoded();
+
+            while decoded.chunks.len() >= self.capacity {
+                let oldest = decoded
+                    .chunks
+                    .iter()
+                    .min_by_key(|(_, (used, _))| *used)
+                    .map(|(index, _)| *index);
+
+                match oldest {
+                    Some(oldest) => decoded.chunks.remove(&oldest),
+                    None => break,
+                };
+            }
+
+            let clock = decoded.clock;
+            decoded.chunks.insert(index, (clock, tiles.clone()));
+        }
+
+        Ok(tiles)
+    }
+
+    /// The maximum number of decoded chunks that are kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of decoded chunks that are currently kept.
+    #[cfg(feature = "std")]
+    pub fn decoded_len(&self) -> usize {
+        self.decoded().chunks.len()
+    }
+
+    /// The number of chunks.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// The area covered by each chunk, in tile coordinates.
+    pub fn regions(&self) -> impl Iterator<Item = Region> + '_ {
+        self.chunks.iter().map(EncodedChunk::region)
+    }
+
+    /// Returns the tiles in `region`, row by row, decoding the chunks that overlap it as needed.
+    ///
+    /// Tiles that aren't covered by any chunk are empty (GID 0).
+    #[cfg(feature = "std")]
+    pub fn tiles(&self, region: Region) -> Result<Vec<Tile>, Error> {
+        let mut tiles = vec![Tile { gid: 0 }; region.width as usize * region.height as usize];
+        let left = i64::from(region.x);
+        let top = i64::from(region.y);
+        let right = left + i64::from(region.width);
+        let bottom = top + i64::from(region.height);
+
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let chunk_left = i64::from(chunk.x);
+            let chunk_top = i64::from(chunk.y);
+            let chunk_right = chunk_left + i64::from(chunk.width);
+            let chunk_bottom = chunk_top + i64::from(chunk.height);
+
+            if chunk_right <= left
+                || chunk_left >= right
+                || chunk_bottom <= top
+                || chunk_top >= bottom
+            {
+                continue;
+            }
+
+            let data = self.get(index)?;
+
+            for y in top.max(chunk_top)..bottom.min(chunk_bottom) {
+                for x in left.max(chunk_left)..right.min(chunk_right) {
+                    let source = (y - chunk_top) * i64::from(chunk.width) + (x - chunk_left);
+                    let target = (y - top) * i64::from(region.width) + (x - left);
+
+                    if let Some(tile) = data.get(source as usize) {
+                        tiles[target as usize] = *tile;
+                    }
+                }
+            }
+        }
+
+        Ok(tiles)
+    }
+
+    /// Returns the tile at the given tile coordinates.
+    #[cfg(feature = "std")]
+    pub fn tile(&self, x: i32, y: i32) -> Result<Tile, Error> {
+        let region = Region {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+
+        Ok(self.tiles(region)?[0])
+    }
+
+    /// Decodes every chunk, without keeping them.
+    pub fn to_chunks(&self) -> Result<Vec<Chunk>, Error> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                Ok(Chunk {
+                    x: chunk.x,
+                    y: chunk.y,
+                    width: chunk.width,
+                    height: chunk.height,
+                    data: self.decode_chunk(index)?.as_ref().clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Clone for LazyChunks {
+    fn clone(&self) -> LazyChunks {
+        LazyChunks {
+            encoding: self.encoding.clone(),
+            compression: self.compression.clone(),
+            chunks: self.chunks.clone(),
+            capacity: self.capacity,
+            limits: self.limits,
+            #[cfg(feature = "std")]
+            decoded: Mutex::default(),
+        }
+    }
+}
+
+impl fmt::Debug for LazyChunks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyChunks")
+            .field("encoding", &self.encoding)
+            .field("compression", &self.compression)
+            .field("chunks", &self.chunks.len())
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl PartialEq for LazyChunks {
+    fn eq(&self, other: &LazyChunks) -> bool {
+        self.encoding == other.encoding
+            && self.compression == other.compression
+            && self.chunks == other.chunks
+    }
+}
+
+impl Eq for LazyChunks {}
+
+impl Hash for LazyChunks {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.encoding.hash(state);
+        self.compression.hash(state);
+        self.chunks.hash(state);
+    }
+}
+
+/// Tile data that has been read but not decoded yet.
+enum Pending {
+    Decoded(LayerData),
+    Encoded {
+        encoding: Option<String>,
+        compression: Option<String>,
+        data: String,
+        limits: ParseLimits,
+        compact: bool,
+    },
+    Chunks {
+        encoding: Option<String>,
+        compression: Option<String>,
+        chunks: Vec<EncodedChunk>,
+        limits: ParseLimits,
+        parallel: bool,
+    },
+}
+
+impl Pending {
+    fn tiles(tiles: Vec<Tile>) -> Pending {
+        Pending::Decoded(LayerData::from_tiles(
+            tiles,
+            options::current().compact_tiles,
+        ))
+    }
+
+    /// Decodes the tile data, returning the index of the chunk that failed along with the error.
+    fn decode(self) -> Result<LayerData, (Option<usize>, Error)> {
+        match self {
+            Pending::Decoded(data) => Ok(data),
+            Pending::Encoded {
+                encoding,
+                compression,
+                data,
+                limits,
+                compact,
+            } => decode_tile_data(encoding.as_deref(), compression.as_deref(), &data, &limits)
+                .map(|tiles| LayerData::from_tiles(tiles, compact))
+                .map_err(|error| (None, error)),
+            Pending::Chunks {
+                encoding,
+                compression,
+                chunks,
+                limits,
+                parallel,
+            } => decode_chunks(
+                encoding.as_deref(),
+                compression.as_deref(),
+                chunks,
+                limits,
+                parallel,
+            )
+            .map(LayerData::Chunks)
+            .map_err(|(index, error)| (Some(index), error)),
+        }
+    }
+}
+
+/// Prepares the chunks of a layer for decoding, unless chunks should be decoded lazily.
+///
+/// When decoding lazily, the decompressed bytes limit applies to each chunk rather than to the whole layer.
+fn chunks_data(
+    encoding: Option<String>,
+    compression: Option<String>,
+    chunks: Vec<EncodedChunk>,
+) -> Result<Pending, Error> {
+    let options = options::current();
+    let limits = options.limits;
+
+    let area = chunks.iter().try_fold(0i64, |area, chunk| {
+        area.checked_add(i64::from(chunk.width) * i64::from(chunk.height))
+    });
+    if let Some(limit) = limits.tiles_exceeded(area.unwrap_or(i64::MAX), 1) {
+        return Err(Error::LimitExceeded(limit));
+    }
+
+    #[cfg(feature = "std")]
+    if let Some(capacity) = options.lazy_chunks {
+        return Ok(Pending::Decoded(LayerData::LazyChunks(LazyChunks::new(
+            encoding,
+            compression,
+            chunks,
+            capacity,
+            limits,
+        ))));
+    }
+
+    Ok(Pending::Chunks {
+        encoding,
+        compression,
+        chunks,
+        limits,
+        parallel: options.parallel(),
+    })
+}
+
+/// The share of a layer's limits given to a chunk decoded in parallel: the tiles it covers, when the layer has any limits.
+fn chunk_share(chunk: &EncodedChunk, limits: &ParseLimits) -> ParseLimits {
+    let tiles = (chunk.width as usize).saturating_mul(chunk.height as usize);
+    let limited = limits.max_decompressed_bytes.is_some() || limits.max_tiles.is_some();
+
+    ParseLimits {
+        max_decompressed_bytes: limited.then(|| tiles.saturating_mul(4)),
+        max_tiles: limited.then_some(tiles),
+        ..*limits
+    }
+}
+
+/// Decodes the encoded chunks on the rayon thread pool, each against its share of the limits of the layer (see `chunk_share`).
+#[cfg(feature = "rayon")]
+fn decode_parallel(
+    encoding: Option<&str>,
+    compression: Option<&str>,
+    chunks: &[EncodedChunk],
+    limits: &ParseLimits,
+) -> Vec<Result<Vec<Tile>, Error>> {
+    use rayon::prelude::*;
+
+    chunks
+        .par_iter()
+        .filter_map(|chunk| match &chunk.payload {
+            Payload::Encoded(data) => Some(decode_tile_data(
+                encoding,
+                compression,
+                data,
+                &chunk_share(chunk, limits),
+            )),
+            Payload::Tiles(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn decode_parallel(
+    _: Option<&str>,
+    _: Option<&str>,
+    _: &[EncodedChunk],
+    _: &ParseLimits,
+) -> Vec<Result<Vec<Tile>, Error>> {
+    Vec::new()
+}
+
+/// Decodes the chunks of a layer, returning the index of the chunk that failed along with the error.
+///
+/// The chunks share the limits of the layer. They are only decoded in parallel if the layer's limits can be split between them, giving each chunk the tiles it covers, so that all of them together stay within the limits. A chunk holding more data than it covers exceeds its share and is decoded again with what is left of the layer's limits, as it would have been sequentially.
+fn decode_chunks(
+    encoding: Option<&str>,
+    compression: Option<&str>,
+    chunks: Vec<EncodedChunk>,
+    mut limits: ParseLimits,
+    parallel: bool,
+) -> Result<Vec<Chunk>, (usize, Error)> {
+    let split = || {
+        let total = |share: fn(ParseLimits) -> Option<usize>| {
+            chunks
+                .iter()
+                .filter(|chunk| matches!(chunk.payload, Payload::Encoded(_)))
+                .try_fold(0usize, |total, chunk| {
+                    total.checked_add(share(chunk_share(chunk, &limits)).unwrap_or(0))
+                })
+        };
+        let within = |max: Option<usize>, total: Option<usize>| match max {
+            Some(max) => matches!(total, Some(total) if total <= max),
+            None => true,
+        };
+
+        within(
+            limits.max_decompressed_bytes,
+            total(|share| share.max_decompressed_bytes),
+        ) && within(limits.max_tiles, total(|share| share.max_tiles))
+    };
+    let mut decoded = if parallel && split() {
+        decode_parallel(encoding, compression, &chunks, &limits)
+    } else {
+        Vec::new()
+    }
+    .into_iter();
+
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let data = match chunk.payload {
+                Payload::Encoded(data) => {
+                    let data = match decoded.next() {
+                        Some(Err(Error::LimitExceeded(_))) | None => {
+                            decode_tile_data(encoding, compression, &data, &limits)
+                        }
+                        Some(result) => result,
+                    }
+                    .map_err(|error| (index, error))?;
+
+                    // The remaining chunks share what is left of the layer's limits.
+                    let remaining = |max: usize, used: usize| max.saturating_sub(used);
+                    limits.max_decompressed_bytes = limits
+                        .max_decompressed_bytes
+                        .map(|max| remaining(max, data.len() * 4));
+                    limits.max_tiles = limits.max_tiles.map(|max| remaining(max, data.len()));
+
+                    data
+                }
+                Payload::Tiles(tiles) => {
+                    Arc::try_unwrap(tiles).unwrap_or_else(|tiles| (*tiles).clone())
+                }
+            };
+
+            Ok(Chunk {
+                x: chunk.x,
+                y: chunk.y,
+                width: chunk.width,
+                height: chunk.height,
+                data,
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "rayon")]
+thread_local! {
+    /// The tile data of the layers read so far, along with where it was read, while decoding is deferred by `Deferred`.
+    static DEFERRED: std::cell::RefCell<Option<Vec<(context::Mark, Pending)>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Defers decoding the tile data of the layers that are read while it is alive, so that they can all be decoded in parallel by `finish`.
+#[cfg(feature = "rayon")]
+pub(crate) struct Deferred(());
+
+#[cfg(feature = "rayon")]
+impl Deferred {
+    pub(crate) fn start() -> Deferred {
+        DEFERRED.with(|deferred| *deferred.borrow_mut() = Some(Vec::new()));
+        Deferred(())
+    }
+
+    fn take() -> Vec<(context::Mark, Pending)> {
+        DEFERRED
+            .with(|deferred| deferred.borrow_mut().take())
+            .unwrap_or_default()
+    }
+
+    /// Reports a decoding error where the tile data was read, as if it had been decoded then.
+    fn fail((mut mark, (chunk, error)): (context::Mark, (Option<usize>, Error))) -> String {
+        if let Some(index) = chunk {
+            mark.enter("chunk", Some(index));
+        }
+
+        context::fail_at(mark, error)
+    }
+
+    /// Decodes the tile data of the layers, in the order they were read, failing with the first error.
+    pub(crate) fn finish<'a, I>(self, layers: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = &'a mut LayerData>,
+    {
+        use rayon::prelude::*;
+
+        let decoded = Deferred::take()
+            .into_par_iter()
+            .map(|(mark, pending)| pending.decode().map_err(|error| (mark, error)))
+            .collect::<Vec<_>>();
+
+        for (data, decoded) in layers.into_iter().zip(decoded) {
+            *data = decoded.map_err(Deferred::fail)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the tile data read before parsing failed, returning the first decoding error, which would have been found before the failure without deferring.
+    pub(crate) fn first_error(self) -> Option<String> {
+        use rayon::prelude::*;
+
+        Deferred::take()
+            .into_par_iter()
+            .find_map_first(|(mark, pending)| pending.decode().err().map(|error| (mark, error)))
+            .map(Deferred::fail)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        DEFERRED.with(|deferred| *deferred.borrow_mut() = None);
+    }
+}
+
+/// Keeps `pending` to be decoded later if decoding is deferred, otherwise returns it.
+#[cfg(feature = "rayon")]
+fn defer(pending: Pending) -> Option<Pending> {
+    DEFERRED.with(|deferred| match deferred.borrow_mut().as_mut() {
+        Some(deferred) => {
+            deferred.push((context::mark(), pending));
+            None
+        }
+        None => Some(pending),
+    })
+}
+
+#[cfg(not(feature = "rayon"))]
+fn defer(pending: Pending) -> Option<Pending> {
+    Some(pending)
+}
+
+/// The tiles of a layer.
+///
+/// More ways of storing them may be added as parsing options, so matches need a wildcard arm.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
 pub enum LayerData {
     Tiles(Vec<Tile>),
     Chunks(Vec<Chunk>),
+    /// Chunks that are decoded on demand, only used when parsing with `ParseOptions::lazy_chunks` and the `std` feature.
+    LazyChunks(LazyChunks),
+    /// Tiles packed into smaller cells, only used when parsing with `ParseOptions::compact_tiles`.
+    CompactTiles(CompactTiles),
+}
+
+impl LayerData {
+    fn from_tiles(tiles: Vec<Tile>, compact: bool) -> LayerData {
+        if compact {
+            LayerData::CompactTiles(CompactTiles::new(&tiles))
+        } else {
+            LayerData::Tiles(tiles)
+        }
+    }
 }
 
 impl<'de> Deserialize<'de> for LayerData {
@@ -149,7 +1052,7 @@ impl<'de> Deserialize<'de> for LayerData {
     where
         D: Deserializer<'de>,
     {
-        use std::convert::TryInto;
+        use core::convert::TryInto;
 
         #[derive(Deserialize)]
         #[serde(untagged)]
@@ -211,13 +1114,13 @@ impl<'de> Deserialize<'de> for LayerData {
         #[derive(Deserialize)]
         #[serde(untagged)]
         enum Data {
-            XML {
+            Xml {
                 data: Vec<XMLTileData>,
             },
             XMLChunks {
                 data: Vec<XMLChunks>,
             },
-            JSON {
+            Json {
                 encoding: Option<String>,
                 compression: Option<String>,
                 data: JSONTileData,
@@ -229,60 +1132,74 @@ impl<'de> Deserialize<'de> for LayerData {
             },
         }
 
-        impl TryInto<LayerData> for Data {
-            type Error = String;
+        impl TryInto<Pending> for Data {
+            type Error = Error;
 
-            fn try_into(self) -> Result<LayerData, Self::Error> {
+            fn try_into(self) -> Result<Pending, Self::Error> {
                 match self {
-                    Data::XML { mut data } => match data.remove(0) {
-                        XMLTileData::Data {
+                    // An empty `data` array is a layer without tiles (JSON) rather than a missing element (XML).
+                    Data::Xml { data } => match data.into_iter().next() {
+                        None => Ok(Pending::tiles(Vec::new())),
+                        Some(XMLTileData::Data {
                             encoding,
                             compression,
                             data,
-                        } => decode_tile_data(encoding, compression, data).map(LayerData::Tiles),
-                        XMLTileData::Tiles { tiles } => Ok(LayerData::Tiles(tiles)),
+                        }) => Ok(Pending::Encoded {
+                            encoding,
+                            compression,
+                            data,
+                            limits: options::current().limits,
+                            compact: options::current().compact_tiles,
+                        }),
+                        Some(XMLTileData::Tiles { tiles }) => {
+                            check_tiles(&tiles, &options::current().limits)?;
+                            Ok(Pending::tiles(tiles))
+                        }
                     },
-                    Data::XMLChunks { mut data, .. } => {
+                    Data::XMLChunks { data, .. } => {
                         let XMLChunks {
                             encoding,
                             compression,
                             chunks,
-                        } = data.remove(0);
+                        } = match data.into_iter().next() {
+                            Some(data) => data,
+                            None => return Ok(Pending::Decoded(LayerData::Chunks(Vec::new()))),
+                        };
                         let chunks = chunks
                             .into_iter()
-                            .map(|chunk| {
-                                let data = match chunk.data {
-                                    XMLTileData::Data { data, .. } => decode_tile_data(
-                                        encoding.clone(),
-                                        compression.clone(),
-                                        data,
-                                    ),
-                                    XMLTileData::Tiles { tiles } => Ok(tiles),
-                                }?;
-
-                                Ok(Chunk {
-                                    x: chunk.x,
-                                    y: chunk.y,
-                                    width: chunk.width,
-                                    height: chunk.height,
-                                    data,
-                                })
+                            .map(|chunk| EncodedChunk {
+                                x: chunk.x,
+                                y: chunk.y,
+                                width: chunk.width,
+                                height: chunk.height,
+                                payload: match chunk.data {
+                                    XMLTileData::Data { data, .. } => Payload::Encoded(data),
+                                    XMLTileData::Tiles { tiles } => Payload::Tiles(Arc::new(tiles)),
+                                },
                             })
-                            .collect::<Result<Vec<_>, String>>()?;
+                            .collect();
 
-                        Ok(LayerData::Chunks(chunks))
+                        chunks_data(encoding, compression, chunks)
                     }
-                    Data::JSON {
+                    Data::Json {
                         data: JSONTileData::Vec(gids),
                         ..
-                    } => Ok(LayerData::Tiles(
-                        gids.into_iter().map(|gid| Tile { gid }).collect(),
-                    )),
-                    Data::JSON {
+                    } => {
+                        let tiles = gids.into_iter().map(|gid| Tile { gid }).collect::<Vec<_>>();
+                        check_tiles(&tiles, &options::current().limits)?;
+                        Ok(Pending::tiles(tiles))
+                    }
+                    Data::Json {
                         encoding,
                         compression,
                         data: JSONTileData::String(data),
-                    } => decode_tile_data(encoding, compression, data).map(LayerData::Tiles),
+                    } => Ok(Pending::Encoded {
+                        encoding,
+                        compression,
+                        data,
+                        limits: options::current().limits,
+                        compact: options::current().compact_tiles,
+                    }),
                     Data::JSONChunks {
                         encoding,
                         compression,
@@ -290,51 +1207,92 @@ impl<'de> Deserialize<'de> for LayerData {
                     } => {
                         let chunks = chunks
                             .into_iter()
-                            .map(|chunk| {
-                                let data = match chunk.data {
-                                    JSONTileData::Vec(gids) => {
-                                        Ok(gids.into_iter().map(|gid| Tile { gid }).collect())
-                                    }
-                                    JSONTileData::String(data) => decode_tile_data(
-                                        encoding.clone(),
-                                        compression.clone(),
-                                        data,
-                                    ),
-                                }?;
-
-                                Ok(Chunk {
-                                    x: chunk.x,
-                                    y: chunk.y,
-                                    width: chunk.width,
-                                    height: chunk.height,
-                                    data,
-                                })
+                            .map(|chunk| EncodedChunk {
+                                x: chunk.x,
+                                y: chunk.y,
+                                width: chunk.width,
+                                height: chunk.height,
+                                payload: match chunk.data {
+                                    JSONTileData::Vec(gids) => Payload::Tiles(Arc::new(
+                                        gids.into_iter().map(|gid| Tile { gid }).collect(),
+                                    )),
+                                    JSONTileData::String(data) => Payload::Encoded(data),
+                                },
                             })
-                            .collect::<Result<Vec<_>, String>>()?;
+                            .collect();
 
-                        Ok(LayerData::Chunks(chunks))
+                        chunks_data(encoding, compression, chunks)
                     }
                 }
             }
         }
 
+        let scope = context::enter("data", None);
         let data = Data::deserialize(deserializer)?;
-        data.try_into().map_err(serde::de::Error::custom)
+        let pending: Pending = data
+            .try_into()
+            .map_err(|e| serde::de::Error::custom(context::fail(e)))?;
+        let data = match defer(pending) {
+            Some(pending) => pending.decode().map_err(|(chunk, e)| {
+                if let Some(index) = chunk {
+                    // Left on the path to report where decoding failed.
+                    let _ = context::enter("chunk", Some(index));
+                }
+
+                serde::de::Error::custom(context::fail(e))
+            })?,
+            // Decoded along with the other layers once they have all been read.
+            None => LayerData::Tiles(Vec::new()),
+        };
+        scope.exit();
+
+        Ok(data)
     }
 }
 
-fn default_visible() -> bool {
+pub(crate) fn default_visible() -> bool {
     true
 }
 
-fn default_opacity() -> f64 {
+pub(crate) fn default_opacity() -> f64 {
     1.0
 }
 
+/// Deserializes the ID of a layer, identifying it by its ID in the location of errors.
+pub(crate) fn deserialize_id<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let id = deserialize_number_from_string(deserializer)?;
+    context::set_id(id);
+
+    Ok(id)
+}
+
+#[cfg(feature = "preserve-unknown")]
+fn deserialize_extra<'de, D>(deserializer: D) -> Result<crate::extra::Extra, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // `startx` and `starty` (JSON) are where the chunks start, which is written from the chunks themselves.
+    crate::extra::deserialize(
+        deserializer,
+        &[
+            "type",
+            "data",
+            "encoding",
+            "compression",
+            "chunks",
+            "startx",
+            "starty",
+        ],
+    )
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct Layer {
     /// Unique ID of the layer. Each layer that added to a map gets a unique id. Even if a layer is deleted, no layer ever gets the same ID. Can not be changed in Tiled. (since Tiled 1.2)
-    #[serde(deserialize_with = "deserialize_number_from_string")]
+    #[serde(deserialize_with = "deserialize_id")]
     pub id: u32,
     /// The name of the layer.
     #[serde(default)]
@@ -366,4 +1324,11 @@ pub struct Layer {
     pub offset_y: f64,
     #[serde(flatten)]
     pub data: LayerData,
+    /// The Tile Layer Format the data was saved with.
+    #[serde(flatten)]
+    pub format: DataFormat,
+    /// Attributes and child elements that are not otherwise modelled.
+    #[cfg(feature = "preserve-unknown")]
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    pub extra: crate::extra::Extra,
 }
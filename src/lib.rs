@@ -1,14 +1,39 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(unknown_lints)]
 #![warn(clippy::all)]
 
+extern crate alloc;
+
+mod context;
+mod de;
+#[cfg(feature = "lua")]
+mod lua;
+mod writer;
 #[cfg(feature = "xml")]
-mod to_json;
+mod xml;
 
+#[cfg(feature = "binary")]
+pub mod binary;
+pub mod borrowed;
+#[cfg(feature = "std")]
+pub mod cache;
+pub mod compat;
+pub mod diff;
 pub mod error;
+#[cfg(feature = "preserve-unknown")]
+pub mod extra;
+pub mod format;
 pub mod layer;
+#[cfg(feature = "std")]
+pub mod loader;
 pub mod map;
 pub mod metadata;
+pub mod options;
 pub mod tileset;
+#[cfg(feature = "watch")]
+pub mod watch;
 
+pub use format::Format;
 pub use map::Map;
+pub use options::ParseOptions;
 pub use tileset::Tileset;
@@ -0,0 +1,288 @@
+use crate::error::Error;
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Component, Path, PathBuf},
+};
+#[cfg(feature = "async")]
+use std::{future::Future, pin::Pin};
+
+/// A boxed future returned by `AsyncResourceReader`.
+#[cfg(feature = "async")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Loads the files referenced by maps and tilesets, such as external tilesets.
+///
+/// Object templates are not loaded through it, see `Map::load_with`.
+pub trait ResourceLoader {
+    /// Reads the contents of the file at `path`.
+    fn load(&self, path: &Path) -> Result<Vec<u8>, Error>;
+
+    /// Resolves `reference`, as written in the file at `base`, to the path of the referenced file.
+    ///
+    /// The default implementation treats `reference` as relative to the directory containing `base`.
+    fn resolve(&self, base: &Path, reference: &str) -> Result<PathBuf, Error> {
+        let parent = base.parent().unwrap_or_else(|| Path::new(""));
+        Ok(normalize(&parent.join(reference)))
+    }
+
+    /// Returns the canonical form of `path`, which identifies the file when caching (see `TilesetCache`).
+    ///
+    /// The default implementation normalizes the path lexically.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        Ok(normalize(path))
+    }
+}
+
+impl<L: ResourceLoader + ?Sized> ResourceLoader for &L {
+    fn load(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        (**self).load(path)
+    }
+
+    fn resolve(&self, base: &Path, reference: &str) -> Result<PathBuf, Error> {
+        (**self).resolve(base, reference)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        (**self).canonicalize(path)
+    }
+}
+
+/// Reads the files referenced by maps and tilesets asynchronously, such as external tilesets.
+///
+/// The returned futures are not tied to any particular runtime.
+#[cfg(feature = "async")]
+pub trait AsyncResourceReader: Sync {
+    /// Reads the contents of the file at `path`.
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, Error>>;
+
+    /// Resolves `reference`, as written in the file at `base`, to the path of the referenced file.
+    ///
+    /// The default implementation treats `reference` as relative to the directory containing `base`.
+    fn resolve(&self, base: &Path, reference: &str) -> Result<PathBuf, Error> {
+        let parent = base.parent().unwrap_or_else(|| Path::new(""));
+        Ok(normalize(&parent.join(reference)))
+    }
+
+    /// Returns the canonical form of `path`, which identifies the file when caching (see `TilesetCache`).
+    ///
+    /// The default implementation normalizes the path lexically, like `ResourceLoader::canonicalize`, so that a cache can be shared between maps loaded either way.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        Ok(normalize(path))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncResourceReader + ?Sized> AsyncResourceReader for &R {
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, Error>> {
+        (**self).read(path)
+    }
+
+    fn resolve(&self, base: &Path, reference: &str) -> Result<PathBuf, Error> {
+        (**self).resolve(base, reference)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        (**self).canonicalize(path)
+    }
+}
+
+/// Normalizes a path lexically, removing `.` components and `..` components that follow a normal component.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Loads files from the filesystem.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileSystemLoader;
+
+impl ResourceLoader for FileSystemLoader {
+    fn load(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        std::fs::read(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        std::fs::canonicalize(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Loads files from memory, e.g. for tests or assets that are embedded in the executable.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLoader {
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> MemoryLoader {
+        MemoryLoader::default()
+    }
+
+    /// Adds a file, replacing any existing file with the same (normalized) path.
+    pub fn insert<P: AsRef<Path>, B: Into<Vec<u8>>>(&mut self, path: P, data: B) {
+        self.files.insert(normalize(path.as_ref()), data.into());
+    }
+
+    /// Adds a file, returning the loader so calls can be chained.
+    pub fn with<P: AsRef<Path>, B: Into<Vec<u8>>>(mut self, path: P, data: B) -> MemoryLoader {
+        self.insert(path, data);
+        self
+    }
+
+    /// Removes a file, returning its contents.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Option<Vec<u8>> {
+        self.files.remove(&normalize(path.as_ref()))
+    }
+}
+
+impl ResourceLoader for MemoryLoader {
+    fn load(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| Error::Io {
+                path: path.to_path_buf(),
+                source: io::Error::new(io::ErrorKind::NotFound, "file not found"),
+            })
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncResourceReader for MemoryLoader {
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, Error>> {
+        Box::pin(futures_util::future::ready(self.load(path)))
+    }
+}
+
+/// Confines all files loaded through `inner` to the `root` directory, e.g. for maps received from untrusted sources.
+///
+/// Absolute references, references that escape `root` with `..` and paths that escape `root` through symbolic links all fail with `Error::PathEscape` without being read. Paths passed to the loader (including the map itself) must be given in the same form as `root`, i.e. both relative or both absolute.
+#[derive(Clone, Debug)]
+pub struct Sandbox<L> {
+    inner: L,
+    root: PathBuf,
+}
+
+impl<L> Sandbox<L> {
+    pub fn new<P: AsRef<Path>>(inner: L, root: P) -> Sandbox<L> {
+        Sandbox {
+            inner,
+            root: normalize(root.as_ref()),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+
+    /// Checks that `path` is inside the root directory, returning its normalized form.
+    pub fn check(&self, path: &Path) -> Result<PathBuf, Error> {
+        let normalized = normalize(path);
+        let escape = || Error::PathEscape {
+            path: path.to_path_buf(),
+        };
+
+        if !normalized.starts_with(&self.root)
+            || normalized
+                .components()
+                .any(|component| component == Component::ParentDir)
+        {
+            return Err(escape());
+        }
+
+        // Files that don't exist on disk (e.g. with an in-memory loader) can't be symbolic links.
+        let root = if self.root.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.root
+        };
+
+        if let (Ok(root), Ok(canonical)) = (
+            std::fs::canonicalize(root),
+            std::fs::canonicalize(&normalized),
+        ) {
+            if !canonical.starts_with(root) {
+                return Err(escape());
+            }
+        }
+
+        Ok(normalized)
+    }
+
+    fn check_reference(&self, reference: &str) -> Result<(), Error> {
+        let path = Path::new(reference);
+
+        if path.has_root() || path.is_absolute() {
+            return Err(Error::PathEscape {
+                path: path.to_path_buf(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<L: ResourceLoader> ResourceLoader for Sandbox<L> {
+    fn load(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        let path = self.check(path)?;
+        self.inner.load(&path)
+    }
+
+    fn resolve(&self, base: &Path, reference: &str) -> Result<PathBuf, Error> {
+        self.check_reference(reference)?;
+        let path = self.inner.resolve(base, reference)?;
+        self.check(&path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        let path = self.check(path)?;
+        self.inner.canonicalize(&path)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: AsyncResourceReader> AsyncResourceReader for Sandbox<R> {
+    fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, Error>> {
+        match self.check(path) {
+            Ok(path) => Box::pin(async move { self.inner.read(&path).await }),
+            Err(e) => Box::pin(futures_util::future::ready(Err(e))),
+        }
+    }
+
+    fn resolve(&self, base: &Path, reference: &str) -> Result<PathBuf, Error> {
+        self.check_reference(reference)?;
+        let path = self.inner.resolve(base, reference)?;
+        self.check(&path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, Error> {
+        let path = self.check(path)?;
+        self.inner.canonicalize(&path)
+    }
+}
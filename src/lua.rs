@@ -0,0 +1,373 @@
+use crate::options::{self, ParseLimits};
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use serde_json::{Map, Number, Value};
+
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unexpected character {0:?} at byte {1}")]
+    UnexpectedChar(char, usize),
+    #[error("unexpected EOF")]
+    UnexpectedEof,
+    #[error("invalid number {0:?}")]
+    InvalidNumber(String),
+    #[error("invalid escape sequence at byte {0}")]
+    InvalidEscape(usize),
+    #[error("string at byte {0} is not valid UTF-8")]
+    InvalidUtf8(usize),
+    #[error("invalid table key at byte {0}")]
+    InvalidKey(usize),
+    #[error("tables nested too deeply")]
+    TooDeep,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    limits: ParseLimits,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(Error::UnexpectedChar(c, self.pos - c.len_utf8())),
+            None => Err(Error::UnexpectedEof),
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), Error> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if !trimmed.starts_with("--") {
+                return Ok(());
+            }
+
+            self.pos += 2;
+
+            if let Some(level) = self.long_bracket_level() {
+                self.long_string(level)?;
+            } else {
+                match self.rest().find('\n') {
+                    Some(end) => self.pos += end + 1,
+                    None => self.pos = self.input.len(),
+                }
+            }
+        }
+    }
+
+    /// Returns the level of a long bracket (`[[`, `[=[`, ...) starting at the current position.
+    fn long_bracket_level(&self) -> Option<usize> {
+        let rest = self.rest().strip_prefix('[')?;
+        let level = rest.len() - rest.trim_start_matches('=').len();
+
+        if rest[level..].starts_with('[') {
+            Some(level)
+        } else {
+            None
+        }
+    }
+
+    fn long_string(&mut self, level: usize) -> Result<String, Error> {
+        self.pos += level + 2;
+
+        let close = format!("]{}]", "=".repeat(level));
+        let end = self.rest().find(&close).ok_or(Error::UnexpectedEof)?;
+        let mut s = &self.rest()[..end];
+        self.pos += end + close.len();
+
+        // A newline immediately following the opening bracket is skipped.
+        if let Some(stripped) = s.strip_prefix("\r\n").or_else(|| s.strip_prefix('\n')) {
+            s = stripped;
+        }
+
+        Ok(s.to_string())
+    }
+
+    fn identifier(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        self.pos += end;
+        &rest[..end]
+    }
+
+    /// Parses a quoted string. Byte escapes (`\xNN`, `\ddd`) are bytes of the string rather than characters, so a UTF-8 sequence can be written as several of them.
+    fn string(&mut self, quote: char) -> Result<String, Error> {
+        let start = self.pos - 1;
+        let mut bytes = Vec::new();
+        fn push(bytes: &mut Vec<u8>, c: char) {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+
+        loop {
+            let c = self.bump().ok_or(Error::UnexpectedEof)?;
+
+            match c {
+                c if c == quote => {
+                    return String::from_utf8(bytes).map_err(|_| Error::InvalidUtf8(start))
+                }
+                '\n' => return Err(Error::UnexpectedChar(c, self.pos - 1)),
+                '\\' => {
+                    let start = self.pos - 1;
+                    let c = self.bump().ok_or(Error::UnexpectedEof)?;
+
+                    match c {
+                        'n' => bytes.push(b'\n'),
+                        't' => bytes.push(b'\t'),
+                        'r' => bytes.push(b'\r'),
+                        'a' => bytes.push(0x7),
+                        'b' => bytes.push(0x8),
+                        'f' => bytes.push(0xc),
+                        'v' => bytes.push(0xb),
+                        '\\' | '"' | '\'' | '\n' => push(&mut bytes, c),
+                        'x' => {
+                            let hex = self.rest().get(..2).ok_or(Error::InvalidEscape(start))?;
+                            let byte = u8::from_str_radix(hex, 16)
+                                .map_err(|_| Error::InvalidEscape(start))?;
+                            self.pos += 2;
+                            bytes.push(byte);
+                        }
+                        'u' => {
+                            self.expect('{')?;
+                            let end = self.rest().find('}').ok_or(Error::InvalidEscape(start))?;
+                            let code = u32::from_str_radix(&self.rest()[..end], 16)
+                                .map_err(|_| Error::InvalidEscape(start))?;
+                            self.pos += end + 1;
+                            push(
+                                &mut bytes,
+                                core::char::from_u32(code).ok_or(Error::InvalidEscape(start))?,
+                            );
+                        }
+                        'z' => {
+                            let rest = self.rest();
+                            self.pos += rest.len() - rest.trim_start().len();
+                        }
+                        c if c.is_ascii_digit() => {
+                            let rest = &self.input[self.pos - 1..];
+                            let len = rest
+                                .find(|c: char| !c.is_ascii_digit())
+                                .unwrap_or(rest.len())
+                                .min(3);
+                            let byte: u8 = rest[..len]
+                                .parse()
+                                .map_err(|_| Error::InvalidEscape(start))?;
+                            self.pos += len - 1;
+                            bytes.push(byte);
+                        }
+                        _ => return Err(Error::InvalidEscape(start)),
+                    }
+                }
+                c => push(&mut bytes, c),
+            }
+        }
+    }
+
+    fn number(&mut self, negative: bool) -> Result<Value, Error> {
+        let rest = self.rest();
+        let hex = rest.starts_with("0x") || rest.starts_with("0X");
+
+        let end = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                let exponent_sign = (c == '-' || c == '+')
+                    && !hex
+                    && matches!(rest[..i].chars().last(), Some('e') | Some('E'));
+                !(c.is_ascii_alphanumeric() || c == '.' || exponent_sign)
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        let literal = &rest[..end];
+        self.pos += end;
+
+        let invalid =
+            || Error::InvalidNumber(format!("{}{}", if negative { "-" } else { "" }, literal));
+
+        if hex {
+            let n = i64::from_str_radix(&literal[2..], 16).map_err(|_| invalid())?;
+            return Ok(Value::Number(Number::from(if negative { -n } else { n })));
+        }
+
+        if let Ok(n) = literal.parse::<u64>() {
+            if !negative {
+                return Ok(Value::Number(Number::from(n)));
+            }
+
+            if let Ok(n) = literal.parse::<i64>() {
+                return Ok(Value::Number(Number::from(-n)));
+            }
+        }
+
+        let f: f64 = literal.parse().map_err(|_| invalid())?;
+        let f = if negative { -f } else { f };
+        Number::from_f64(f).map(Value::Number).ok_or_else(invalid)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, Error> {
+        self.skip_whitespace()?;
+
+        match self.peek().ok_or(Error::UnexpectedEof)? {
+            '{' => self.table(depth + 1),
+            quote @ '"' | quote @ '\'' => {
+                self.pos += 1;
+                self.string(quote).map(Value::String)
+            }
+            '[' => match self.long_bracket_level() {
+                Some(level) => self.long_string(level).map(Value::String),
+                None => Err(Error::UnexpectedChar('[', self.pos)),
+            },
+            '-' => {
+                self.pos += 1;
+                self.number(true)
+            }
+            c if c.is_ascii_digit() || c == '.' => self.number(false),
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = self.pos;
+
+                match self.identifier() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "nil" => Ok(Value::Null),
+                    _ => Err(Error::UnexpectedChar(c, start)),
+                }
+            }
+            c => Err(Error::UnexpectedChar(c, self.pos)),
+        }
+    }
+
+    fn key(&mut self, depth: usize) -> Result<Option<String>, Error> {
+        let start = self.pos;
+
+        match self.peek() {
+            Some('[') if self.long_bracket_level().is_none() => {
+                self.pos += 1;
+
+                let key = match self.value(depth)? {
+                    Value::String(s) => s,
+                    Value::Number(n) => n.to_string(),
+                    _ => return Err(Error::InvalidKey(start)),
+                };
+
+                self.skip_whitespace()?;
+                self.expect(']')?;
+                self.skip_whitespace()?;
+                self.expect('=')?;
+
+                Ok(Some(key))
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.identifier();
+                self.skip_whitespace()?;
+
+                if self.peek() == Some('=') && !self.rest().starts_with("==") {
+                    self.pos += 1;
+                    Ok(Some(name.to_string()))
+                } else {
+                    self.pos = start;
+                    Ok(None)
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn table(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+
+        self.limits.check_depth(depth).map_err(|_| Error::TooDeep)?;
+
+        self.expect('{')?;
+
+        let mut array = Vec::new();
+        let mut map = Map::new();
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                break;
+            }
+
+            match self.key(depth)? {
+                Some(key) => {
+                    let value = self.value(depth)?;
+                    map.insert(key, value);
+                }
+                None => array.push(self.value(depth)?),
+            }
+
+            self.skip_whitespace()?;
+
+            match self.bump().ok_or(Error::UnexpectedEof)? {
+                ',' | ';' => {}
+                '}' => break,
+                c => return Err(Error::UnexpectedChar(c, self.pos - 1)),
+            }
+        }
+
+        if map.is_empty() {
+            return Ok(Value::Array(array));
+        }
+
+        // Positional entries in a keyed table are stored under their (1-based) Lua index.
+        for (i, value) in array.into_iter().enumerate() {
+            map.insert((i + 1).to_string(), value);
+        }
+
+        Ok(Value::Object(map))
+    }
+}
+
+/// Converts a Lua literal table, as written by Tiled's Lua export, into the equivalent JSON value.
+///
+/// Tables with only positional entries (including empty tables) become arrays, all other tables become objects.
+pub fn to_json(lua: &str) -> Result<Value, Error> {
+    let mut parser = Parser {
+        input: lua.trim_start_matches('\u{feff}'),
+        pos: 0,
+        limits: options::current().limits,
+    };
+
+    parser.skip_whitespace()?;
+
+    let start = parser.pos;
+
+    if parser.identifier() != "return" {
+        parser.pos = start;
+    }
+
+    let value = parser.value(0)?;
+    parser.skip_whitespace()?;
+
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(Error::UnexpectedChar(c, parser.pos)),
+    }
+}
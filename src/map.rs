@@ -1,8 +1,33 @@
-use crate::{error::Error, layer, metadata, tileset};
+#[cfg(feature = "std")]
+use crate::{
+    cache::TilesetCache,
+    loader::{FileSystemLoader, ResourceLoader},
+};
+use crate::{
+    context,
+    de::{deserialize_bool_from_anything, deserialize_number_from_string, Intercept, Merged},
+    error::Error,
+    format::Format,
+    layer, metadata,
+    options::{self, Limit, ParseOptions, Warning},
+    tileset,
+};
 
+#[cfg(feature = "async")]
+use crate::loader::AsyncResourceReader;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use serde::Deserialize;
-use serde_aux::field_attributes::deserialize_bool_from_anything;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_json::Value;
+#[cfg(feature = "std")]
+use std::{
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
 
 /// For staggered and hexagonal maps, determines which axis (“x” or “y”) is staggered.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
@@ -21,9 +46,10 @@ pub enum StaggerIndex {
 }
 
 /// Map orientation. Tiled supports “orthogonal”, “isometric”, “staggered” and “hexagonal”.
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq)]
 #[serde(rename_all = "lowercase", tag = "orientation")]
 pub enum Orientation {
+    #[default]
     Orthogonal,
     Isometric,
     Staggered {
@@ -50,9 +76,10 @@ pub enum Orientation {
 
 /// The order in which tiles on tile layers are rendered. Valid values are right-down (the default), right-up, left-down and left-up. In all cases, the map is drawn row-by-row. (only supported for orthogonal maps at the moment)
 
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum RenderOrder {
+    #[default]
     RightDown,
     RightUp,
     LeftDown,
@@ -71,38 +98,404 @@ pub struct Tileset {
     pub kind: TilesetKind,
 }
 
+impl Tileset {
+    /// The tileset data, if the tileset is embedded or is external and has been loaded (e.g. by `Map::load_with`).
+    pub fn tileset(&self) -> Option<&tileset::Tileset> {
+        match &self.kind {
+            TilesetKind::Embedded(tileset) => Some(tileset),
+            TilesetKind::External { tileset, .. } => tileset.as_deref(),
+        }
+    }
+
+    /// Resolves the path of the tileset image, relative to the file that references it: the map at `map_path` for embedded tilesets, or the external tileset file.
+    ///
+    /// Returns `None` for external tilesets that have not been loaded.
+    #[cfg(feature = "std")]
+    pub fn image_path<L: ResourceLoader + ?Sized>(
+        &self,
+        loader: &L,
+        map_path: &Path,
+    ) -> Result<Option<PathBuf>, Error> {
+        match &self.kind {
+            TilesetKind::Embedded(tileset) => {
+                loader.resolve(map_path, &tileset.image.source).map(Some)
+            }
+            TilesetKind::External {
+                source,
+                tileset: Some(tileset),
+            } => {
+                let tileset_path = loader.resolve(map_path, source)?;
+                loader
+                    .resolve(&tileset_path, &tileset.image.source)
+                    .map(Some)
+            }
+            TilesetKind::External { tileset: None, .. } => Ok(None),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum TilesetKind {
     Embedded(tileset::Tileset),
     External {
         /// If this tileset is stored in an external TSX (Tile Set XML) file, this attribute refers to that file. That TSX file has the same structure as the <tileset> element described here. (There is the firstgid attribute missing and this source attribute is also not there. These two attributes are kept in the TMX map, since they are map specific.)
         source: String,
+        /// The contents of the external tileset, once loaded (e.g. by `Map::load_with`).
+        #[serde(skip)]
+        tileset: Option<Arc<tileset::Tileset>>,
     },
 }
 
+impl TilesetKind {
+    /// The format of an external tileset, as determined by the extension of its `source`.
+    ///
+    /// Returns `None` for embedded tilesets and for sources with an unrecognised extension, in which case the format can be determined from the content with `Format::sniff`.
+    pub fn format(&self) -> Option<Format> {
+        match self {
+            TilesetKind::Embedded(_) => None,
+            TilesetKind::External { source, .. } => Format::from_extension(source),
+        }
+    }
+}
+
 fn default_compression_level() -> i32 {
     -1
 }
 
+pub(crate) fn deserialize_orientation<'de, D>(deserializer: D) -> Result<Orientation, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    options::or_default(deserializer, "orientation")
+}
+
+/// Checks the size of the map against `ParseLimits::max_tiles` once both the width and height have been read, so that in XML (where they are attributes) oversized maps are rejected before any layer is parsed.
+fn check_map_size<E: serde::de::Error>(width: Option<i32>, height: Option<i32>) -> Result<(), E> {
+    match context::map_size(width, height) {
+        Some((width, height)) => options::current()
+            .limits
+            .check_tiles(width.into(), height.into())
+            .map_err(E::custom),
+        None => Ok(()),
+    }
+}
+
+fn deserialize_width<'de, D>(deserializer: D) -> Result<i32, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let width = deserialize_number_from_string(deserializer)?;
+    check_map_size(Some(width), None)?;
+    Ok(width)
+}
+
+fn deserialize_height<'de, D>(deserializer: D) -> Result<i32, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let height = deserialize_number_from_string(deserializer)?;
+    check_map_size(None, Some(height))?;
+    Ok(height)
+}
+
+pub(crate) fn deserialize_render_order<'de, D>(deserializer: D) -> Result<RenderOrder, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    options::or_default(deserializer, "renderorder")
+}
+
+/// The parts of a tile layer that are checked (and, when parsing leniently, fixed) as the layers of a map are read.
+pub(crate) trait TileLayer {
+    fn id(&self) -> u32;
+    fn size(&self) -> (i32, i32);
+    fn data_mut(&mut self) -> &mut layer::LayerData;
+}
+
+impl TileLayer for layer::Layer {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    fn data_mut(&mut self) -> &mut layer::LayerData {
+        &mut self.data
+    }
+}
+
+/// What is reported about a layer that is skipped, taken before it is deserialized.
+struct Skipped {
+    index: usize,
+    id: Option<u32>,
+    name: Option<String>,
+    kind: Option<String>,
+}
+
+impl Skipped {
+    fn new(index: usize, layer: &Value) -> Skipped {
+        Skipped {
+            index,
+            id: layer.get("id").and_then(|id| match id {
+                Value::String(id) => id.parse().ok(),
+                id => id.as_u64().map(|id| id as u32),
+            }),
+            name: layer.get("name").and_then(Value::as_str).map(String::from),
+            kind: layer.get("type").and_then(Value::as_str).map(String::from),
+        }
+    }
+
+    fn warn(self, error: serde_json::Error) {
+        let reason = match self.kind {
+            Some(kind) if kind != "tilelayer" => format!("unsupported layer type {:?}", kind),
+            _ => error.to_string(),
+        };
+
+        options::warn(Warning::SkippedLayer {
+            index: self.index,
+            id: self.id,
+            name: self.name,
+            reason,
+        });
+    }
+}
+
+fn fix_tile_count<L: TileLayer>(layer: &mut L) {
+    let id = layer.id();
+    let (width, height) = layer.size();
+    let expected = (width.max(0) as usize).saturating_mul(height.max(0) as usize);
+    let data = layer.data_mut();
+    let found = match data {
+        layer::LayerData::Tiles(tiles) => tiles.len(),
+        layer::LayerData::CompactTiles(tiles) => tiles.len(),
+        _ => return,
+    };
+
+    if found != expected {
+        options::warn(Warning::TileCount {
+            layer: id,
+            expected,
+            found,
+        });
+
+        match data {
+            layer::LayerData::Tiles(tiles) => tiles.resize(expected, layer::Tile::default()),
+            layer::LayerData::CompactTiles(tiles) => tiles.resize(expected),
+            _ => {}
+        }
+    }
+}
+
+/// The layers of a map, read one at a time so that `ParseLimits` are checked before the next layer is read.
+///
+/// In XML, layers split up by other elements come as a sequence per run, each added to the layers read before. When parsing leniently, layers that can't be parsed are skipped and tile data that doesn't match the size of its layer is padded or truncated.
+pub(crate) struct Layers<L> {
+    options: ParseOptions,
+    layers: Vec<L>,
+    /// The number of layers read, including those that were skipped.
+    read: usize,
+    #[cfg(feature = "rayon")]
+    deferred: Option<layer::Deferred>,
+}
+
+impl<L: TileLayer> Layers<L> {
+    pub(crate) fn new() -> Layers<L> {
+        let options = options::current();
+
+        Layers {
+            #[cfg(feature = "rayon")]
+            deferred: (options.parallel() && !options.lenient && context::defer())
+                .then(layer::Deferred::start),
+            options,
+            layers: Vec::new(),
+            read: 0,
+        }
+    }
+
+    /// Returns the error to fail with when parsing the map fails with `error`.
+    pub(crate) fn fail<E: serde::de::Error>(&mut self, error: E) -> E {
+        #[cfg(feature = "rayon")]
+        if let Some(deferred) = self.deferred.take() {
+            // Tile data read before the failure would have been decoded before it was found.
+            return deferred.first_error().map_or(error, E::custom);
+        }
+
+        error
+    }
+
+    /// Returns the layers once the map has been read, decoding their tile data if it was deferred.
+    pub(crate) fn finish<E: serde::de::Error>(self) -> Result<Vec<L>, E> {
+        #[cfg(feature = "rayon")]
+        if let Some(deferred) = self.deferred {
+            let mut layers = self.layers;
+            deferred
+                .finish(layers.iter_mut().map(L::data_mut))
+                .map_err(E::custom)?;
+            return Ok(layers);
+        }
+
+        Ok(self.layers)
+    }
+}
+
+impl<'de, L: Deserialize<'de> + TileLayer> serde::de::DeserializeSeed<'de> for &mut Layers<L> {
+    type Value = ();
+
+    fn deserialize<D: serde::de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, L: Deserialize<'de> + TileLayer> serde::de::Visitor<'de> for &mut Layers<L> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a sequence of layers")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        use serde::de::{Error, IgnoredAny};
+
+        let limits = self.options.limits;
+
+        loop {
+            let index = self.read;
+            let scope = context::enter("layer", Some(index));
+
+            // A layer past the limit is skipped over rather than parsed, to tell whether there is one.
+            if let Some(max) = limits.max_layers {
+                if self.layers.len() >= max {
+                    if seq.next_element::<IgnoredAny>()?.is_none() {
+                        scope.exit();
+                        break;
+                    }
+
+                    let error = crate::error::Error::LimitExceeded(Limit::Layers(max));
+                    return Err(A::Error::custom(context::fail(error)));
+                }
+            }
+
+            let layer = if self.options.lenient {
+                let value = match seq.next_element::<Merged>()? {
+                    Some(Merged(value)) => value,
+                    None => {
+                        scope.exit();
+                        break;
+                    }
+                };
+                self.read += 1;
+                let skipped = Skipped::new(index, &value);
+
+                match L::deserialize(value) {
+                    Ok(layer) => layer,
+                    Err(e) => match context::take_failure() {
+                        // Exceeding a limit is never recovered from.
+                        Some(error @ crate::error::Error::LimitExceeded(_)) => {
+                            return Err(A::Error::custom(context::fail(error)))
+                        }
+                        _ => {
+                            scope.exit();
+                            skipped.warn(e);
+                            continue;
+                        }
+                    },
+                }
+            } else {
+                match seq.next_element::<L>()? {
+                    Some(layer) => {
+                        self.read += 1;
+                        layer
+                    }
+                    None => {
+                        scope.exit();
+                        break;
+                    }
+                }
+            };
+
+            let mut layer = layer;
+            let (width, height) = layer.size();
+            limits
+                .check_tiles(width.into(), height.into())
+                .map_err(A::Error::custom)?;
+
+            if self.options.lenient {
+                fix_tile_count(&mut layer);
+            }
+
+            self.layers.push(layer);
+            scope.exit();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "preserve-unknown")]
+fn deserialize_extra<'de, D>(deserializer: D) -> Result<crate::extra::Extra, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    crate::extra::deserialize(
+        deserializer,
+        &[
+            "type",
+            "version",
+            "tiledversion",
+            "orientation",
+            "staggeraxis",
+            "staggerindex",
+            "hexsidelength",
+        ],
+    )
+}
+
+/// A layer as listed in a `Header`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+pub struct LayerHeader {
+    /// Unique ID of the layer.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub id: u32,
+    /// The name of the layer.
+    #[serde(default)]
+    pub name: String,
+}
+
+/// A tileset reference as listed in a `Header`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+pub struct TilesetHeader {
+    /// The first global tile ID of this tileset.
+    #[serde(
+        deserialize_with = "deserialize_number_from_string",
+        rename = "firstgid"
+    )]
+    pub first_gid: u32,
+    /// The file of an external tileset.
+    pub source: Option<String>,
+    /// The name of an embedded tileset.
+    pub name: Option<String>,
+}
+
+/// A summary of a map, as returned by `Map::peek_header`.
 #[derive(Clone, Debug, Deserialize, PartialEq)]
-pub struct Map {
+pub struct Header {
     #[serde(flatten)]
     pub metadata: metadata::Metadata,
     /// Map orientation. Tiled supports “orthogonal”, “isometric”, “staggered” and “hexagonal”
     #[serde(flatten)]
     pub orientation: Orientation,
-    /// The order in which tiles on tile layers are rendered. Valid values are right-down (the default), right-up, left-down and left-up. In all cases, the map is drawn row-by-row. (only supported for orthogonal maps at the moment)
-    #[serde(rename = "renderorder", alias = "$renderorder")]
+    /// The order in which tiles on tile layers are rendered.
+    #[serde(rename = "renderorder")]
     pub render_order: RenderOrder,
-    /// The compression level to use for tile layer data (defaults to -1, which means to use the algorithm default).
-    #[serde(rename = "compressionlevel", default = "default_compression_level")]
-    pub compression_level: i32,
     /// The map width in tiles.
-    #[serde(deserialize_with = "deserialize_number_from_string")]
+    #[serde(deserialize_with = "deserialize_width")]
     pub width: i32,
     /// The map height in tiles.
-    #[serde(deserialize_with = "deserialize_number_from_string")]
+    #[serde(deserialize_with = "deserialize_height")]
     pub height: i32,
     /// The width of a tile.
     #[serde(
@@ -118,34 +511,169 @@ pub struct Map {
     pub tile_height: i32,
     #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
     pub infinite: bool,
-    /// The background color of the map. (optional, may include alpha value since 0.15 in the form #AARRGGBB)
+    #[serde(default)]
+    pub layers: Vec<LayerHeader>,
+    #[serde(default)]
+    pub tilesets: Vec<TilesetHeader>,
+}
+
+/// The fields of a `Map` other than its layers and tilesets, which `Map` reads itself.
+#[derive(Deserialize)]
+struct Fields {
+    #[serde(flatten)]
+    metadata: metadata::Metadata,
+    #[serde(flatten, deserialize_with = "deserialize_orientation")]
+    orientation: Orientation,
+    #[serde(
+        rename = "renderorder",
+        alias = "$renderorder",
+        deserialize_with = "deserialize_render_order"
+    )]
+    render_order: RenderOrder,
+    #[serde(rename = "compressionlevel", default = "default_compression_level")]
+    compression_level: i32,
+    #[serde(deserialize_with = "deserialize_width")]
+    width: i32,
+    #[serde(deserialize_with = "deserialize_height")]
+    height: i32,
+    #[serde(
+        rename = "tilewidth",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    tile_width: i32,
+    #[serde(
+        rename = "tileheight",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    tile_height: i32,
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    infinite: bool,
     #[serde(rename = "backgroundcolor", alias = "$backgroundcolor")]
-    pub background_color: Option<String>,
-    /// Stores the next available ID for new layers. This number is stored to prevent reuse of the same ID after layers have been removed. (since 1.2)
+    background_color: Option<String>,
     #[serde(
         rename = "nextlayerid",
         deserialize_with = "deserialize_number_from_string"
     )]
-    pub next_layer_id: u32,
-    /// Stores the next available ID for new objects. This number is stored to prevent reuse of the same ID after objects have been removed. (since 0.11)
+    next_layer_id: u32,
     #[serde(
         rename = "nextobjectid",
         deserialize_with = "deserialize_number_from_string"
     )]
+    next_object_id: u32,
+    #[cfg(feature = "preserve-unknown")]
+    #[serde(flatten, deserialize_with = "deserialize_extra")]
+    extra: crate::extra::Extra,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Map {
+    pub metadata: metadata::Metadata,
+    /// Map orientation. Tiled supports “orthogonal”, “isometric”, “staggered” and “hexagonal”
+    pub orientation: Orientation,
+    /// The order in which tiles on tile layers are rendered. Valid values are right-down (the default), right-up, left-down and left-up. In all cases, the map is drawn row-by-row. (only supported for orthogonal maps at the moment)
+    pub render_order: RenderOrder,
+    /// The compression level to use for tile layer data (defaults to -1, which means to use the algorithm default).
+    pub compression_level: i32,
+    /// The map width in tiles.
+    pub width: i32,
+    /// The map height in tiles.
+    pub height: i32,
+    /// The width of a tile.
+    pub tile_width: i32,
+    /// The height of a tile.
+    pub tile_height: i32,
+    pub infinite: bool,
+    /// The background color of the map. (optional, may include alpha value since 0.15 in the form #AARRGGBB)
+    pub background_color: Option<String>,
+    /// Stores the next available ID for new layers. This number is stored to prevent reuse of the same ID after layers have been removed. (since 1.2)
+    pub next_layer_id: u32,
+    /// Stores the next available ID for new objects. This number is stored to prevent reuse of the same ID after objects have been removed. (since 0.11)
     pub next_object_id: u32,
-    #[serde(alias = "layer")]
     pub layers: Vec<layer::Layer>,
-    #[serde(alias = "tileset")]
     pub tilesets: Vec<Tileset>,
+    /// Attributes and child elements that are not otherwise modelled.
+    #[cfg(feature = "preserve-unknown")]
+    pub extra: crate::extra::Extra,
+}
+
+impl<'de> Deserialize<'de> for Map {
+    fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MapVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for MapVisitor {
+            type Value = Map;
+
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Map, A::Error> {
+                use serde::de::Error;
+
+                let mut layers = Layers::new();
+                let mut tilesets = Vec::new();
+                let (mut has_layers, mut has_tilesets) = (false, false);
+
+                let fields = Fields::deserialize(Intercept::new(map, |key, map| match key {
+                    "layers" | "layer" => {
+                        has_layers = true;
+                        map.next_value_seed(&mut layers).map(|()| true)
+                    }
+                    "tilesets" | "tileset" => {
+                        has_tilesets = true;
+                        map.next_value_seed(context::Append {
+                            name: Some("tileset"),
+                            elements: &mut tilesets,
+                        })
+                        .map(|()| true)
+                    }
+                    _ => Ok(false),
+                }))
+                .map_err(|e| layers.fail(e))?;
+
+                if !has_layers {
+                    return Err(layers.fail(A::Error::missing_field("layers")));
+                }
+
+                if !has_tilesets {
+                    return Err(layers.fail(A::Error::missing_field("tilesets")));
+                }
+
+                Ok(Map {
+                    metadata: fields.metadata,
+                    orientation: fields.orientation,
+                    render_order: fields.render_order,
+                    compression_level: fields.compression_level,
+                    width: fields.width,
+                    height: fields.height,
+                    tile_width: fields.tile_width,
+                    tile_height: fields.tile_height,
+                    infinite: fields.infinite,
+                    background_color: fields.background_color,
+                    next_layer_id: fields.next_layer_id,
+                    next_object_id: fields.next_object_id,
+                    layers: layers.finish()?,
+                    tilesets,
+                    #[cfg(feature = "preserve-unknown")]
+                    extra: fields.extra,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor)
+    }
 }
 
 impl Map {
     pub fn from_json(s: &str) -> Result<Map, Error> {
-        serde_json::from_str(s).map_err(From::from)
+        context::parse_parallel("map", Some(s), || {
+            options::current().limits.check_json_depth(s)?;
+            serde_json::from_str(s).map_err(From::from)
+        })
     }
 
     pub fn from_json_data(buf: &[u8]) -> Result<Map, Error> {
-        let s = std::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+        let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
         Map::from_json(s)
     }
 
@@ -153,18 +681,318 @@ impl Map {
     pub fn from_xml(s: &str) -> Result<Map, Error> {
         #[derive(Deserialize)]
         struct Doc {
-            map: Vec<Map>,
+            map: Option<Map>,
         }
 
-        let json = super::to_json::to_json(s).map_err(Error::Conversion)?;
-        let mut doc: Doc = serde_json::from_value(json).map_err(Error::Deserialization)?;
-
-        Ok(doc.map.remove(0))
+        context::parse_parallel("map", Some(s), || {
+            let doc: Doc = super::xml::from_str(s).map_err(Error::Conversion)?;
+            doc.map.ok_or(Error::MissingElement("map"))
+        })
     }
 
     #[cfg(feature = "xml")]
     pub fn from_xml_data(buf: &[u8]) -> Result<Map, Error> {
-        let s = std::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+        let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
         Map::from_xml(s)
     }
+
+    #[cfg(feature = "lua")]
+    pub fn from_lua(s: &str) -> Result<Map, Error> {
+        context::parse_parallel("map", None, || {
+            let json = super::lua::to_json(s).map_err(Error::LuaConversion)?;
+            serde_json::from_value(json).map_err(Error::Deserialization)
+        })
+    }
+
+    #[cfg(feature = "lua")]
+    pub fn from_lua_data(buf: &[u8]) -> Result<Map, Error> {
+        let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+        Map::from_lua(s)
+    }
+
+    /// Loads a map from JSON read incrementally from `reader`.
+    #[cfg(feature = "std")]
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Map, Error> {
+        context::parse("map", None, || {
+            serde_json::from_reader(BufReader::new(options::JsonDepthReader::new(reader)))
+                .map_err(From::from)
+        })
+    }
+
+    /// Loads a map from XML read incrementally from `reader`.
+    ///
+    /// Elements are deserialized as they are read, without keeping their XML around. Embedded tilesets are the exception: each is read whole before it is deserialized, as it is told apart from a reference to an external tileset by its content.
+    #[cfg(feature = "xml")]
+    pub fn from_xml_reader<R: Read>(reader: R) -> Result<Map, Error> {
+        #[derive(Deserialize)]
+        struct Doc {
+            map: Option<Map>,
+        }
+
+        context::parse("map", None, || {
+            let doc: Doc =
+                super::xml::from_reader(BufReader::new(reader)).map_err(Error::Conversion)?;
+            doc.map.ok_or(Error::MissingElement("map"))
+        })
+    }
+
+    /// Loads a map from `reader`, detecting the format from the content.
+    ///
+    /// JSON and XML are parsed incrementally, while a Lua export is converted as a whole and is read entirely first.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read>(reader: R) -> Result<Map, Error> {
+        let mut reader = BufReader::new(reader);
+
+        match Format::sniff_reader(&mut reader)?.ok_or(Error::UnknownFormat)? {
+            Format::Json => Map::from_json_reader(reader),
+            #[cfg(feature = "xml")]
+            Format::Xml => Map::from_xml_reader(reader),
+            #[cfg(feature = "lua")]
+            Format::Lua => {
+                let mut s = String::new();
+                reader.read_to_string(&mut s)?;
+                Map::from_lua(&s)
+            }
+            #[allow(unreachable_patterns)]
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    /// Reads the summary of a map (metadata, orientation, sizes, layer names and tileset references) without decoding any layer data, detecting the format from the content.
+    pub fn peek_header(buf: &[u8]) -> Result<Header, Error> {
+        match Format::sniff(buf).ok_or(Error::UnknownFormat)? {
+            Format::Json => serde_json::from_slice(buf).map_err(From::from),
+            #[cfg(feature = "xml")]
+            Format::Xml => {
+                let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+                let json = super::xml::peek_header(s).map_err(Error::Conversion)?;
+                serde_json::from_value(json).map_err(From::from)
+            }
+            #[cfg(feature = "lua")]
+            Format::Lua => {
+                let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+                let json = super::lua::to_json(s).map_err(Error::LuaConversion)?;
+                serde_json::from_value(json).map_err(From::from)
+            }
+            #[allow(unreachable_patterns)]
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    /// Loads a map in the given format.
+    pub fn from_format_data(format: Format, buf: &[u8]) -> Result<Map, Error> {
+        match format {
+            Format::Json => Map::from_json_data(buf),
+            #[cfg(feature = "xml")]
+            Format::Xml => Map::from_xml_data(buf),
+            #[cfg(feature = "lua")]
+            Format::Lua => Map::from_lua_data(buf),
+            #[allow(unreachable_patterns)]
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    /// Writes the map as a TMX file, formatted the way Tiled formats it.
+    ///
+    /// The tile data of each layer is written in its `format`, compressed with the map's `compression_level` where supported (Zstandard). External tilesets are written as references to their source, whether they have been loaded or not.
+    ///
+    /// With `preserve-unknown`, the attributes and child elements that are not otherwise modelled are written too. Those read from JSON or Lua are written as attributes and elements as far as they fit, but aren't read back as the same values.
+    pub fn to_xml(&self) -> Result<String, Error> {
+        crate::writer::map_to_xml(self)
+    }
+
+    /// Writes the map as a JSON map file (`.tmj`), formatted the way Tiled formats it.
+    ///
+    /// Tile data is written as arrays of GIDs, or as base64 strings (compressed as with `to_xml`) for layers whose `format` is base64. External tilesets are written as references to their source.
+    ///
+    /// With `preserve-unknown`, the attributes and child elements that are not otherwise modelled are written too. Properties read from XML are converted to the JSON properties arrays Tiled writes, with values of their declared type; other attributes read from XML are written as strings.
+    pub fn to_json(&self) -> Result<String, Error> {
+        crate::writer::map_to_json(self)
+    }
+
+    /// Encodes the map in the binary cache format (see the `binary` module), for loading it with `Map::from_cache_bytes`.
+    ///
+    /// External tilesets are included if they have been loaded, e.g. by `Map::load_with`. Fails with `CacheError::TooLarge` if the encoded map is over 4 GiB.
+    #[cfg(feature = "binary")]
+    pub fn to_cache_bytes(&self) -> Result<Vec<u8>, Error> {
+        crate::binary::to_bytes(crate::binary::Kind::Map, self)
+    }
+
+    /// Loads a map written by `Map::to_cache_bytes`, checking its format version and checksum.
+    #[cfg(feature = "binary")]
+    pub fn from_cache_bytes(buf: &[u8]) -> Result<Map, Error> {
+        crate::binary::from_bytes(crate::binary::Kind::Map, buf)
+    }
+
+    /// Loads a map, detecting the format from the content.
+    pub fn from_data(buf: &[u8]) -> Result<Map, Error> {
+        let format = Format::sniff(buf).ok_or(Error::UnknownFormat)?;
+        Map::from_format_data(format, buf)
+    }
+
+    /// Loads a map with the given options, detecting the format from the content.
+    ///
+    /// Parsing fails with `Error::LimitExceeded` if the map exceeds any of `options.limits`.
+    #[cfg(feature = "std")]
+    pub fn from_data_with_options(buf: &[u8], options: &ParseOptions) -> Result<Map, Error> {
+        options::with(options, || Map::from_data(buf))
+    }
+
+    /// Loads a map with the given options from `reader`, detecting the format from the content (see `Map::from_reader`).
+    #[cfg(feature = "std")]
+    pub fn from_reader_with_options<R: Read>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<Map, Error> {
+        options::with(options, || Map::from_reader(reader))
+    }
+
+    /// Loads a map with the given options, detecting the format from the content, along with the warnings reported when parsing leniently.
+    #[cfg(feature = "std")]
+    pub fn from_data_with_warnings(
+        buf: &[u8],
+        options: &ParseOptions,
+    ) -> Result<(Map, Vec<Warning>), Error> {
+        options::with_warnings(options, || Map::from_data(buf))
+    }
+
+    /// Loads a map leniently, recovering from out-of-spec content where possible instead of failing.
+    ///
+    /// Invalid orientations and render orders are replaced with their defaults, layers that can't be parsed (such as object layers) are skipped and tile data that doesn't match the size of its layer is padded with empty tiles or truncated. Each of these is reported as a `Warning`.
+    ///
+    /// Padding allocates a tile for every cell of a layer, so set `ParseLimits::max_tiles` when parsing untrusted maps with `Map::from_data_with_warnings`.
+    #[cfg(feature = "std")]
+    pub fn from_data_lenient(buf: &[u8]) -> Result<(Map, Vec<Warning>), Error> {
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+
+        Map::from_data_with_warnings(buf, &options)
+    }
+
+    /// Loads the map at `path` using `loader` with the given options, along with any external tilesets it references.
+    #[cfg(feature = "std")]
+    pub fn load_with_options<L: ResourceLoader + ?Sized, P: AsRef<Path>>(
+        loader: &L,
+        path: P,
+        options: &ParseOptions,
+    ) -> Result<Map, Error> {
+        options::with(options, || Map::load_with(loader, path))
+    }
+
+    /// Loads the map file at `path`, detecting the format from its extension (`.tmx`, `.tmj`, `.json`, `.lua`), falling back to the content.
+    ///
+    /// External tilesets are not loaded, use `Map::load_with` with a `FileSystemLoader` for that.
+    #[cfg(feature = "std")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Map, Error> {
+        let path = path.as_ref();
+        let buf = FileSystemLoader.load(path)?;
+        let format = Format::detect(path, &buf).ok_or(Error::UnknownFormat)?;
+        Map::from_format_data(format, &buf)
+    }
+
+    /// Loads the map at `path` using `loader`, along with any external tilesets it references.
+    ///
+    /// External tilesets are resolved relative to the map, and their format is detected from their extension or content. Object templates (`.tx`, `.tj`) are not loaded, as object layers are not modelled; with `preserve-unknown` their `template` attributes are kept as written.
+    #[cfg(feature = "std")]
+    pub fn load_with<L: ResourceLoader + ?Sized, P: AsRef<Path>>(
+        loader: &L,
+        path: P,
+    ) -> Result<Map, Error> {
+        Map::load_with_cache(loader, path, &TilesetCache::new())
+    }
+
+    /// Loads the map at `path` using `loader`, taking external tilesets from `cache` or loading them into it.
+    ///
+    /// All maps loaded with the same cache share a single instance of each external tileset.
+    #[cfg(feature = "std")]
+    pub fn load_with_cache<L: ResourceLoader + ?Sized, P: AsRef<Path>>(
+        loader: &L,
+        path: P,
+        cache: &TilesetCache,
+    ) -> Result<Map, Error> {
+        let path = path.as_ref();
+        let buf = loader.load(path)?;
+        let format = Format::detect(path, &buf).ok_or(Error::UnknownFormat)?;
+        let mut map = Map::from_format_data(format, &buf)?;
+
+        for tileset in &mut map.tilesets {
+            if let TilesetKind::External { source, tileset } = &mut tileset.kind {
+                let tileset_path = loader.resolve(path, source)?;
+                *tileset = Some(cache.get_or_load(loader, &tileset_path)?);
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Loads the map at `path` using `reader`, along with any external tilesets it references.
+    ///
+    /// Once the map itself has been read, all of the external tilesets are read concurrently, each file only once.
+    #[cfg(feature = "async")]
+    pub async fn load_async<R: AsyncResourceReader + ?Sized, P: AsRef<Path>>(
+        reader: &R,
+        path: P,
+    ) -> Result<Map, Error> {
+        Map::load_async_with_cache(reader, path, &TilesetCache::new()).await
+    }
+
+    /// Loads the map at `path` using `reader`, taking external tilesets from `cache` or reading them concurrently into it.
+    ///
+    /// Tilesets are cached under the path returned by `AsyncResourceReader::canonicalize`, so a cache can be shared with `Map::load_with_cache` as long as the reader and loader canonicalize paths the same way.
+    #[cfg(feature = "async")]
+    pub async fn load_async_with_cache<R: AsyncResourceReader + ?Sized, P: AsRef<Path>>(
+        reader: &R,
+        path: P,
+        cache: &TilesetCache,
+    ) -> Result<Map, Error> {
+        let path = path.as_ref();
+        let buf = reader.read(path).await?;
+        let format = Format::detect(path, &buf).ok_or(Error::UnknownFormat)?;
+        let mut map = Map::from_format_data(format, &buf)?;
+
+        // The path each tileset is read from, and the key it is cached under.
+        let mut paths = Vec::new();
+        let mut keys = Vec::new();
+        // The key of each external tileset, and the tileset if it was already cached.
+        let mut external = Vec::new();
+
+        for (index, tileset) in map.tilesets.iter().enumerate() {
+            if let TilesetKind::External { source, .. } = &tileset.kind {
+                let tileset_path = reader.resolve(path, source)?;
+                let key = reader.canonicalize(&tileset_path)?;
+                let cached = cache.get(&key);
+
+                if cached.is_none() && !keys.contains(&key) {
+                    paths.push(tileset_path);
+                    keys.push(key.clone());
+                }
+
+                external.push((index, key, cached));
+            }
+        }
+
+        let contents =
+            futures_util::future::try_join_all(paths.iter().map(|path| reader.read(path))).await?;
+        let mut loaded = Vec::with_capacity(keys.len());
+
+        for ((path, key), buf) in paths.iter().zip(&keys).zip(contents) {
+            let tileset = tileset::Tileset::from_source_data(path, &buf)?;
+            loaded.push(cache.insert(key.clone(), Arc::new(tileset)));
+        }
+
+        for (index, key, cached) in external {
+            let loaded = keys
+                .iter()
+                .position(|other| *other == key)
+                .map(|position| loaded[position].clone());
+
+            if let TilesetKind::External { tileset, .. } = &mut map.tilesets[index].kind {
+                *tileset = cached.or(loaded);
+            }
+        }
+
+        Ok(map)
+    }
 }
@@ -1,3 +1,7 @@
+use crate::error::Error;
+
+use alloc::string::{String, ToString};
+use core::{fmt, str::FromStr};
 use serde::{de::Deserializer, Deserialize};
 
 fn deserialize_version<'de, D>(deserializer: D) -> Result<String, D::Error>
@@ -24,3 +28,82 @@ pub struct Metadata {
     #[serde(rename = "tiledversion")]
     pub tiled_version: String,
 }
+
+impl Metadata {
+    /// The TMX format version, e.g. `1.10`.
+    pub fn format_version(&self) -> Result<Version, Error> {
+        self.version.parse()
+    }
+
+    /// The version of Tiled the file was saved with, e.g. `1.10.2`.
+    pub fn editor_version(&self) -> Result<Version, Error> {
+        self.tiled_version.parse()
+    }
+}
+
+/// A version of Tiled or of the TMX format, ordered numerically so that `1.10` comes after `1.9`.
+///
+/// Missing components are 0, and suffixes such as `-beta` are ignored.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// The TMX format version written by this version of Tiled.
+    ///
+    /// Tiled 1.0 and 1.1 wrote format 1.0, 1.2 and 1.3 wrote 1.2, and since 1.4 the format version follows the minor version of Tiled.
+    pub fn format_version(self) -> Version {
+        match (self.major, self.minor) {
+            (0, _) | (1, 0..=1) => Version::new(1, 0, 0),
+            (1, 2..=3) => Version::new(1, 2, 0),
+            (major, minor) => Version::new(major, minor, 0),
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Version, Error> {
+        let invalid = || Error::InvalidVersion(s.to_string());
+        let end = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let mut components = s[..end].split('.');
+        let mut component = |required| match components.next() {
+            Some(component) => component.parse().map_err(|_| invalid()),
+            None if required => Err(invalid()),
+            None => Ok(0),
+        };
+
+        let version = Version::new(component(true)?, component(false)?, component(false)?);
+
+        match components.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(version),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+
+        if self.patch > 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+
+        Ok(())
+    }
+}
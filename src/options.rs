@@ -0,0 +1,335 @@
+use crate::{context, error::Error};
+
+use alloc::string::{String, ToString};
+use core::fmt;
+use serde::{
+    de::{DeserializeOwned, Deserializer},
+    Deserialize,
+};
+use serde_json::Value;
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+
+/// Limits on the resources a map may use, to guard against decompression bombs and oversized maps from untrusted sources.
+///
+/// All limits are disabled by default.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ParseLimits {
+    /// The maximum number of bytes the tile data of a single layer may decompress to.
+    pub max_decompressed_bytes: Option<usize>,
+    /// The maximum number of tiles in a single layer or map, i.e. its width × height or the total size of its chunks.
+    pub max_tiles: Option<usize>,
+    /// The maximum number of layers in a map.
+    pub max_layers: Option<usize>,
+    /// The maximum nesting depth of elements (XML), objects and arrays (JSON) or tables (Lua).
+    pub max_depth: Option<usize>,
+}
+
+/// A limit that was exceeded, along with its configured value.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Limit {
+    DecompressedBytes(usize),
+    Tiles(usize),
+    Layers(usize),
+    Depth(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::DecompressedBytes(max) => {
+                write!(f, "layer data decompresses to more than {} bytes", max)
+            }
+            Limit::Tiles(max) => write!(f, "more than {} tiles", max),
+            Limit::Layers(max) => write!(f, "more than {} layers", max),
+            Limit::Depth(max) => write!(f, "nested more than {} levels deep", max),
+        }
+    }
+}
+
+impl ParseLimits {
+    /// Returns the exceeded limit if a grid of `width` × `height` tiles is over `max_tiles`, without overflowing.
+    pub(crate) fn tiles_exceeded(&self, width: i64, height: i64) -> Option<Limit> {
+        let max = self.max_tiles?;
+        let tiles = width.max(0).checked_mul(height.max(0));
+
+        match tiles {
+            Some(tiles) if tiles <= max as i64 => None,
+            _ => Some(Limit::Tiles(max)),
+        }
+    }
+
+    /// Checks that a grid of `width` × `height` tiles is within `max_tiles`.
+    pub(crate) fn check_tiles(&self, width: i64, height: i64) -> Result<(), String> {
+        match self.tiles_exceeded(width, height) {
+            Some(limit) => Err(context::fail(Error::LimitExceeded(limit))),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that nesting `depth` levels deep is within `max_depth`.
+    #[cfg(any(feature = "xml", feature = "lua"))]
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<(), String> {
+        match self.max_depth {
+            Some(max) if depth > max => Err(context::fail(Error::LimitExceeded(Limit::Depth(max)))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the nesting depth of a JSON document before parsing it.
+    pub(crate) fn check_json_depth(&self, json: &str) -> Result<(), Error> {
+        match self.max_depth {
+            Some(max) => JsonDepth::new(max).scan(json.as_bytes()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Tracks the nesting depth of JSON as it is scanned, possibly in several parts.
+struct JsonDepth {
+    max: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonDepth {
+    fn new(max: usize) -> JsonDepth {
+        JsonDepth {
+            max,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+        }
+    }
+
+    fn scan(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        for &b in bytes {
+            match b {
+                _ if self.escaped => self.escaped = false,
+                b'\\' if self.in_string => self.escaped = true,
+                b'"' => self.in_string = !self.in_string,
+                _ if self.in_string => {}
+                b'{' | b'[' => {
+                    self.depth += 1;
+
+                    if self.depth > self.max {
+                        return Err(Error::LimitExceeded(Limit::Depth(self.max)));
+                    }
+                }
+                b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the nesting depth of JSON as it is read, against the `max_depth` of the current options.
+///
+/// Exceeding it fails the read, and `context::parse` reports the `Error::LimitExceeded` recorded instead.
+#[cfg(feature = "std")]
+pub(crate) struct JsonDepthReader<R> {
+    reader: R,
+    depth: Option<JsonDepth>,
+}
+
+#[cfg(feature = "std")]
+impl<R> JsonDepthReader<R> {
+    pub(crate) fn new(reader: R) -> JsonDepthReader<R> {
+        JsonDepthReader {
+            reader,
+            depth: current().limits.max_depth.map(JsonDepth::new),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> std::io::Read for JsonDepthReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+
+        if let Some(depth) = &mut self.depth {
+            depth.scan(&buf[..n]).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, context::fail(e))
+            })?;
+        }
+
+        Ok(n)
+    }
+}
+
+/// Options controlling how a map is parsed, see `Map::from_data_with_options`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ParseOptions {
+    /// Keep the chunks of infinite maps encoded and only decode them when requested, keeping at most this many decoded chunks per layer (see `layer::LazyChunks`).
+    pub lazy_chunks: Option<usize>,
+    pub limits: ParseLimits,
+    /// Recover from out-of-spec content where possible instead of failing, reporting a `Warning` for each problem (see `Map::from_data_with_warnings`).
+    pub lenient: bool,
+    /// Store the tiles of fixed-size layers in 8 or 16 bit cells where possible (see `layer::CompactTiles`). The chunks of infinite maps are kept as they are, `lazy_chunks` limits the memory they use instead.
+    pub compact_tiles: bool,
+    /// Decode tile data on the current thread, rather than decoding layers and chunks in parallel.
+    #[cfg(feature = "rayon")]
+    pub sequential: bool,
+}
+
+impl ParseOptions {
+    /// Whether layers and chunks are decoded in parallel.
+    pub(crate) fn parallel(&self) -> bool {
+        #[cfg(feature = "rayon")]
+        return !self.sequential;
+        #[cfg(not(feature = "rayon"))]
+        false
+    }
+}
+
+/// A problem that was recovered from while parsing leniently.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Warning {
+    /// An attribute had an invalid value and the default was used instead.
+    InvalidValue { attribute: String, value: String },
+    /// The number of tiles in a layer didn't match its size, so the data was padded with empty tiles or truncated.
+    TileCount {
+        layer: u32,
+        expected: usize,
+        found: usize,
+    },
+    /// A layer couldn't be parsed, e.g. because it is of an unknown kind, and was skipped.
+    SkippedLayer {
+        /// The position of the layer in the map.
+        index: usize,
+        id: Option<u32>,
+        name: Option<String>,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::InvalidValue { attribute, value } => {
+                write!(f, "invalid {}: {}, using the default", attribute, value)
+            }
+            Warning::TileCount {
+                layer,
+                expected,
+                found,
+            } => write!(
+                f,
+                "layer {} has {} tiles instead of {}",
+                layer, found, expected
+            ),
+            Warning::SkippedLayer {
+                index,
+                name,
+                reason,
+                ..
+            } => match name {
+                Some(name) => write!(f, "skipped layer {:?}: {}", name, reason),
+                None => write!(f, "skipped layer #{}: {}", index, reason),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static CURRENT: RefCell<ParseOptions> = RefCell::new(ParseOptions::default());
+    static WARNINGS: RefCell<Vec<Warning>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Restores the previous options and warnings when parsing finishes, even by unwinding.
+#[cfg(feature = "std")]
+struct Restore(Option<(ParseOptions, Vec<Warning>)>);
+
+#[cfg(feature = "std")]
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some((options, warnings)) = self.0.take() {
+            CURRENT.with(|current| *current.borrow_mut() = options);
+            WARNINGS.with(|current| *current.borrow_mut() = warnings);
+        }
+    }
+}
+
+/// Runs `f` with `options` in effect, returning the warnings reported while parsing.
+///
+/// The `Deserialize` implementations can't be given any arguments, so they look the options up with `current` instead.
+#[cfg(feature = "std")]
+pub(crate) fn with_warnings<T, F: FnOnce() -> Result<T, Error>>(
+    options: &ParseOptions,
+    f: F,
+) -> Result<(T, Vec<Warning>), Error> {
+    let previous = CURRENT.with(|current| current.replace(options.clone()));
+    let warnings = WARNINGS.with(|warnings| warnings.replace(Vec::new()));
+    let _restore = Restore(Some((previous, warnings)));
+
+    let value = f()?;
+    let warnings = WARNINGS.with(|warnings| warnings.replace(Vec::new()));
+
+    Ok((value, warnings))
+}
+
+/// Runs `f` with `options` in effect.
+#[cfg(feature = "std")]
+pub(crate) fn with<T, F: FnOnce() -> Result<T, Error>>(
+    options: &ParseOptions,
+    f: F,
+) -> Result<T, Error> {
+    with_warnings(options, f).map(|(value, _)| value)
+}
+
+/// Returns the options of the map currently being parsed on this thread.
+#[cfg(feature = "std")]
+pub(crate) fn current() -> ParseOptions {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Without `std` there are no thread locals to keep options in, so maps are always parsed with the default options.
+#[cfg(not(feature = "std"))]
+pub(crate) fn current() -> ParseOptions {
+    ParseOptions::default()
+}
+
+/// Reports a problem that was recovered from.
+#[cfg(feature = "std")]
+pub(crate) fn warn(warning: Warning) {
+    WARNINGS.with(|warnings| warnings.borrow_mut().push(warning));
+}
+
+/// Parsing is never lenient without `std`, so there are no warnings to report.
+#[cfg(not(feature = "std"))]
+pub(crate) fn warn(_warning: Warning) {}
+
+/// Deserializes an attribute, falling back to its default value (with a warning) if it is invalid and parsing is lenient.
+pub(crate) fn or_default<'de, D, T>(deserializer: D, attribute: &str) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    if !current().lenient {
+        return T::deserialize(deserializer);
+    }
+
+    let value = Value::deserialize(deserializer)?;
+
+    match T::deserialize(&value) {
+        Ok(value) => Ok(value),
+        Err(_) => {
+            // Flattened attributes see the whole element, only report the attribute itself.
+            let value = value.get(attribute).unwrap_or(&value);
+
+            warn(Warning::InvalidValue {
+                attribute: attribute.to_string(),
+                value: match value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                },
+            });
+
+            Ok(T::default())
+        }
+    }
+}
@@ -1,8 +1,24 @@
-use crate::{error::Error, metadata};
+#[cfg(feature = "std")]
+use crate::loader::{FileSystemLoader, ResourceLoader};
+use crate::{
+    context,
+    de::{deserialize_number_from_string, Intercept},
+    error::Error,
+    format::Format,
+    metadata, options,
+};
 
-use serde::{de::Deserializer, Deserialize};
-use serde_aux::field_attributes::deserialize_number_from_string;
-use std::time::Duration;
+use alloc::{string::String, vec::Vec};
+use core::{convert::TryFrom, fmt, time::Duration};
+use serde::{
+    de::{Deserializer, MapAccess, Visitor},
+    Deserialize,
+};
+#[cfg(feature = "std")]
+use std::{
+    io::{BufReader, Read},
+    path::Path,
+};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Image {
@@ -34,10 +50,10 @@ impl<'de> Deserialize<'de> for Image {
         #[derive(Deserialize)]
         #[serde(untagged)]
         enum ImageData {
-            XML {
+            Xml {
                 image: Vec<XMLImage>,
             },
-            JSON {
+            Json {
                 image: String,
                 imageheight: u32,
                 imagewidth: u32,
@@ -45,36 +61,38 @@ impl<'de> Deserialize<'de> for Image {
             },
         }
 
-        impl Into<Image> for ImageData {
-            fn into(self) -> Image {
-                match self {
-                    ImageData::XML { mut image } => {
-                        let image = image.remove(0);
+        impl TryFrom<ImageData> for Image {
+            type Error = &'static str;
 
-                        Image {
+            fn try_from(data: ImageData) -> Result<Image, Self::Error> {
+                match data {
+                    ImageData::Xml { image } => {
+                        let image = image.into_iter().next().ok_or("missing image element")?;
+
+                        Ok(Image {
                             source: image.source,
                             transparent_color: image.trans,
                             width: image.width,
                             height: image.height,
-                        }
+                        })
                     }
-                    ImageData::JSON {
+                    ImageData::Json {
                         image,
                         imageheight,
                         imagewidth,
                         transparentcolor,
-                    } => Image {
+                    } => Ok(Image {
                         source: image,
                         transparent_color: transparentcolor,
                         width: imagewidth,
                         height: imageheight,
-                    },
+                    }),
                 }
             }
         }
 
         let data = ImageData::deserialize(deserializer)?;
-        Ok(data.into())
+        Image::try_from(data).map_err(serde::de::Error::custom)
     }
 }
 
@@ -96,7 +114,7 @@ pub struct Frame {
     pub duration: Duration,
 }
 
-fn deserialize_animation<'de, D>(deserializer: D) -> Result<Vec<Frame>, D::Error>
+pub(crate) fn deserialize_animation<'de, D>(deserializer: D) -> Result<Vec<Frame>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -109,82 +127,187 @@ where
     #[derive(Deserialize)]
     #[serde(untagged)]
     enum Animations {
-        JSON(Vec<Frame>),
-        XML(Vec<Animation>),
+        Json(Vec<Frame>),
+        Xml(Vec<Animation>),
     }
 
     match Animations::deserialize(deserializer)? {
-        Animations::XML(animations) => Ok(animations[0].frames.clone()),
-        Animations::JSON(frames) => Ok(frames),
+        Animations::Xml(animations) => Ok(animations
+            .into_iter()
+            .next()
+            .map(|animation| animation.frames)
+            .unwrap_or_default()),
+        Animations::Json(frames) => Ok(frames),
     }
 }
 
+#[cfg(feature = "preserve-unknown")]
+fn deserialize_tile_extra<'de, D>(deserializer: D) -> Result<crate::extra::Extra, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    crate::extra::deserialize(deserializer, &[])
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
 pub struct Tile {
     /// The local tile ID within its tileset.
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub id: u32,
     /// The type of the tile. Refers to an object type and is used by tile objects. (optional) (since 1.0)
+    #[serde(default)]
     pub r#type: String,
     /// Contains a list of animation frames.
     ///
     /// Each tile can have exactly one animation associated with it. In the future, there could be support for multiple named animations on a tile.
     #[serde(deserialize_with = "deserialize_animation", default)]
     pub animation: Vec<Frame>,
+    /// Attributes and child elements that are not otherwise modelled.
+    #[cfg(feature = "preserve-unknown")]
+    #[serde(flatten, deserialize_with = "deserialize_tile_extra")]
+    pub extra: crate::extra::Extra,
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
-pub struct Tileset {
+#[cfg(feature = "preserve-unknown")]
+fn deserialize_tileset_extra<'de, D>(deserializer: D) -> Result<crate::extra::Extra, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    crate::extra::deserialize(
+        deserializer,
+        &[
+            "type",
+            "version",
+            "tiledversion",
+            "image",
+            "imagewidth",
+            "imageheight",
+            "transparentcolor",
+        ],
+    )
+}
+
+/// The fields of a `Tileset` other than its tiles, which `Tileset` reads itself.
+#[derive(Deserialize)]
+struct Fields {
     #[serde(flatten)]
-    pub metadata: Option<metadata::Metadata>,
-    /// The name of this tileset.
-    pub name: String,
+    metadata: Option<metadata::Metadata>,
+    name: String,
     #[serde(
         deserialize_with = "deserialize_number_from_string",
         rename = "tilewidth"
     )]
-    /// The (maximum) width of the tiles in this tileset.
-    pub tile_width: u32,
-    /// The (maximum) height of the tiles in this tileset.
+    tile_width: u32,
     #[serde(
         deserialize_with = "deserialize_number_from_string",
         rename = "tileheight"
     )]
-    pub tile_height: u32,
-    /// The spacing in pixels between the tiles in this tileset (applies to the tileset image).
+    tile_height: u32,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
-    pub spacing: u32,
-    /// The margin around the tiles in this tileset (applies to the tileset image).
+    spacing: u32,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
-    pub margin: u32,
-    /// The number of tiles in this tileset (since 0.13)
+    margin: u32,
     #[serde(
         deserialize_with = "deserialize_number_from_string",
         rename = "tilecount"
     )]
+    tile_count: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    columns: u32,
+    #[serde(rename = "backgroundcolor")]
+    background_color: Option<String>,
+    #[serde(flatten)]
+    image: Image,
+    #[cfg(feature = "preserve-unknown")]
+    #[serde(flatten, deserialize_with = "deserialize_tileset_extra")]
+    extra: crate::extra::Extra,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Tileset {
+    pub metadata: Option<metadata::Metadata>,
+    /// The name of this tileset.
+    pub name: String,
+    /// The (maximum) width of the tiles in this tileset.
+    pub tile_width: u32,
+    /// The (maximum) height of the tiles in this tileset.
+    pub tile_height: u32,
+    /// The spacing in pixels between the tiles in this tileset (applies to the tileset image).
+    pub spacing: u32,
+    /// The margin around the tiles in this tileset (applies to the tileset image).
+    pub margin: u32,
+    /// The number of tiles in this tileset (since 0.13)
     pub tile_count: usize,
     /// The number of tile columns in the tileset. For image collection tilesets it is editable and is used when displaying the tileset. (since 0.15)
-    #[serde(deserialize_with = "deserialize_number_from_string")]
     pub columns: u32,
-    #[serde(rename = "backgroundcolor")]
     pub background_color: Option<String>,
     // tileoffset
     // grid
-    #[serde(flatten)]
     pub image: Image,
     // terrainttypes
-    #[serde(alias = "tile", default)]
     pub tiles: Vec<Tile>,
     // wangsets
+    /// Attributes and child elements that are not otherwise modelled.
+    #[cfg(feature = "preserve-unknown")]
+    pub extra: crate::extra::Extra,
+}
+
+impl<'de> Deserialize<'de> for Tileset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TilesetVisitor;
+
+        impl<'de> Visitor<'de> for TilesetVisitor {
+            type Value = Tileset;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a tileset")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Tileset, A::Error> {
+                let mut tiles = Vec::new();
+
+                let fields = Fields::deserialize(Intercept::new(map, |key, map| match key {
+                    "tiles" | "tile" => map
+                        .next_value_seed(context::Append {
+                            name: None,
+                            elements: &mut tiles,
+                        })
+                        .map(|()| true),
+                    _ => Ok(false),
+                }))?;
+
+                Ok(Tileset {
+                    metadata: fields.metadata,
+                    name: fields.name,
+                    tile_width: fields.tile_width,
+                    tile_height: fields.tile_height,
+                    spacing: fields.spacing,
+                    margin: fields.margin,
+                    tile_count: fields.tile_count,
+                    columns: fields.columns,
+                    background_color: fields.background_color,
+                    image: fields.image,
+                    tiles,
+                    #[cfg(feature = "preserve-unknown")]
+                    extra: fields.extra,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(TilesetVisitor)
+    }
 }
 
 impl Tileset {
     pub fn from_json(s: &str) -> Result<Tileset, Error> {
-        serde_json::from_str(s).map_err(From::from)
+        context::parse("tileset", Some(s), || {
+            options::current().limits.check_json_depth(s)?;
+            serde_json::from_str(s).map_err(From::from)
+        })
     }
 
     pub fn from_json_data(buf: &[u8]) -> Result<Tileset, Error> {
-        let s = std::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+        let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
         Tileset::from_json(s)
     }
 
@@ -192,18 +315,140 @@ impl Tileset {
     pub fn from_xml(s: &str) -> Result<Tileset, Error> {
         #[derive(Deserialize)]
         struct Doc {
-            tileset: Vec<Tileset>,
+            tileset: Option<Tileset>,
         }
 
-        let json = super::to_json::to_json(s).map_err(Error::Conversion)?;
-        let mut doc: Doc = serde_json::from_value(json).map_err(Error::Deserialization)?;
-
-        Ok(doc.tileset.remove(0))
+        context::parse("tileset", Some(s), || {
+            let doc: Doc = super::xml::from_str(s).map_err(Error::Conversion)?;
+            doc.tileset.ok_or(Error::MissingElement("tileset"))
+        })
     }
 
     #[cfg(feature = "xml")]
     pub fn from_xml_data(buf: &[u8]) -> Result<Tileset, Error> {
-        let s = std::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+        let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
         Tileset::from_xml(s)
     }
+
+    #[cfg(feature = "lua")]
+    pub fn from_lua(s: &str) -> Result<Tileset, Error> {
+        context::parse("tileset", None, || {
+            let json = super::lua::to_json(s).map_err(Error::LuaConversion)?;
+            serde_json::from_value(json).map_err(Error::Deserialization)
+        })
+    }
+
+    #[cfg(feature = "lua")]
+    pub fn from_lua_data(buf: &[u8]) -> Result<Tileset, Error> {
+        let s = core::str::from_utf8(buf).map_err(Error::Utf8Error)?;
+        Tileset::from_lua(s)
+    }
+
+    /// Loads a tileset from JSON read incrementally from `reader`.
+    #[cfg(feature = "std")]
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Tileset, Error> {
+        context::parse("tileset", None, || {
+            serde_json::from_reader(BufReader::new(options::JsonDepthReader::new(reader)))
+                .map_err(From::from)
+        })
+    }
+
+    /// Loads a tileset from XML read incrementally from `reader`.
+    ///
+    /// Elements are deserialized as they are read.
+    #[cfg(feature = "xml")]
+    pub fn from_xml_reader<R: Read>(reader: R) -> Result<Tileset, Error> {
+        #[derive(Deserialize)]
+        struct Doc {
+            tileset: Option<Tileset>,
+        }
+
+        context::parse("tileset", None, || {
+            let doc: Doc =
+                super::xml::from_reader(BufReader::new(reader)).map_err(Error::Conversion)?;
+            doc.tileset.ok_or(Error::MissingElement("tileset"))
+        })
+    }
+
+    /// Loads a tileset from `reader`, detecting the format from the content.
+    ///
+    /// JSON and XML are parsed incrementally, while a Lua export is converted as a whole and is read entirely first.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read>(reader: R) -> Result<Tileset, Error> {
+        let mut reader = BufReader::new(reader);
+
+        match Format::sniff_reader(&mut reader)?.ok_or(Error::UnknownFormat)? {
+            Format::Json => Tileset::from_json_reader(reader),
+            #[cfg(feature = "xml")]
+            Format::Xml => Tileset::from_xml_reader(reader),
+            #[cfg(feature = "lua")]
+            Format::Lua => {
+                let mut s = String::new();
+                reader.read_to_string(&mut s)?;
+                Tileset::from_lua(&s)
+            }
+            #[allow(unreachable_patterns)]
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    /// Loads a tileset in the given format.
+    pub fn from_format_data(format: Format, buf: &[u8]) -> Result<Tileset, Error> {
+        match format {
+            Format::Json => Tileset::from_json_data(buf),
+            #[cfg(feature = "xml")]
+            Format::Xml => Tileset::from_xml_data(buf),
+            #[cfg(feature = "lua")]
+            Format::Lua => Tileset::from_lua_data(buf),
+            #[allow(unreachable_patterns)]
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    /// Writes the tileset as a TSX file, formatted the way Tiled formats it.
+    ///
+    /// Tilesets hold no tile data to encode, so this never returns an error.
+    pub fn to_xml(&self) -> Result<String, Error> {
+        crate::writer::tileset_to_xml(self)
+    }
+
+    /// Writes the tileset as a JSON tileset file (`.tsj`), formatted the way Tiled formats it.
+    ///
+    /// Never returns an error, as with `to_xml`.
+    pub fn to_json(&self) -> Result<String, Error> {
+        crate::writer::tileset_to_json(self)
+    }
+
+    /// Encodes the tileset in the binary cache format (see the `binary` module), for loading it with `Tileset::from_cache_bytes`.
+    #[cfg(feature = "binary")]
+    pub fn to_cache_bytes(&self) -> Result<Vec<u8>, Error> {
+        crate::binary::to_bytes(crate::binary::Kind::Tileset, self)
+    }
+
+    /// Loads a tileset written by `Tileset::to_cache_bytes`, checking its format version and checksum.
+    #[cfg(feature = "binary")]
+    pub fn from_cache_bytes(buf: &[u8]) -> Result<Tileset, Error> {
+        crate::binary::from_bytes(crate::binary::Kind::Tileset, buf)
+    }
+
+    /// Loads a tileset, detecting the format from the content.
+    pub fn from_data(buf: &[u8]) -> Result<Tileset, Error> {
+        let format = Format::sniff(buf).ok_or(Error::UnknownFormat)?;
+        Tileset::from_format_data(format, buf)
+    }
+
+    /// Loads an external tileset referenced by `source` (e.g. `TilesetKind::External::source`), detecting the format from its extension, falling back to the content.
+    #[cfg(feature = "std")]
+    pub fn from_source_data<P: AsRef<Path>>(source: P, buf: &[u8]) -> Result<Tileset, Error> {
+        let format = Format::detect(source, buf).ok_or(Error::UnknownFormat)?;
+        Tileset::from_format_data(format, buf)
+    }
+
+    /// Loads the tileset file at `path`, detecting the format from its extension (`.tsx`, `.tsj`, `.json`, `.lua`), falling back to the content.
+    #[cfg(feature = "std")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Tileset, Error> {
+        let path = path.as_ref();
+        let buf = FileSystemLoader.load(path)?;
+        Tileset::from_source_data(path, &buf)
+    }
 }
@@ -0,0 +1,212 @@
+use crate::{
+    cache::TilesetCache,
+    diff::{diff, ChangeSet},
+    error::Error,
+    loader::{FileSystemLoader, ResourceLoader},
+    map::{Map, TilesetKind},
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+/// How long to wait for further events after a file changed, editors often write a file in several steps.
+const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+/// Watches a map file and its external tilesets, reloading the map when any of them change on disk.
+///
+/// Each reload produces a `ChangeSet` describing what changed compared to the previous version of the map.
+pub struct MapWatcher {
+    path: PathBuf,
+    map: Map,
+    cache: TilesetCache,
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    directories: Vec<PathBuf>,
+    /// The watched files changed since the map was last reloaded.
+    changed: Vec<PathBuf>,
+    /// When to reload the map, unless further changes are seen by then.
+    settled: Option<Instant>,
+}
+
+impl MapWatcher {
+    /// Loads the map at `path` and starts watching it.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<MapWatcher, Error> {
+        let path = FileSystemLoader.canonicalize(path.as_ref())?;
+        let cache = TilesetCache::new();
+        let map = Map::load_with_cache(&FileSystemLoader, &path, &cache)?;
+
+        let (sender, events) = channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            // The receiver is only dropped along with the watcher.
+            let _ = sender.send(event);
+        })?;
+
+        let mut watcher = MapWatcher {
+            path,
+            map,
+            cache,
+            watcher,
+            events,
+            directories: Vec::new(),
+            changed: Vec::new(),
+            settled: None,
+        };
+        watcher.watch_directories()?;
+
+        Ok(watcher)
+    }
+
+    /// The path of the map file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The current version of the map.
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// The map file and the files of all of its external tilesets.
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.path.clone()];
+
+        for tileset in &self.map.tilesets {
+            if let TilesetKind::External { source, .. } = &tileset.kind {
+                if let Ok(path) = FileSystemLoader
+                    .resolve(&self.path, source)
+                    .and_then(|path| FileSystemLoader.canonicalize(&path))
+                {
+                    files.push(path);
+                }
+            }
+        }
+
+        files
+    }
+
+    /// Watches the directories containing the watched files, rather than the files themselves, so that files replaced by renaming (as many editors do when saving) are still noticed.
+    fn watch_directories(&mut self) -> Result<(), Error> {
+        for file in self.files() {
+            if let Some(directory) = file.parent() {
+                if !self.directories.iter().any(|watched| watched == directory) {
+                    self.watcher.watch(directory, RecursiveMode::NonRecursive)?;
+                    self.directories.push(directory.to_path_buf());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the watched files affected by an event.
+    fn changed_files(&self, event: notify::Result<Event>) -> Result<Vec<PathBuf>, Error> {
+        let event = event?;
+
+        if let EventKind::Access(_) = event.kind {
+            return Ok(Vec::new());
+        }
+
+        let files = self.files();
+
+        Ok(event
+            .paths
+            .into_iter()
+            .filter(|path| files.contains(path))
+            .collect())
+    }
+
+    /// Records the watched files affected by an event, putting off the reload until they have settled.
+    fn record(&mut self, event: notify::Result<Event>) -> Result<(), Error> {
+        let files = self.changed_files(event)?;
+
+        if !files.is_empty() {
+            self.changed.extend(files);
+            self.settled = Some(Instant::now() + SETTLE_TIME);
+        }
+
+        Ok(())
+    }
+
+    /// Reloads the map after the recorded files were modified, returning what changed, or `None` if nothing did.
+    fn reload(&mut self) -> Option<Result<ChangeSet, Error>> {
+        self.settled = None;
+
+        // Only the watched files are recorded, which are canonical paths like the keys of the cache.
+        for path in std::mem::take(&mut self.changed) {
+            self.cache.invalidate(&path);
+        }
+
+        let result =
+            Map::load_with_cache(&FileSystemLoader, &self.path, &self.cache).and_then(|map| {
+                let changes = diff(&self.map, &map);
+                self.map = map;
+
+                // The map may now reference tilesets in other directories.
+                self.watch_directories()?;
+
+                Ok(changes)
+            });
+
+        match result {
+            Ok(changes) if changes.is_empty() => None,
+            result => Some(result),
+        }
+    }
+
+    /// Waits up to `timeout` for the map or one of its tilesets to change, returning what changed.
+    ///
+    /// Returns `None` if nothing changed in time. If reloading fails (e.g. because the file is invalid), the error is returned and the previous version of the map is kept.
+    pub fn wait(&mut self, timeout: Duration) -> Option<Result<ChangeSet, Error>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let now = Instant::now();
+
+            if let Some(settled) = self.settled {
+                if now >= settled {
+                    match self.reload() {
+                        Some(result) => return Some(result),
+                        None => continue,
+                    }
+                }
+            } else if now >= deadline {
+                return None;
+            }
+
+            let until = self.settled.unwrap_or(deadline);
+
+            match self
+                .events
+                .recv_timeout(until.saturating_duration_since(now))
+            {
+                Ok(event) => {
+                    if let Err(e) = self.record(event) {
+                        return Some(Err(e));
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Checks whether the map or one of its tilesets changed, without blocking.
+    ///
+    /// Like `wait`, the map is only reloaded once no further change has been seen for a moment, as editors often write a file in several steps. A change is therefore reported by a later call than the one that first notices it.
+    pub fn poll(&mut self) -> Option<Result<ChangeSet, Error>> {
+        while let Ok(event) = self.events.try_recv() {
+            if let Err(e) = self.record(event) {
+                return Some(Err(e));
+            }
+        }
+
+        match self.settled {
+            Some(settled) if Instant::now() >= settled => self.reload(),
+            _ => None,
+        }
+    }
+}
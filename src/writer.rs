@@ -0,0 +1,1175 @@
+//! Writes maps and tilesets in the formats Tiled saves them in, see `Map::to_xml`, `Map::to_json`, `Tileset::to_xml` and `Tileset::to_json`.
+
+use crate::{
+    error::Error,
+    layer::{Chunk, Compression, DataFormat, Encoding, Layer, LayerData, Tile},
+    map::{self, Map, Orientation, RenderOrder, StaggerAxis, StaggerIndex, TilesetKind},
+    tileset::{self, Image, Tileset},
+};
+
+#[cfg(feature = "preserve-unknown")]
+use alloc::collections::BTreeMap;
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{Display, Write};
+use serde_json::Value;
+
+/// Builds an XML document indented the way Tiled indents it, with one space per level.
+#[derive(Default)]
+struct XmlWriter {
+    out: String,
+    /// The names of the elements that are open.
+    open: Vec<String>,
+    /// Whether the start tag of the innermost element is still open, so that attributes can be added to it.
+    in_tag: bool,
+    /// Whether the innermost element has text, which its end tag directly follows.
+    has_text: bool,
+}
+
+impl XmlWriter {
+    fn new() -> XmlWriter {
+        XmlWriter {
+            out: String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"),
+            ..XmlWriter::default()
+        }
+    }
+
+    fn close_tag(&mut self) {
+        if self.in_tag {
+            self.out.push('>');
+            self.in_tag = false;
+        }
+    }
+
+    fn start(&mut self, name: &str) {
+        if self.in_tag {
+            self.out.push_str(">\n");
+            self.in_tag = false;
+        }
+
+        self.indent(self.open.len());
+        self.out.push('<');
+        self.out.push_str(name);
+        self.open.push(name.to_string());
+        self.in_tag = true;
+        self.has_text = false;
+    }
+
+    fn attribute<T: Display>(&mut self, name: &str, value: T) {
+        let _ = write!(self.out, " {}=\"", name);
+        escape(&mut self.out, &value.to_string(), true);
+        self.out.push('"');
+    }
+
+    fn text(&mut self, text: &str) {
+        self.close_tag();
+        escape(&mut self.out, text, false);
+        self.has_text = true;
+    }
+
+    fn end(&mut self) {
+        let name = self.open.pop().unwrap_or_default();
+
+        if self.in_tag {
+            self.out.push_str("/>\n");
+            self.in_tag = false;
+        } else {
+            if !self.has_text {
+                self.indent(self.open.len());
+            }
+
+            let _ = writeln!(self.out, "</{}>", name);
+        }
+
+        self.has_text = false;
+    }
+
+    fn indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            self.out.push(' ');
+        }
+    }
+
+    /// The number of elements that are open.
+    fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(out: &mut String, text: &str, attribute: bool) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            '\n' if attribute => out.push_str("&#10;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Adds the attributes kept in `extra`: everything but text and child elements.
+#[cfg(feature = "preserve-unknown")]
+fn extra_attributes(xml: &mut XmlWriter, extra: &crate::extra::Extra) {
+    for (name, value) in extra.iter() {
+        match value {
+            Value::String(value) if name != "_" => xml.attribute(name, value),
+            Value::Bool(value) => xml.attribute(name, u8::from(*value)),
+            Value::Number(value) => xml.attribute(name, value),
+            _ => {}
+        }
+    }
+}
+
+/// Writes an element kept in `extra`, in the shape the XML reader keeps it: an object holding its attributes, text (as `_`) and children, grouped by name into arrays.
+#[cfg(feature = "preserve-unknown")]
+fn extra_element(xml: &mut XmlWriter, name: &str, element: &Value) {
+    let object = match element {
+        Value::Object(object) => object,
+        _ => return,
+    };
+
+    xml.start(name);
+
+    for (name, value) in object {
+        match value {
+            Value::String(value) if name != "_" => xml.attribute(name, value),
+            Value::Bool(value) => xml.attribute(name, u8::from(*value)),
+            Value::Number(value) => xml.attribute(name, value),
+            _ => {}
+        }
+    }
+
+    for (name, value) in object {
+        if let Value::Array(children) = value {
+            for child in children {
+                extra_element(xml, name, child);
+            }
+        }
+    }
+
+    if let Some(Value::String(text)) = object.get("_") {
+        xml.text(text);
+    }
+
+    xml.end();
+}
+
+/// Adds the arrays of objects kept in `extra` from JSON and Lua as child elements, before the modelled children.
+#[cfg(feature = "preserve-unknown")]
+fn extra_arrays(xml: &mut XmlWriter, extra: &crate::extra::Extra) {
+    for (name, value) in extra.iter() {
+        if let Value::Array(children) = value {
+            for child in children {
+                extra_element(xml, name, child);
+            }
+        }
+    }
+}
+
+/// Writes the child elements kept in `extra` in between the modelled children, at the positions they were read at.
+#[cfg(feature = "preserve-unknown")]
+struct ExtraChildren<'a> {
+    children: core::iter::Peekable<core::slice::Iter<'a, crate::extra::Child>>,
+    /// The number of modelled children written so far.
+    position: usize,
+}
+
+#[cfg(feature = "preserve-unknown")]
+impl<'a> ExtraChildren<'a> {
+    fn new(extra: &'a crate::extra::Extra) -> ExtraChildren<'a> {
+        ExtraChildren {
+            children: extra.children.iter().peekable(),
+            position: 0,
+        }
+    }
+
+    /// Writes the children that come before the modelled child about to be written.
+    fn before(&mut self, xml: &mut XmlWriter) {
+        let position = self.position;
+
+        while let Some(child) = self.children.next_if(|child| child.position <= position) {
+            extra_element(xml, &child.name, &child.element);
+        }
+
+        self.position += 1;
+    }
+
+    /// Writes the children that come after the last modelled child.
+    fn rest(self, xml: &mut XmlWriter) {
+        for child in self.children {
+            extra_element(xml, &child.name, &child.element);
+        }
+    }
+}
+
+fn orientation_name(orientation: &Orientation) -> &'static str {
+    match orientation {
+        Orientation::Orthogonal => "orthogonal",
+        Orientation::Isometric => "isometric",
+        Orientation::Staggered { .. } => "staggered",
+        Orientation::Hexagonal { .. } => "hexagonal",
+    }
+}
+
+fn render_order_name(render_order: RenderOrder) -> &'static str {
+    match render_order {
+        RenderOrder::RightDown => "right-down",
+        RenderOrder::RightUp => "right-up",
+        RenderOrder::LeftDown => "left-down",
+        RenderOrder::LeftUp => "left-up",
+    }
+}
+
+fn stagger_axis_name(axis: StaggerAxis) -> &'static str {
+    match axis {
+        StaggerAxis::X => "x",
+        StaggerAxis::Y => "y",
+    }
+}
+
+fn stagger_index_name(index: StaggerIndex) -> &'static str {
+    match index {
+        StaggerIndex::Odd => "odd",
+        StaggerIndex::Even => "even",
+    }
+}
+
+fn encoding_name(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Csv => "csv",
+        Encoding::Base64 => "base64",
+    }
+}
+
+fn compression_name(compression: Compression) -> &'static str {
+    match compression {
+        Compression::Gzip => "gzip",
+        Compression::Zlib => "zlib",
+        Compression::Zstd => "zstd",
+    }
+}
+
+/// The tiles of a layer, decoding lazily decoded chunks and unpacking compact tiles.
+enum Tiles<'a> {
+    Tiles(Cow<'a, [Tile]>),
+    Chunks(Cow<'a, [Chunk]>),
+}
+
+fn tiles(data: &LayerData) -> Result<Tiles<'_>, Error> {
+    Ok(match data {
+        LayerData::Tiles(tiles) => Tiles::Tiles(Cow::Borrowed(tiles)),
+        LayerData::CompactTiles(tiles) => Tiles::Tiles(Cow::Owned(tiles.to_vec())),
+        LayerData::Chunks(chunks) => Tiles::Chunks(Cow::Borrowed(chunks)),
+        LayerData::LazyChunks(chunks) => Tiles::Chunks(Cow::Owned(chunks.to_chunks()?)),
+    })
+}
+
+/// Writes tiles as rows of comma separated GIDs, the way Tiled does: each row on its own line, with a comma after every GID but the last.
+fn csv(tiles: &[Tile], width: usize) -> String {
+    let mut csv = String::from("\n");
+
+    for (i, tile) in tiles.iter().enumerate() {
+        let _ = write!(csv, "{}", tile.gid);
+
+        if i + 1 < tiles.len() {
+            csv.push(',');
+        }
+
+        if width == 0 || (i + 1) % width == 0 || i + 1 == tiles.len() {
+            csv.push('\n');
+        }
+    }
+
+    csv
+}
+
+/// Encodes tiles in base64, compressed as requested. `level` is the map's `compression_level`, used for Zstandard compression.
+pub(crate) fn base64(tiles: &[Tile], format: DataFormat, level: i32) -> Result<String, Error> {
+    #[cfg(feature = "base64-data")]
+    {
+        let data = tiles
+            .iter()
+            .flat_map(|tile| tile.gid.to_le_bytes().to_vec())
+            .collect::<Vec<u8>>();
+        let data = compress(data, format, level)?;
+
+        Ok(base64::encode(&data))
+    }
+    #[cfg(not(feature = "base64-data"))]
+    {
+        let _ = (tiles, level);
+        Err(Error::UnsupportedDataFormat(format))
+    }
+}
+
+#[cfg(feature = "base64-data")]
+#[allow(unused_variables)]
+fn compress(data: Vec<u8>, format: DataFormat, level: i32) -> Result<Vec<u8>, Error> {
+    #[cfg(any(feature = "gzip-data", feature = "zlib-data", feature = "zstd-data"))]
+    use crate::error::DataError;
+    #[cfg(any(feature = "gzip-data", feature = "zlib-data"))]
+    use std::io::Write;
+
+    match format.compression {
+        None => Ok(data),
+        #[cfg(feature = "gzip-data")]
+        Some(Compression::Gzip) => {
+            let mut encoder =
+                libflate::gzip::Encoder::new(Vec::new()).map_err(DataError::Compression)?;
+            encoder.write_all(&data).map_err(DataError::Compression)?;
+            Ok(encoder
+                .finish()
+                .into_result()
+                .map_err(DataError::Compression)?)
+        }
+        #[cfg(feature = "zlib-data")]
+        Some(Compression::Zlib) => {
+            let mut encoder =
+                libflate::zlib::Encoder::new(Vec::new()).map_err(DataError::Compression)?;
+            encoder.write_all(&data).map_err(DataError::Compression)?;
+            Ok(encoder
+                .finish()
+                .into_result()
+                .map_err(DataError::Compression)?)
+        }
+        #[cfg(feature = "zstd-data")]
+        Some(Compression::Zstd) => {
+            // Tiled uses -1 for the default level, zstd uses 0.
+            Ok(zstd::stream::encode_all(data.as_slice(), level.max(0))
+                .map_err(DataError::Compression)?)
+        }
+        #[allow(unreachable_patterns)]
+        Some(_) => Err(Error::UnsupportedDataFormat(format)),
+    }
+}
+
+/// Checks that tile data can be written in `format`.
+pub(crate) fn check_format(format: DataFormat) -> Result<(), Error> {
+    match (format.encoding, format.compression) {
+        (Some(Encoding::Base64), _) | (_, None) => Ok(()),
+        _ => Err(Error::UnsupportedDataFormat(format)),
+    }
+}
+
+/// Writes the text of a `<data>` or `<chunk>` element, or its `<tile>` elements if it has no encoding.
+fn xml_data(
+    xml: &mut XmlWriter,
+    tiles: &[Tile],
+    width: u32,
+    format: DataFormat,
+    level: i32,
+) -> Result<(), Error> {
+    match format.encoding {
+        None => {
+            for tile in tiles {
+                xml.start("tile");
+
+                if tile.gid != 0 {
+                    xml.attribute("gid", tile.gid);
+                }
+
+                xml.end();
+            }
+        }
+        Some(Encoding::Csv) => xml.text(&csv(tiles, width as usize)),
+        Some(Encoding::Base64) => {
+            // Indented one level deeper than the element, which ends on a line of its own.
+            let depth = xml.depth();
+            let text = format!(
+                "\n{:indent$}{}\n{:outdent$}",
+                "",
+                base64(tiles, format, level)?,
+                "",
+                indent = depth,
+                outdent = depth - 1
+            );
+            xml.text(&text);
+        }
+    }
+
+    Ok(())
+}
+
+fn xml_layer(xml: &mut XmlWriter, layer: &Layer, level: i32) -> Result<(), Error> {
+    check_format(layer.format)?;
+
+    xml.start("layer");
+    xml.attribute("id", layer.id);
+
+    if !layer.name.is_empty() {
+        xml.attribute("name", &layer.name);
+    }
+
+    if layer.x != 0 {
+        xml.attribute("x", layer.x);
+    }
+
+    if layer.y != 0 {
+        xml.attribute("y", layer.y);
+    }
+
+    xml.attribute("width", layer.width);
+    xml.attribute("height", layer.height);
+
+    if !layer.visible {
+        xml.attribute("visible", 0);
+    }
+
+    if layer.locked {
+        xml.attribute("locked", 1);
+    }
+
+    if layer.opacity != 1.0 {
+        xml.attribute("opacity", layer.opacity);
+    }
+
+    if layer.offset_x != 0.0 || layer.offset_y != 0.0 {
+        xml.attribute("offsetx", layer.offset_x);
+        xml.attribute("offsety", layer.offset_y);
+    }
+
+    #[cfg(feature = "preserve-unknown")]
+    let mut extra = {
+        extra_attributes(xml, &layer.extra);
+        extra_arrays(xml, &layer.extra);
+        ExtraChildren::new(&layer.extra)
+    };
+
+    #[cfg(feature = "preserve-unknown")]
+    extra.before(xml);
+
+    xml.start("data");
+
+    if let Some(encoding) = layer.format.encoding {
+        xml.attribute("encoding", encoding_name(encoding));
+    }
+
+    if let Some(compression) = layer.format.compression {
+        xml.attribute("compression", compression_name(compression));
+    }
+
+    match tiles(&layer.data)? {
+        Tiles::Tiles(tiles) => {
+            xml_data(xml, &tiles, layer.width.max(0) as u32, layer.format, level)?
+        }
+        Tiles::Chunks(chunks) => {
+            for chunk in chunks.iter() {
+                xml.start("chunk");
+                xml.attribute("x", chunk.x);
+                xml.attribute("y", chunk.y);
+                xml.attribute("width", chunk.width);
+                xml.attribute("height", chunk.height);
+                xml_data(xml, &chunk.data, chunk.width, layer.format, level)?;
+                xml.end();
+            }
+        }
+    }
+
+    xml.end();
+
+    #[cfg(feature = "preserve-unknown")]
+    extra.rest(xml);
+
+    xml.end();
+
+    Ok(())
+}
+
+fn xml_image(xml: &mut XmlWriter, image: &Image) {
+    xml.start("image");
+    xml.attribute("source", &image.source);
+
+    if let Some(trans) = &image.transparent_color {
+        xml.attribute("trans", trans);
+    }
+
+    xml.attribute("width", image.width);
+    xml.attribute("height", image.height);
+    xml.end();
+}
+
+fn xml_tile(xml: &mut XmlWriter, tile: &tileset::Tile) {
+    xml.start("tile");
+    xml.attribute("id", tile.id);
+
+    if !tile.r#type.is_empty() {
+        xml.attribute("type", &tile.r#type);
+    }
+
+    #[cfg(feature = "preserve-unknown")]
+    let mut extra = {
+        extra_attributes(xml, &tile.extra);
+        extra_arrays(xml, &tile.extra);
+        ExtraChildren::new(&tile.extra)
+    };
+
+    if !tile.animation.is_empty() {
+        #[cfg(feature = "preserve-unknown")]
+        extra.before(xml);
+
+        xml.start("animation");
+
+        for frame in &tile.animation {
+            xml.start("frame");
+            xml.attribute("tileid", frame.tileid);
+            xml.attribute("duration", frame.duration.as_millis());
+            xml.end();
+        }
+
+        xml.end();
+    }
+
+    #[cfg(feature = "preserve-unknown")]
+    extra.rest(xml);
+
+    xml.end();
+}
+
+/// Writes the attributes and children of a tileset, after `firstgid` (embedded tilesets) or the metadata (tileset files).
+fn xml_tileset(xml: &mut XmlWriter, tileset: &Tileset) {
+    xml.attribute("name", &tileset.name);
+    xml.attribute("tilewidth", tileset.tile_width);
+    xml.attribute("tileheight", tileset.tile_height);
+
+    if tileset.spacing != 0 {
+        xml.attribute("spacing", tileset.spacing);
+    }
+
+    if tileset.margin != 0 {
+        xml.attribute("margin", tileset.margin);
+    }
+
+    xml.attribute("tilecount", tileset.tile_count);
+    xml.attribute("columns", tileset.columns);
+
+    if let Some(color) = &tileset.background_color {
+        xml.attribute("backgroundcolor", color);
+    }
+
+    #[cfg(feature = "preserve-unknown")]
+    let mut extra = {
+        extra_attributes(xml, &tileset.extra);
+        extra_arrays(xml, &tileset.extra);
+        ExtraChildren::new(&tileset.extra)
+    };
+
+    #[cfg(feature = "preserve-unknown")]
+    extra.before(xml);
+
+    xml_image(xml, &tileset.image);
+
+    for tile in &tileset.tiles {
+        #[cfg(feature = "preserve-unknown")]
+        extra.before(xml);
+
+        xml_tile(xml, tile);
+    }
+
+    #[cfg(feature = "preserve-unknown")]
+    extra.rest(xml);
+}
+
+fn xml_map_tileset(xml: &mut XmlWriter, tileset: &map::Tileset) {
+    xml.start("tileset");
+    xml.attribute("firstgid", tileset.first_gid);
+
+    match &tileset.kind {
+        TilesetKind::Embedded(tileset) => xml_tileset(xml, tileset),
+        TilesetKind::External { source, .. } => xml.attribute("source", source),
+    }
+
+    xml.end();
+}
+
+pub(crate) fn map_to_xml(map: &Map) -> Result<String, Error> {
+    let mut xml = XmlWriter::new();
+
+    xml.start("map");
+    xml.attribute("version", &map.metadata.version);
+    xml.attribute("tiledversion", &map.metadata.tiled_version);
+    xml.attribute("orientation", orientation_name(&map.orientation));
+    xml.attribute("renderorder", render_order_name(map.render_order));
+
+    if map.compression_level != -1 {
+        xml.attribute("compressionlevel", map.compression_level);
+    }
+
+    xml.attribute("width", map.width);
+    xml.attribute("height", map.height);
+    xml.attribute("tilewidth", map.tile_width);
+    xml.attribute("tileheight", map.tile_height);
+    xml.attribute("infinite", u8::from(map.infinite));
+
+    match map.orientation {
+        Orientation::Hexagonal {
+            hexside_length,
+            stagger_axis,
+            stagger_index,
+        } => {
+            xml.attribute("hexsidelength", hexside_length);
+            xml.attribute("staggeraxis", stagger_axis_name(stagger_axis));
+            xml.attribute("staggerindex", stagger_index_name(stagger_index));
+        }
+        Orientation::Staggered {
+            stagger_axis,
+            stagger_index,
+        } => {
+            xml.attribute("staggeraxis", stagger_axis_name(stagger_axis));
+            xml.attribute("staggerindex", stagger_index_name(stagger_index));
+        }
+        _ => {}
+    }
+
+    if let Some(color) = &map.background_color {
+        xml.attribute("backgroundcolor", color);
+    }
+
+    xml.attribute("nextlayerid", map.next_layer_id);
+    xml.attribute("nextobjectid", map.next_object_id);
+
+    #[cfg(feature = "preserve-unknown")]
+    let mut extra = {
+        extra_attributes(&mut xml, &map.extra);
+        extra_arrays(&mut xml, &map.extra);
+        ExtraChildren::new(&map.extra)
+    };
+
+    for tileset in &map.tilesets {
+        #[cfg(feature = "preserve-unknown")]
+        extra.before(&mut xml);
+
+        xml_map_tileset(&mut xml, tileset);
+    }
+
+    for layer in &map.layers {
+        #[cfg(feature = "preserve-unknown")]
+        extra.before(&mut xml);
+
+        xml_layer(&mut xml, layer, map.compression_level)?;
+    }
+
+    #[cfg(feature = "preserve-unknown")]
+    extra.rest(&mut xml);
+
+    xml.end();
+
+    Ok(xml.finish())
+}
+
+pub(crate) fn tileset_to_xml(tileset: &Tileset) -> Result<String, Error> {
+    let mut xml = XmlWriter::new();
+
+    xml.start("tileset");
+
+    if let Some(metadata) = &tileset.metadata {
+        xml.attribute("version", &metadata.version);
+        xml.attribute("tiledversion", &metadata.tiled_version);
+    }
+
+    xml_tileset(&mut xml, tileset);
+    xml.end();
+
+    Ok(xml.finish())
+}
+
+/// A number as Tiled writes it, without a fractional part if it is a whole number.
+fn number(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+        Value::from(value as i64)
+    } else {
+        Value::from(value)
+    }
+}
+
+/// Converts properties kept from XML (`<properties>` holding `<property>` elements) into the array Tiled writes in JSON, with values of the declared type. Properties kept from JSON or Lua are already in that shape.
+#[cfg(feature = "preserve-unknown")]
+fn json_properties(properties: &Value) -> Value {
+    fn elements<'a>(value: &'a Value, name: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(move |element| element.get(name))
+            .filter_map(Value::as_array)
+            .flatten()
+    }
+
+    fn value(property: &Value) -> Value {
+        let text = property
+            .get("value")
+            .or_else(|| property.get("_"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        match property.get("type").and_then(Value::as_str) {
+            Some("int") | Some("object") => {
+                text.parse::<i64>().map_or(Value::from(text), Value::from)
+            }
+            Some("float") => text.parse::<f64>().map_or(Value::from(text), number),
+            Some("bool") => Value::Bool(text == "true"),
+            Some("class") => Value::Object(
+                elements(
+                    property.get("properties").unwrap_or(&Value::Null),
+                    "property",
+                )
+                .filter_map(|member| {
+                    let name = member.get("name")?.as_str()?;
+                    Some((name.to_string(), value(member)))
+                })
+                .collect(),
+            ),
+            _ => Value::from(text),
+        }
+    }
+
+    let xml = properties
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|element| element.get("property").is_some());
+
+    if !xml {
+        return properties.clone();
+    }
+
+    Value::Array(
+        elements(properties, "property")
+            .map(|property| {
+                let mut json = serde_json::Map::new();
+                json.insert(
+                    "name".to_string(),
+                    property.get("name").cloned().unwrap_or_default(),
+                );
+                json.insert(
+                    "type".to_string(),
+                    property
+                        .get("type")
+                        .cloned()
+                        .unwrap_or_else(|| Value::from("string")),
+                );
+
+                if let Some(property_type) = property.get("propertytype") {
+                    json.insert("propertytype".to_string(), property_type.clone());
+                }
+
+                json.insert("value".to_string(), value(property));
+
+                Value::Object(json)
+            })
+            .collect(),
+    )
+}
+
+/// Adds the attributes and child elements kept in `extra` to the JSON object of an element, before the modelled fields, which take precedence.
+///
+/// Child elements are grouped by name into arrays, as the XML reader reads them.
+#[cfg(feature = "preserve-unknown")]
+fn json_extra(object: &mut serde_json::Map<String, Value>, extra: &crate::extra::Extra) {
+    let mut children = BTreeMap::<&str, Vec<Value>>::new();
+
+    for child in &extra.children {
+        children
+            .entry(&child.name)
+            .or_default()
+            .push(child.element.clone());
+    }
+
+    let children = children
+        .into_iter()
+        .map(|(name, elements)| (name, Value::Array(elements)));
+
+    for (key, value) in extra
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.clone()))
+        .chain(children)
+    {
+        match key {
+            "_" => {}
+            "properties" => {
+                object.insert(key.to_string(), json_properties(&value));
+            }
+            _ => {
+                object.insert(key.to_string(), value);
+            }
+        }
+    }
+}
+
+/// Tiled writes colors in JSON with a leading `#`, which TMX files may leave out.
+fn json_color(color: &str) -> Value {
+    if color.starts_with('#') {
+        Value::from(color)
+    } else {
+        Value::from(format!("#{}", color))
+    }
+}
+
+/// The tile data of a layer or chunk: an array of GIDs, or a base64 string if the layer is encoded in base64.
+fn json_data(tiles: &[Tile], format: DataFormat, level: i32) -> Result<Value, Error> {
+    Ok(match format.encoding {
+        Some(Encoding::Base64) => Value::from(base64(tiles, format, level)?),
+        // Without an encoding (`<tile>` elements), the tiles are written the way Tiled writes them in JSON.
+        _ => Value::Array(tiles.iter().map(|tile| Value::from(tile.gid)).collect()),
+    })
+}
+
+fn json_layer(layer: &Layer, level: i32) -> Result<Value, Error> {
+    check_format(layer.format)?;
+
+    let mut object = serde_json::Map::new();
+
+    #[cfg(feature = "preserve-unknown")]
+    json_extra(&mut object, &layer.extra);
+
+    match tiles(&layer.data)? {
+        Tiles::Tiles(tiles) => {
+            object.insert("data".to_string(), json_data(&tiles, layer.format, level)?);
+        }
+        Tiles::Chunks(chunks) => {
+            let mut json_chunks = Vec::with_capacity(chunks.len());
+
+            for chunk in chunks.iter() {
+                let mut json_chunk = serde_json::Map::new();
+                json_chunk.insert(
+                    "data".to_string(),
+                    json_data(&chunk.data, layer.format, level)?,
+                );
+                json_chunk.insert("height".to_string(), Value::from(chunk.height));
+                json_chunk.insert("width".to_string(), Value::from(chunk.width));
+                json_chunk.insert("x".to_string(), Value::from(chunk.x));
+                json_chunk.insert("y".to_string(), Value::from(chunk.y));
+                json_chunks.push(Value::Object(json_chunk));
+            }
+
+            let start_x = chunks.iter().map(|chunk| chunk.x).min().unwrap_or(0);
+            let start_y = chunks.iter().map(|chunk| chunk.y).min().unwrap_or(0);
+
+            object.insert("chunks".to_string(), Value::Array(json_chunks));
+            object.insert("startx".to_string(), Value::from(start_x));
+            object.insert("starty".to_string(), Value::from(start_y));
+        }
+    }
+
+    // CSV is the default in JSON, so only base64 is named.
+    if let Some(Encoding::Base64) = layer.format.encoding {
+        object.insert("encoding".to_string(), Value::from("base64"));
+
+        if let Some(compression) = layer.format.compression {
+            object.insert(
+                "compression".to_string(),
+                Value::from(compression_name(compression)),
+            );
+        }
+    }
+
+    object.insert("height".to_string(), Value::from(layer.height));
+    object.insert("id".to_string(), Value::from(layer.id));
+
+    if layer.locked {
+        object.insert("locked".to_string(), Value::Bool(true));
+    }
+
+    object.insert("name".to_string(), Value::from(layer.name.as_str()));
+
+    if layer.offset_x != 0.0 || layer.offset_y != 0.0 {
+        object.insert("offsetx".to_string(), number(layer.offset_x));
+        object.insert("offsety".to_string(), number(layer.offset_y));
+    }
+
+    object.insert("opacity".to_string(), number(layer.opacity));
+    object.insert("type".to_string(), Value::from("tilelayer"));
+    object.insert("visible".to_string(), Value::Bool(layer.visible));
+    object.insert("width".to_string(), Value::from(layer.width));
+    object.insert("x".to_string(), Value::from(layer.x));
+    object.insert("y".to_string(), Value::from(layer.y));
+
+    Ok(Value::Object(object))
+}
+
+fn json_tile(tile: &tileset::Tile) -> Value {
+    let mut object = serde_json::Map::new();
+
+    #[cfg(feature = "preserve-unknown")]
+    json_extra(&mut object, &tile.extra);
+
+    if !tile.animation.is_empty() {
+        let frames = tile
+            .animation
+            .iter()
+            .map(|frame| {
+                let mut object = serde_json::Map::new();
+                object.insert(
+                    "duration".to_string(),
+                    Value::from(frame.duration.as_millis() as u64),
+                );
+                object.insert("tileid".to_string(), Value::from(frame.tileid));
+                Value::Object(object)
+            })
+            .collect();
+
+        object.insert("animation".to_string(), Value::Array(frames));
+    }
+
+    object.insert("id".to_string(), Value::from(tile.id));
+
+    if !tile.r#type.is_empty() {
+        object.insert("type".to_string(), Value::from(tile.r#type.as_str()));
+    }
+
+    Value::Object(object)
+}
+
+/// The JSON object of a tileset, without the `firstgid` (embedded tilesets) or the metadata and type (tileset files).
+fn json_tileset(tileset: &Tileset) -> serde_json::Map<String, Value> {
+    let mut object = serde_json::Map::new();
+
+    #[cfg(feature = "preserve-unknown")]
+    json_extra(&mut object, &tileset.extra);
+
+    if let Some(color) = &tileset.background_color {
+        object.insert("backgroundcolor".to_string(), json_color(color));
+    }
+
+    object.insert("columns".to_string(), Value::from(tileset.columns));
+    object.insert(
+        "image".to_string(),
+        Value::from(tileset.image.source.as_str()),
+    );
+    object.insert("imageheight".to_string(), Value::from(tileset.image.height));
+    object.insert("imagewidth".to_string(), Value::from(tileset.image.width));
+    object.insert("margin".to_string(), Value::from(tileset.margin));
+    object.insert("name".to_string(), Value::from(tileset.name.as_str()));
+    object.insert("spacing".to_string(), Value::from(tileset.spacing));
+    object.insert("tilecount".to_string(), Value::from(tileset.tile_count));
+    object.insert("tileheight".to_string(), Value::from(tileset.tile_height));
+
+    if !tileset.tiles.is_empty() {
+        object.insert(
+            "tiles".to_string(),
+            Value::Array(tileset.tiles.iter().map(json_tile).collect()),
+        );
+    }
+
+    object.insert("tilewidth".to_string(), Value::from(tileset.tile_width));
+
+    if let Some(color) = &tileset.image.transparent_color {
+        object.insert("transparentcolor".to_string(), json_color(color));
+    }
+
+    object
+}
+
+fn json_map_tileset(tileset: &map::Tileset) -> Value {
+    let mut object = match &tileset.kind {
+        TilesetKind::Embedded(tileset) => json_tileset(tileset),
+        TilesetKind::External { source, .. } => {
+            let mut object = serde_json::Map::new();
+            object.insert("source".to_string(), Value::from(source.as_str()));
+            object
+        }
+    };
+
+    object.insert("firstgid".to_string(), Value::from(tileset.first_gid));
+
+    Value::Object(object)
+}
+
+/// Writes a JSON document indented the way Tiled indents it.
+///
+/// Members of the document are on lines of their own, indented by one space, except for the first, which follows the opening brace. Objects nested in an object start on the line after their key, three spaces further in, and objects in arrays start seven spaces further in than the key of the array. Arrays of anything else are written on one line.
+fn tiled_json(document: &serde_json::Map<String, Value>) -> String {
+    fn key(out: &mut String, key: &str) {
+        out.push_str(&Value::from(key).to_string());
+        out.push(':');
+    }
+
+    fn object(out: &mut String, object: &serde_json::Map<String, Value>, indent: usize) {
+        out.push('{');
+
+        for (i, (name, value)) in object.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+
+            let _ = write!(out, "\n{:indent$}", "", indent = indent + 1);
+            key(out, name);
+            member(out, value, indent + 1);
+        }
+
+        let _ = write!(out, "\n{:indent$}}}", "", indent = indent);
+    }
+
+    /// Writes the value of a member whose key is at `indent`.
+    fn member(out: &mut String, value: &Value, indent: usize) {
+        match value {
+            Value::Object(value) => {
+                let _ = write!(out, "\n{:indent$}", "", indent = indent + 3);
+                object(out, value, indent + 3);
+            }
+            Value::Array(values) if values.iter().any(Value::is_object) => {
+                out.push_str("[\n");
+
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", \n");
+                    }
+
+                    let _ = write!(out, "{:indent$}", "", indent = indent + 7);
+
+                    match value {
+                        Value::Object(value) => object(out, value, indent + 7),
+                        value => out.push_str(&value.to_string()),
+                    }
+                }
+
+                out.push(']');
+            }
+            Value::Array(values) => {
+                out.push('[');
+
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+
+                    out.push_str(&value.to_string());
+                }
+
+                out.push(']');
+            }
+            value => out.push_str(&value.to_string()),
+        }
+    }
+
+    let mut out = String::from("{ ");
+
+    for (i, (name, value)) in document.iter().enumerate() {
+        if i > 0 {
+            out.push_str(",\n ");
+        }
+
+        key(&mut out, name);
+        member(&mut out, value, 1);
+    }
+
+    out.push_str("\n}");
+
+    out
+}
+
+pub(crate) fn map_to_json(map: &Map) -> Result<String, Error> {
+    let mut object = serde_json::Map::new();
+
+    #[cfg(feature = "preserve-unknown")]
+    json_extra(&mut object, &map.extra);
+
+    if let Some(color) = &map.background_color {
+        object.insert("backgroundcolor".to_string(), json_color(color));
+    }
+
+    object.insert(
+        "compressionlevel".to_string(),
+        Value::from(map.compression_level),
+    );
+    object.insert("height".to_string(), Value::from(map.height));
+
+    match map.orientation {
+        Orientation::Hexagonal {
+            hexside_length,
+            stagger_axis,
+            stagger_index,
+        } => {
+            object.insert("hexsidelength".to_string(), Value::from(hexside_length));
+            object.insert(
+                "staggeraxis".to_string(),
+                Value::from(stagger_axis_name(stagger_axis)),
+            );
+            object.insert(
+                "staggerindex".to_string(),
+                Value::from(stagger_index_name(stagger_index)),
+            );
+        }
+        Orientation::Staggered {
+            stagger_axis,
+            stagger_index,
+        } => {
+            object.insert(
+                "staggeraxis".to_string(),
+                Value::from(stagger_axis_name(stagger_axis)),
+            );
+            object.insert(
+                "staggerindex".to_string(),
+                Value::from(stagger_index_name(stagger_index)),
+            );
+        }
+        _ => {}
+    }
+
+    object.insert("infinite".to_string(), Value::Bool(map.infinite));
+    object.insert(
+        "layers".to_string(),
+        Value::Array(
+            map.layers
+                .iter()
+                .map(|layer| json_layer(layer, map.compression_level))
+                .collect::<Result<_, _>>()?,
+        ),
+    );
+    object.insert("nextlayerid".to_string(), Value::from(map.next_layer_id));
+    object.insert("nextobjectid".to_string(), Value::from(map.next_object_id));
+    object.insert(
+        "orientation".to_string(),
+        Value::from(orientation_name(&map.orientation)),
+    );
+    object.insert(
+        "renderorder".to_string(),
+        Value::from(render_order_name(map.render_order)),
+    );
+    object.insert(
+        "tiledversion".to_string(),
+        Value::from(map.metadata.tiled_version.as_str()),
+    );
+    object.insert("tileheight".to_string(), Value::from(map.tile_height));
+    object.insert(
+        "tilesets".to_string(),
+        Value::Array(map.tilesets.iter().map(json_map_tileset).collect()),
+    );
+    object.insert("tilewidth".to_string(), Value::from(map.tile_width));
+    object.insert("type".to_string(), Value::from("map"));
+    object.insert(
+        "version".to_string(),
+        Value::from(map.metadata.version.as_str()),
+    );
+    object.insert("width".to_string(), Value::from(map.width));
+
+    Ok(tiled_json(&object))
+}
+
+pub(crate) fn tileset_to_json(tileset: &Tileset) -> Result<String, Error> {
+    let mut object = json_tileset(tileset);
+
+    if let Some(metadata) = &tileset.metadata {
+        object.insert(
+            "tiledversion".to_string(),
+            Value::from(metadata.tiled_version.as_str()),
+        );
+        object.insert(
+            "version".to_string(),
+            Value::from(metadata.version.as_str()),
+        );
+    }
+
+    object.insert("type".to_string(), Value::from("tileset"));
+
+    Ok(tiled_json(&object))
+}
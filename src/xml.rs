@@ -0,0 +1,816 @@
+use crate::{
+    context,
+    options::{self, ParseLimits},
+};
+
+use quick_xml::{
+    events::{attributes::Attributes, BytesStart, Event as XmlEvent},
+    Reader,
+};
+use serde::{
+    de::{
+        self,
+        value::{BorrowedStrDeserializer, CowStrDeserializer},
+        DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize,
+};
+use serde_json::{Map, Value};
+use std::{borrow::Cow, fmt, io::BufRead, mem};
+
+/// Elements are deserialized recursively, so nesting is limited to keep malformed documents from overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/// The elements whose position is recorded for the location of errors (see `context::parse`).
+const TRACKED: [&str; 5] = ["map", "tileset", "layer", "data", "chunk"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to parse string")]
+    ParseString(#[from] std::string::FromUtf8Error),
+    #[error("failed to deserialize XML")]
+    Xml(#[from] quick_xml::Error),
+    #[error("unexpected EOF")]
+    UnexpectedEof,
+    #[error("{0}")]
+    TooDeep(String),
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+fn attributes_to_json<B: BufRead>(
+    reader: &Reader<B>,
+    attributes: Attributes,
+) -> Result<Map<String, Value>, Error> {
+    let mut map = Map::new();
+
+    for attribute in attributes {
+        let attribute = attribute?;
+        map.insert(
+            String::from_utf8(attribute.key.to_vec())?,
+            Value::String(attribute.unescape_and_decode_value(reader)?),
+        );
+    }
+
+    Ok(map)
+}
+
+/// Records where a tracked element starts, returning the `name[index]` path to it if its parent was tracked too.
+fn track(parent: Option<&str>, name: &str, index: usize, offset: usize) -> Option<String> {
+    let parent = parent?;
+
+    if !TRACKED.contains(&name) {
+        return None;
+    }
+
+    let path = if parent.is_empty() {
+        format!("{}[{}]", name, index)
+    } else {
+        format!("{}/{}[{}]", parent, name, index)
+    };
+    context::element(path.clone(), offset);
+
+    Some(path)
+}
+
+/// Checks that an element nested `depth` levels deep is within `max_depth` and `MAX_DEPTH`.
+///
+/// Every element is checked as it is read, including those that are skipped.
+fn check_depth(depth: usize, limits: &ParseLimits) -> Result<(), Error> {
+    limits.check_depth(depth).map_err(Error::TooDeep)?;
+
+    if depth > MAX_DEPTH {
+        return Err(Error::TooDeep(format!(
+            "nested more than {} levels deep",
+            MAX_DEPTH
+        )));
+    }
+
+    Ok(())
+}
+
+/// The start of an element.
+struct Start<'de> {
+    name: Cow<'de, str>,
+    attributes: Vec<(Cow<'de, str>, Cow<'de, str>)>,
+    /// The byte offset of the `<`.
+    offset: usize,
+}
+
+/// The names of the children of an element in document order, as runs of the same name.
+type Order<'de> = Vec<(Cow<'de, str>, usize)>;
+
+/// The parts of a document that are deserialized, with empty elements read as a start and an end.
+///
+/// The end of an element holds the order of its children with `preserve-unknown`, so that unknown children can be written back in place.
+enum Event<'de> {
+    Start(Start<'de>),
+    Text(Cow<'de, str>),
+    End(#[cfg_attr(not(feature = "preserve-unknown"), allow(dead_code))] Order<'de>),
+    Eof,
+}
+
+/// Where a document is read from.
+trait Source<'de> {
+    fn read_event<'b>(&mut self, buf: &'b mut Vec<u8>) -> quick_xml::Result<XmlEvent<'b>>;
+
+    /// The number of bytes read so far.
+    fn position(&self) -> usize;
+
+    /// Returns `part` of the bytes `read` from offset `start` of the document.
+    fn string(&self, start: usize, read: &[u8], part: &[u8]) -> Result<Cow<'de, str>, Error>;
+}
+
+/// A document that is already in memory, so that names and text are borrowed from it where possible.
+struct StrSource<'de> {
+    document: &'de str,
+    reader: Reader<&'de [u8]>,
+}
+
+impl<'de> Source<'de> for StrSource<'de> {
+    fn read_event<'b>(&mut self, buf: &'b mut Vec<u8>) -> quick_xml::Result<XmlEvent<'b>> {
+        self.reader.read_event(buf)
+    }
+
+    fn position(&self) -> usize {
+        self.reader.buffer_position()
+    }
+
+    /// Events are read into a buffer, so this is how names and values are borrowed from the document rather than copied again.
+    fn string(&self, start: usize, read: &[u8], part: &[u8]) -> Result<Cow<'de, str>, Error> {
+        let start = start + (part.as_ptr() as usize - read.as_ptr() as usize);
+        Ok(Cow::Borrowed(&self.document[start..start + part.len()]))
+    }
+}
+
+/// A document read from a reader, so that names and text are copied.
+struct ReadSource<R: BufRead> {
+    reader: Reader<R>,
+}
+
+impl<'de, R: BufRead> Source<'de> for ReadSource<R> {
+    fn read_event<'b>(&mut self, buf: &'b mut Vec<u8>) -> quick_xml::Result<XmlEvent<'b>> {
+        self.reader.read_event(buf)
+    }
+
+    fn position(&self) -> usize {
+        self.reader.buffer_position()
+    }
+
+    fn string(&self, _start: usize, _read: &[u8], part: &[u8]) -> Result<Cow<'de, str>, Error> {
+        Ok(Cow::Owned(String::from_utf8(part.to_vec())?))
+    }
+}
+
+/// Reads the events of a document in one pass.
+struct Deserializer<'de, S> {
+    source: S,
+    buf: Vec<u8>,
+    /// An event that was read but not used, to be read again.
+    pending: Option<Event<'de>>,
+    /// How deeply the last event read from `source` is nested, the document itself is 0.
+    depth: usize,
+    /// Whether the last element read from `source` was empty, so that it ends next.
+    empty: bool,
+    /// The order of the children read so far of each element that hasn't ended, starting with the document.
+    order: Vec<Order<'de>>,
+    limits: ParseLimits,
+}
+
+impl<'de, S: Source<'de>> Deserializer<'de, S> {
+    fn new(source: S, limits: ParseLimits) -> Deserializer<'de, S> {
+        Deserializer {
+            source,
+            buf: Vec::new(),
+            pending: None,
+            depth: 0,
+            empty: false,
+            order: vec![Vec::new()],
+            limits,
+        }
+    }
+
+    /// Records where the document is malformed.
+    fn malformed<E: Into<Error>>(&self, error: E) -> Error {
+        context::error_offset(self.source.position());
+        error.into()
+    }
+
+    /// Returns the next event, reading it from the source unless it is pending.
+    fn next(&mut self) -> Result<Event<'de>, Error> {
+        if let Some(event) = self.pending.take() {
+            return Ok(event);
+        }
+
+        if self.empty {
+            self.empty = false;
+            return Ok(self.end());
+        }
+
+        let mut buf = mem::take(&mut self.buf);
+        let event = self.read(&mut buf);
+        buf.clear();
+        self.buf = buf;
+
+        event
+    }
+
+    fn read(&mut self, buf: &mut Vec<u8>) -> Result<Event<'de>, Error> {
+        loop {
+            let offset = self.source.position();
+            let event = match self.source.read_event(buf) {
+                Ok(event) => event,
+                Err(e) => return Err(self.malformed(e)),
+            };
+            let empty = matches!(event, XmlEvent::Empty(_));
+
+            let event = match event {
+                XmlEvent::Start(e) | XmlEvent::Empty(e) => {
+                    self.depth += 1;
+                    self.empty = empty;
+                    check_depth(self.depth, &self.limits).map_err(|e| self.malformed(e))?;
+                    let start = self.start(offset, &e)?;
+                    self.enter(&start);
+                    Some(Event::Start(start))
+                }
+                XmlEvent::Text(e) => Some(Event::Text(
+                    match e.unescaped().map_err(|e| self.malformed(e))? {
+                        Cow::Borrowed(_) => self.source.string(offset, e.escaped(), e.escaped())?,
+                        Cow::Owned(text) => Cow::Owned(String::from_utf8(text)?),
+                    },
+                )),
+                XmlEvent::End(_) => Some(self.end()),
+                XmlEvent::Eof if self.depth > 0 => return Err(self.malformed(Error::UnexpectedEof)),
+                XmlEvent::Eof => Some(Event::Eof),
+                _ => None,
+            };
+
+            match event {
+                Some(event) => return Ok(event),
+                None => buf.clear(),
+            }
+        }
+    }
+
+    /// Adds the element that was just read to the order of its parent's children.
+    fn enter(&mut self, start: &Start<'de>) {
+        if cfg!(feature = "preserve-unknown") {
+            if let Some(order) = self.order.last_mut() {
+                match order.last_mut() {
+                    Some((last, count)) if *last == start.name => *count += 1,
+                    _ => order.push((start.name.clone(), 1)),
+                }
+            }
+        }
+
+        self.order.push(Vec::new());
+    }
+
+    /// Ends the element that was read last.
+    fn end(&mut self) -> Event<'de> {
+        self.depth -= 1;
+        Event::End(self.order.pop().unwrap_or_default())
+    }
+
+    /// Reads the name and attributes of the tag at `offset`.
+    fn start(&self, offset: usize, tag: &BytesStart) -> Result<Start<'de>, Error> {
+        // The tag is read without its `<`.
+        let start = offset + 1;
+        let attributes = tag
+            .attributes()
+            .map(|attribute| {
+                let attribute = attribute?;
+                let key = self.source.string(start, tag, attribute.key)?;
+                let value = match (attribute.unescaped_value()?, &attribute.value) {
+                    (Cow::Borrowed(_), Cow::Borrowed(value)) => {
+                        self.source.string(start, tag, value)?
+                    }
+                    (value, _) => Cow::Owned(String::from_utf8(value.into_owned())?),
+                };
+
+                Ok((key, value))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Start {
+            name: self.source.string(start, tag, tag.name())?,
+            attributes,
+            offset,
+        })
+    }
+
+    /// Skips the rest of an element whose start was just read.
+    fn skip(&mut self) -> Result<(), Error> {
+        let mut nested = 0;
+
+        loop {
+            match self.next()? {
+                Event::Start(_) => nested += 1,
+                Event::End(_) if nested == 0 => return Ok(()),
+                Event::End(_) => nested -= 1,
+                Event::Text(_) => {}
+                Event::Eof => return Err(Error::UnexpectedEof),
+            }
+        }
+    }
+}
+
+/// An element whose start has been read, or the document itself (whose children are the root elements).
+///
+/// Elements are deserialized in the same shape as the JSON format: a map of the attributes, the text (as `_`) and the children, with each run of consecutive children of the same name as a sequence. Children are read as they are deserialized, so ignored elements are skipped over and text is borrowed from the document where possible.
+///
+/// Nothing is read ahead, so children of the same name that are split up by other elements (e.g. the layers of a map, between object groups) come as an entry per run, and the types that hold them add each run to the elements read before (see `crate::de::Intercept`).
+///
+/// With `preserve-unknown`, elements with children end with an entry holding their order (see `crate::extra::ORDER`).
+struct Element<'a, 'de, S> {
+    de: &'a mut Deserializer<'de, S>,
+    start: Option<Start<'de>>,
+    /// The `name[index]` path to the element, if it and all of its ancestors are tracked.
+    path: Option<String>,
+}
+
+impl<'a, 'de, S: Source<'de>> Element<'a, 'de, S> {
+    fn entries(self) -> Entries<'a, 'de, S> {
+        let attributes = self.start.map_or_else(Vec::new, |start| start.attributes);
+        // Attributes take precedence over children of the same name.
+        let mut shadowed = attributes
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        shadowed.push(Cow::Borrowed("_"));
+
+        Entries {
+            de: self.de,
+            path: self.path,
+            attributes: attributes.into_iter(),
+            shadowed,
+            seen: Vec::new(),
+            text: false,
+            done: false,
+            value: None,
+        }
+    }
+}
+
+impl<'a, 'de, S: Source<'de>> de::Deserializer<'de> for Element<'a, 'de, S> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut entries = self.entries();
+        let value = visitor.visit_map(&mut entries)?;
+        entries.finish()?;
+
+        Ok(value)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.entries().finish()?;
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum
+        identifier
+    }
+}
+
+enum Entry<'de> {
+    Text(Cow<'de, str>),
+    /// A run of children, the index into `Entries::seen` of their name.
+    Children(usize),
+    /// The order of the children, see `crate::extra::ORDER`.
+    #[cfg(feature = "preserve-unknown")]
+    Order(Order<'de>),
+}
+
+fn key<'de, K: DeserializeSeed<'de>>(seed: K, key: Cow<'de, str>) -> Result<K::Value, Error> {
+    match key {
+        Cow::Borrowed(key) => seed.deserialize(BorrowedStrDeserializer::new(key)),
+        Cow::Owned(key) => seed.deserialize(key.into_deserializer()),
+    }
+}
+
+/// What has been read of the children of an element with a given name.
+struct Seen<'de> {
+    name: Cow<'de, str>,
+    /// The number of children read, to continue counting them in the next run.
+    count: usize,
+    /// Whether a single child was expected rather than a sequence, so that the rest are skipped.
+    single: bool,
+}
+
+/// The entries of an element, see `Element`.
+struct Entries<'a, 'de, S> {
+    de: &'a mut Deserializer<'de, S>,
+    path: Option<String>,
+    attributes: std::vec::IntoIter<(Cow<'de, str>, Cow<'de, str>)>,
+    /// The names of the attributes and the text, children with these names are skipped.
+    shadowed: Vec<Cow<'de, str>>,
+    /// The names of the children found so far.
+    seen: Vec<Seen<'de>>,
+    /// Whether the text was found.
+    text: bool,
+    /// Whether the end of the element was read.
+    done: bool,
+    value: Option<Entry<'de>>,
+}
+
+impl<'a, 'de, S: Source<'de>> Entries<'a, 'de, S> {
+    /// Skips whatever is left of the element once it has been deserialized.
+    fn finish(&mut self) -> Result<(), Error> {
+        while !self.done {
+            match self.de.next()? {
+                Event::Start(_) => self.de.skip()?,
+                Event::Text(_) => {}
+                Event::End(_) | Event::Eof => self.done = true,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, 'de, S: Source<'de>> MapAccess<'de> for Entries<'a, 'de, S> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if let Some((name, value)) = self.attributes.next() {
+            self.value = Some(Entry::Text(value));
+            return key(seed, name).map(Some);
+        }
+
+        while !self.done {
+            match self.de.next()? {
+                Event::Text(text) if !self.text && !text.trim().is_empty() => {
+                    self.text = true;
+                    self.value = Some(Entry::Text(text));
+                    return key(seed, Cow::Borrowed("_")).map(Some);
+                }
+                Event::Text(_) => {}
+                Event::Start(start) if self.shadowed.contains(&start.name) => self.de.skip()?,
+                Event::Start(start) => {
+                    let index = match self.seen.iter().position(|seen| seen.name == start.name) {
+                        Some(index) if self.seen[index].single => {
+                            self.de.skip()?;
+                            continue;
+                        }
+                        Some(index) => index,
+                        None => {
+                            self.seen.push(Seen {
+                                name: start.name.clone(),
+                                count: 0,
+                                single: false,
+                            });
+                            self.seen.len() - 1
+                        }
+                    };
+                    let name = start.name.clone();
+                    self.de.pending = Some(Event::Start(start));
+                    self.value = Some(Entry::Children(index));
+                    return key(seed, name).map(Some);
+                }
+                #[cfg(feature = "preserve-unknown")]
+                Event::End(order) if !order.is_empty() => {
+                    self.done = true;
+                    self.value = Some(Entry::Order(order));
+                    return key(seed, Cow::Borrowed(crate::extra::ORDER)).map(Some);
+                }
+                Event::End(_) | Event::Eof => self.done = true,
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.value.take() {
+            Some(Entry::Text(text)) => seed.deserialize(Text(text)),
+            Some(Entry::Children(index)) => seed.deserialize(Children {
+                de: &mut *self.de,
+                parent: self.path.clone(),
+                seen: &mut self.seen[index],
+            }),
+            #[cfg(feature = "preserve-unknown")]
+            Some(Entry::Order(order)) => seed
+                .deserialize(Value::from(
+                    order
+                        .into_iter()
+                        .map(|(name, count)| {
+                            Value::from(vec![Value::from(name), Value::from(count)])
+                        })
+                        .collect::<Vec<_>>(),
+                ))
+                .map_err(de::Error::custom),
+            None => Err(de::Error::custom("value is missing")),
+        }
+    }
+}
+
+/// A run of children of an element with the same name, starting with the one to be read next, deserialized as a sequence, or as the first one where a single element is expected.
+struct Children<'a, 'de, S> {
+    de: &'a mut Deserializer<'de, S>,
+    parent: Option<String>,
+    seen: &'a mut Seen<'de>,
+}
+
+impl<'a, 'de, S: Source<'de>> Children<'a, 'de, S> {
+    /// The next child, whose `start` was just read.
+    fn element(&mut self, start: Start<'de>) -> Element<'_, 'de, S> {
+        let index = self.seen.count;
+        self.seen.count += 1;
+        let path = track(self.parent.as_deref(), &self.seen.name, index, start.offset);
+
+        Element {
+            de: &mut *self.de,
+            start: Some(start),
+            path,
+        }
+    }
+
+    fn first(self) -> Result<Element<'a, 'de, S>, Error> {
+        self.seen.single = true;
+
+        match self.de.next()? {
+            Event::Start(start) => {
+                let index = self.seen.count;
+                self.seen.count += 1;
+                let path = track(self.parent.as_deref(), &self.seen.name, index, start.offset);
+
+                Ok(Element {
+                    de: self.de,
+                    start: Some(start),
+                    path,
+                })
+            }
+            _ => Err(de::Error::custom("expected an element")),
+        }
+    }
+}
+
+impl<'a, 'de, S: Source<'de>> de::Deserializer<'de> for Children<'a, 'de, S> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Elements {
+            children: self,
+            done: false,
+        })
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.first()?.deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.first()?.deserialize_any(visitor)
+    }
+
+    /// Skips the first child, and the rest along with anything else that isn't deserialized.
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.seen.single = true;
+        self.de.next()?;
+        self.de.skip()?;
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct enum identifier
+    }
+}
+
+/// A run of children of an element with the same name as a sequence, ending before the first sibling with another name.
+struct Elements<'a, 'de, S> {
+    children: Children<'a, 'de, S>,
+    /// Whether the run has ended.
+    done: bool,
+}
+
+impl<'a, 'de, S: Source<'de>> SeqAccess<'de> for Elements<'a, 'de, S> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        while !self.done {
+            match self.children.de.next()? {
+                Event::Start(start) if start.name == self.children.seen.name => {
+                    return seed.deserialize(self.children.element(start)).map(Some);
+                }
+                Event::Text(text) if text.trim().is_empty() => {}
+                event => {
+                    self.children.de.pending = Some(event);
+                    self.done = true;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.trim().parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+/// An attribute value or the text of an element, parsed as a number or boolean where one is expected.
+struct Text<'de>(Cow<'de, str>);
+
+impl<'de> de::Deserializer<'de> for Text<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Cow::Borrowed(text) => visitor.visit_borrowed_str(text),
+            Cow::Owned(text) => visitor.visit_string(text),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.0.trim() {
+            "1" | "true" => visitor.visit_bool(true),
+            "0" | "false" => visitor.visit_bool(false),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(CowStrDeserializer::new(self.0))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier
+    }
+}
+
+/// Deserializes an XML document, as a map of its root elements.
+pub fn from_str<'de, T: Deserialize<'de>>(xml: &'de str) -> Result<T, Error> {
+    let source = StrSource {
+        document: xml,
+        reader: Reader::from_str(xml),
+    };
+    let mut de = Deserializer::new(source, options::current().limits);
+
+    T::deserialize(Element {
+        de: &mut de,
+        start: None,
+        path: Some(String::new()),
+    })
+}
+
+/// Deserializes an XML document read from `reader`, as a map of its root elements.
+pub fn from_reader<R: BufRead, T: DeserializeOwned>(reader: R) -> Result<T, Error> {
+    let source = ReadSource {
+        reader: Reader::from_reader(reader),
+    };
+    let mut de = Deserializer::new(source, options::current().limits);
+
+    T::deserialize(Element {
+        de: &mut de,
+        start: None,
+        path: Some(String::new()),
+    })
+}
+
+/// Converts the attributes of the `<map>` element and of its layer (`<layer>`, `<objectgroup>`, `<imagelayer>` and `<group>`) and `<tileset>` children to JSON, skipping the contents of everything else (in particular the layer data and the layers inside groups).
+pub fn peek_header(xml: &str) -> Result<Value, Error> {
+    let mut buf = vec![];
+    let mut skip_buf = vec![];
+    let mut reader = Reader::from_str(xml);
+
+    let mut map = None;
+    let mut layers = vec![];
+    let mut tilesets = vec![];
+
+    loop {
+        let (start, e) = match reader.read_event(&mut buf)? {
+            XmlEvent::Start(e) => (true, e),
+            XmlEvent::Empty(e) => (false, e),
+            XmlEvent::End(ref e) if e.name() == b"map" => break,
+            XmlEvent::Eof => return Err(Error::UnexpectedEof),
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+
+        let name = e.name().to_vec();
+
+        match name.as_slice() {
+            b"map" if map.is_none() => {
+                map = Some(attributes_to_json(&reader, e.attributes())?);
+
+                if !start {
+                    break;
+                }
+
+                buf.clear();
+                continue;
+            }
+            b"layer" | b"objectgroup" | b"imagelayer" | b"group" => {
+                layers.push(Value::Object(attributes_to_json(&reader, e.attributes())?))
+            }
+            b"tileset" => {
+                tilesets.push(Value::Object(attributes_to_json(&reader, e.attributes())?))
+            }
+            _ => {}
+        }
+
+        if start {
+            reader.read_to_end(&name, &mut skip_buf)?;
+            skip_buf.clear();
+        }
+
+        buf.clear();
+    }
+
+    let mut map = map.ok_or(Error::UnexpectedEof)?;
+    map.insert("layers".to_string(), Value::Array(layers));
+    map.insert("tilesets".to_string(), Value::Array(tilesets));
+
+    Ok(Value::Object(map))
+}
@@ -1,3 +1,38 @@
+/// Writes the map as XML and parses it again, checking that nothing was lost.
+///
+/// Unknown attributes read from JSON and Lua keep their types (e.g. numbers), which XML can't, so those maps are only checked without `preserve-unknown`.
+#[cfg(feature = "xml")]
+fn assert_xml_round_trip(map: &tmx::Map) {
+    let xml = map.to_xml().unwrap();
+    assert_eq!(&tmx::Map::from_xml(&xml).unwrap(), map, "{}", xml);
+}
+
+/// Writes the tileset as XML and parses it again, checking that nothing was lost.
+#[cfg(feature = "xml")]
+fn assert_tileset_xml_round_trip(tileset: &tmx::Tileset) {
+    let xml = tileset.to_xml().unwrap();
+    assert_eq!(&tmx::Tileset::from_xml(&xml).unwrap(), tileset, "{}", xml);
+}
+
+/// Writes the map as JSON and parses it again, checking that nothing was lost.
+///
+/// Unknown attributes read from XML are strings, which Tiled writes in JSON as values of their own type, so XML maps are only checked without `preserve-unknown`.
+fn assert_json_round_trip(map: &tmx::Map) {
+    let json = map.to_json().unwrap();
+    assert_eq!(&tmx::Map::from_json(&json).unwrap(), map, "{}", json);
+}
+
+/// Writes the tileset as JSON and parses it again, checking that nothing was lost.
+fn assert_tileset_json_round_trip(tileset: &tmx::Tileset) {
+    let json = tileset.to_json().unwrap();
+    assert_eq!(
+        &tmx::Tileset::from_json(&json).unwrap(),
+        tileset,
+        "{}",
+        json
+    );
+}
+
 #[cfg(feature = "xml")]
 #[test]
 fn test_xml() {
@@ -32,6 +67,7 @@ fn test_xml() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -74,6 +110,7 @@ fn test_xml_animation() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -98,6 +135,9 @@ fn test_xml_csv() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -119,6 +159,9 @@ fn test_xml_base64() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -140,6 +183,9 @@ fn test_xml_gzip() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -161,6 +207,9 @@ fn test_xml_zlib() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -182,6 +231,9 @@ fn test_xml_zstd() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -220,6 +272,7 @@ fn test_xml_chunks() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -246,6 +299,9 @@ fn test_xml_chunks_csv() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -269,6 +325,9 @@ fn test_xml_chunks_base64() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -292,6 +351,9 @@ fn test_xml_chunks_gzip() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -315,6 +377,9 @@ fn test_xml_chunks_zlib() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -338,6 +403,9 @@ fn test_xml_chunks_zstd() {
     "##;
 
     let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
     println!("xml: {:?}", tmx);
 }
 
@@ -393,6 +461,9 @@ fn test_json() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -457,6 +528,9 @@ fn test_json_animation() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -509,6 +583,9 @@ fn test_json_base64() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -562,6 +639,9 @@ fn test_json_gzip() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -615,6 +695,9 @@ fn test_json_zlib() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -668,6 +751,9 @@ fn test_json_zstd() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -734,6 +820,9 @@ fn test_json_chunks() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -802,6 +891,9 @@ fn test_json_chunks_base64() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -871,6 +963,9 @@ fn test_json_chunks_gzip() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -940,6 +1035,9 @@ fn test_json_chunks_zlib() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
@@ -1009,9 +1107,13 @@ fn test_json_chunks_zstd() {
     "##;
 
     let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
     println!("json: {:?}", tmx);
 }
 
+#[cfg(feature = "xml")]
 #[test]
 fn test_xml_tileset() {
     let tileset = r##"
@@ -1038,6 +1140,9 @@ fn test_xml_tileset() {
     "##;
 
     let tileset = tmx::Tileset::from_xml(tileset).unwrap();
+    assert_tileset_xml_round_trip(&tileset);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_tileset_json_round_trip(&tileset);
     println!("tileset: {:?}", tileset);
 }
 
@@ -1126,5 +1231,3247 @@ fn test_json_tileset() {
     "##;
 
     let tileset = tmx::Tileset::from_json(tileset).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_tileset_xml_round_trip(&tileset);
+    assert_tileset_json_round_trip(&tileset);
+    println!("tileset: {:?}", tileset);
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn test_lua() {
+    let map = r##"
+    return {
+      version = "1.2",
+      luaversion = "5.1",
+      tiledversion = "1.3.3",
+      orientation = "orthogonal",
+      renderorder = "right-down",
+      width = 4,
+      height = 4,
+      tilewidth = 16,
+      tileheight = 16,
+      nextlayerid = 2,
+      nextobjectid = 1,
+      properties = {},
+      tilesets = {
+        {
+          name = "test",
+          firstgid = 1,
+          tilewidth = 16,
+          tileheight = 16,
+          spacing = 0,
+          margin = 0,
+          columns = 16,
+          image = "tiles16.png",
+          imagewidth = 256,
+          imageheight = 256,
+          tileoffset = {
+            x = 0,
+            y = 0
+          },
+          grid = {
+            orientation = "orthogonal",
+            width = 16,
+            height = 16
+          },
+          properties = {},
+          terrains = {},
+          tilecount = 256,
+          tiles = {
+            {
+              id = 0,
+              type = "Tile",
+              animation = {
+                {
+                  tileid = 0,
+                  duration = 100
+                },
+                {
+                  tileid = 1,
+                  duration = 100
+                }
+              }
+            }
+          }
+        }
+      },
+      layers = {
+        {
+          type = "tilelayer",
+          id = 1,
+          name = "Tile Layer 1",
+          x = 0,
+          y = 0,
+          width = 4,
+          height = 4,
+          visible = true,
+          opacity = 1,
+          offsetx = 0,
+          offsety = 0,
+          properties = {},
+          encoding = "lua",
+          data = {
+            1, 2684354561, 1, 2147483649,
+            1610612737, 3221225473, 1073741825, 3221225473,
+            2147483649, 3758096385, 1073741825, 536870913,
+            536870913, 1073741825, 3758096385, 2147483649
+          }
+        }
+      }
+    }
+    "##;
+
+    let json = r##"
+    { "height":4,
+    "infinite":false,
+    "layers":[
+           {
+            "data":[1, 2684354561, 1, 2147483649, 1610612737, 3221225473, 1073741825, 3221225473, 2147483649, 3758096385, 1073741825, 536870913, 536870913, 1073741825, 3758096385, 2147483649],
+            "height":4,
+            "id":1,
+            "name":"Tile Layer 1",
+            "opacity":1,
+            "type":"tilelayer",
+            "visible":true,
+            "width":4,
+            "x":0,
+            "y":0
+           }],
+    "nextlayerid":2,
+    "nextobjectid":1,
+    "orientation":"orthogonal",
+    "renderorder":"right-down",
+    "tiledversion":"1.3.3",
+    "tileheight":16,
+    "tilesets":[
+           {
+            "columns":16,
+            "firstgid":1,
+            "image":"tiles16.png",
+            "imageheight":256,
+            "imagewidth":256,
+            "margin":0,
+            "name":"test",
+            "spacing":0,
+            "tilecount":256,
+            "tileheight":16,
+            "tiles":[
+                   {
+                    "animation":[
+                        {
+                         "duration":100,
+                         "tileid":0
+                        },
+                        {
+                         "duration":100,
+                         "tileid":1
+                        }],
+                    "id":0,
+                    "type":"Tile"
+                   }],
+            "tilewidth":16
+           }],
+    "tilewidth":16,
+    "type":"map",
+    "version":1.2,
+    "width":4
+   }
+    "##;
+
+    let tmx = tmx::Map::from_lua(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
+    println!("lua: {:?}", tmx);
+
+    // The Lua export writes a few extra keys (`luaversion`, empty `properties`) that would be preserved.
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_eq!(tmx, tmx::Map::from_json(json).unwrap());
+    #[cfg(feature = "preserve-unknown")]
+    let _ = json;
+}
+
+#[cfg(all(feature = "lua", feature = "zlib-data"))]
+#[test]
+fn test_lua_zlib() {
+    let map = r##"
+    -- Exported from Tiled
+    return {
+      version = "1.2",
+      luaversion = "5.1",
+      tiledversion = "1.3.3",
+      orientation = "orthogonal",
+      renderorder = "right-down",
+      width = 4,
+      height = 4,
+      tilewidth = 16,
+      tileheight = 16,
+      nextlayerid = 2,
+      nextobjectid = 1,
+      properties = {},
+      tilesets = {
+        {
+          name = "test",
+          firstgid = 1,
+          tilewidth = 16,
+          tileheight = 16,
+          spacing = 0,
+          margin = 0,
+          columns = 16,
+          image = "tiles16.png",
+          imagewidth = 256,
+          imageheight = 256,
+          properties = {},
+          tilecount = 256,
+          tiles = {}
+        }
+      },
+      layers = {
+        {
+          type = "tilelayer",
+          id = 1,
+          name = "Tile Layer 1",
+          x = 0,
+          y = 0,
+          width = 4,
+          height = 4,
+          visible = true,
+          opacity = 1,
+          offsetx = 0,
+          offsety = 0,
+          properties = {},
+          encoding = "base64",
+          compression = "zlib",
+          data = "eJxjZGBgYGRgWMAIoRuAOAGIDwCxA5QGiT2A8hWg2AEq1gAAxKAG0Q=="
+        }
+      }
+    }
+    "##;
+
+    let tmx = tmx::Map::from_lua(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
+    println!("lua: {:?}", tmx);
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn test_lua_chunks() {
+    let map = r##"
+    return {
+      version = "1.2",
+      luaversion = "5.1",
+      tiledversion = "1.3.3",
+      orientation = "orthogonal",
+      renderorder = "right-down",
+      width = 4,
+      height = 4,
+      tilewidth = 16,
+      tileheight = 16,
+      nextlayerid = 2,
+      nextobjectid = 1,
+      properties = {},
+      tilesets = {
+        {
+          name = "test",
+          firstgid = 1,
+          tilewidth = 16,
+          tileheight = 16,
+          spacing = 0,
+          margin = 0,
+          columns = 16,
+          image = "tiles16.png",
+          imagewidth = 256,
+          imageheight = 256,
+          properties = {},
+          tilecount = 256,
+          tiles = {}
+        }
+      },
+      layers = {
+        {
+          type = "tilelayer",
+          id = 1,
+          name = "Tile Layer 1",
+          x = 0,
+          y = 0,
+          width = 4,
+          height = 4,
+          visible = true,
+          opacity = 1,
+          offsetx = 0,
+          offsety = 0,
+          properties = {},
+          encoding = "lua",
+          chunks = {
+            {
+              x = 0, y = 0, width = 2, height = 2,
+              data = {
+                1, 2684354561,
+                1610612737, 3221225473
+              }
+            },
+            {
+              x = 2, y = 0, width = 2, height = 2,
+              data = {
+                1, 2147483649,
+                1073741825, 3221225473
+              }
+            }
+          }
+        }
+      }
+    }
+    "##;
+
+    let tmx = tmx::Map::from_lua(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
+    println!("lua: {:?}", tmx);
+
+    match &tmx.layers[0].data {
+        tmx::layer::LayerData::Chunks(chunks) => assert_eq!(chunks.len(), 2),
+        data => panic!("expected chunks, got {:?}", data),
+    }
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn test_lua_tileset() {
+    let tileset = r##"
+    return {
+      version = "1.2",
+      luaversion = "5.1",
+      tiledversion = "1.3.3",
+      name = "tiles16",
+      tilewidth = 16,
+      tileheight = 16,
+      spacing = 0,
+      margin = 0,
+      columns = 16,
+      image = "tiles16.png",
+      imagewidth = 256,
+      imageheight = 256,
+      properties = {},
+      tilecount = 256,
+      tiles = {
+        { id = 0, type = "Solid" },
+        { id = 1, type = "Solid" },
+        { id = 3, ["type"] = 'OneWay' },
+        { id = 4, type = "Caf\195\169 \xc3\xa9 \u{e9}" }
+      }
+    }
+    "##;
+
+    let tileset = tmx::Tileset::from_lua(tileset).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_tileset_xml_round_trip(&tileset);
+    assert_tileset_json_round_trip(&tileset);
     println!("tileset: {:?}", tileset);
+
+    assert_eq!(tileset.tiles.len(), 4);
+    assert_eq!(tileset.tiles[2].r#type, "OneWay");
+    // Byte escapes are bytes of a UTF-8 string.
+    assert_eq!(tileset.tiles[3].r#type, "Café é é");
+
+    let invalid = r##"return { name = "\195", tilewidth = 16 }"##;
+    match tmx::Tileset::from_lua(invalid) {
+        Err(error @ tmx::error::Error::LuaConversion(_)) => assert_eq!(
+            std::error::Error::source(&error).unwrap().to_string(),
+            "string at byte 16 is not valid UTF-8"
+        ),
+        result => panic!("expected invalid UTF-8, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_json_external_tilesets() {
+    let map = r##"
+    { "height":4,
+    "infinite":false,
+    "layers":[
+           {
+            "data":[1, 2, 257, 258, 1, 2, 257, 258, 1, 2, 257, 258, 1, 2, 257, 258],
+            "height":4,
+            "id":1,
+            "name":"Tile Layer 1",
+            "opacity":1,
+            "type":"tilelayer",
+            "visible":true,
+            "width":4,
+            "x":0,
+            "y":0
+           }],
+    "nextlayerid":2,
+    "nextobjectid":1,
+    "orientation":"orthogonal",
+    "renderorder":"right-down",
+    "tiledversion":"1.8.0",
+    "tileheight":16,
+    "tilesets":[
+           {
+            "firstgid":1,
+            "source":"tiles16.tsx"
+           },
+           {
+            "firstgid":257,
+            "source":"tiles16.tsj"
+           },
+           {
+            "firstgid":513,
+            "source":"tiles16.tileset"
+           }],
+    "tilewidth":16,
+    "type":"map",
+    "version":"1.8",
+    "width":4
+   }
+    "##;
+
+    let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
+    println!("json: {:?}", tmx);
+
+    let formats = tmx
+        .tilesets
+        .iter()
+        .map(|tileset| tileset.kind.format())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        formats,
+        vec![Some(tmx::Format::Xml), Some(tmx::Format::Json), None]
+    );
+}
+
+#[test]
+fn test_format_from_extension() {
+    use tmx::Format;
+
+    assert_eq!(
+        Format::from_extension("../tilesets/Terrain.TSX"),
+        Some(Format::Xml)
+    );
+    assert_eq!(
+        Format::from_extension("tiles.v2/tiles.tsj"),
+        Some(Format::Json)
+    );
+    assert_eq!(Format::from_extension("export.lua"), Some(Format::Lua));
+    assert_eq!(Format::from_extension("tiles.v2/tiles"), None);
+    assert_eq!(Format::from_extension(".tsx"), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_tileset_from_source_data() {
+    let json = br##"
+    { "columns":16,
+    "image":"tiles16.png",
+    "imageheight":256,
+    "imagewidth":256,
+    "margin":0,
+    "name":"tiles16",
+    "spacing":0,
+    "tilecount":256,
+    "tiledversion":"1.8.0",
+    "tileheight":16,
+    "tilewidth":16,
+    "type":"tileset",
+    "version":"1.8"
+   }
+    "##;
+
+    assert_eq!(tmx::Format::sniff(json), Some(tmx::Format::Json));
+
+    let tileset = tmx::Tileset::from_source_data("tiles16.tsj", json).unwrap();
+    assert_eq!(
+        tmx::Tileset::from_source_data("tiles16.tileset", json).unwrap(),
+        tileset
+    );
+    assert!(tmx::Tileset::from_source_data("tiles16.tileset", b"tiles16").is_err());
+
+    #[cfg(feature = "xml")]
+    {
+        let xml = br##"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <tileset version="1.8" tiledversion="1.8.0" name="tiles16" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+         <image source="tiles16.png" width="256" height="256"/>
+        </tileset>
+        "##;
+
+        assert_eq!(tmx::Format::sniff(xml), Some(tmx::Format::Xml));
+        assert_eq!(
+            tmx::Tileset::from_source_data("tiles16.tsx", xml).unwrap(),
+            tileset
+        );
+        assert_eq!(tmx::Tileset::from_data(xml).unwrap(), tileset);
+    }
+}
+
+#[cfg(all(feature = "xml", feature = "preserve-unknown"))]
+#[test]
+fn test_xml_preserve_unknown() {
+    let map = r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <map version="1.2" tiledversion="1.3.3" orientation="staggered" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" staggeraxis="y" staggerindex="odd" infinite="0" nextlayerid="2" nextobjectid="1" class="Level">
+     <editorsettings>
+      <export target="." format="json"/>
+     </editorsettings>
+     <tileset firstgid="1" name="test" tilewidth="16" tileheight="16" tilecount="256" columns="16" objectalignment="topleft">
+      <image source="tiles16.png" width="256" height="256"/>
+      <tile id="0" type="Tile" probability="0.5">
+       <properties>
+        <property name="solid" type="bool" value="true"/>
+       </properties>
+      </tile>
+     </tileset>
+     <layer id="1" name="Tile Layer 1" width="4" height="4" tintcolor="#ff0000">
+      <data encoding="csv">
+    1,2684354561,1,2147483649,
+    1610612737,3221225473,1073741825,3221225473,
+    2147483649,3758096385,1073741825,536870913,
+    536870913,1073741825,3758096385,2147483649
+    </data>
+     </layer>
+    </map>
+    "##;
+
+    let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+    #[cfg(not(feature = "preserve-unknown"))]
+    assert_json_round_trip(&tmx);
+    println!("xml: {:?}", tmx);
+
+    assert_eq!(
+        tmx.extra
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>(),
+        vec!["class"]
+    );
+    assert_eq!(
+        tmx.extra
+            .children
+            .iter()
+            .map(|child| (child.name.as_str(), child.position))
+            .collect::<Vec<_>>(),
+        vec![("editorsettings", 0)]
+    );
+    assert_eq!(tmx.extra.get("class"), Some(&serde_json::json!("Level")));
+    assert_eq!(
+        tmx.layers[0].extra.get("tintcolor"),
+        Some(&serde_json::json!("#ff0000"))
+    );
+
+    let tileset = match &tmx.tilesets[0].kind {
+        tmx::map::TilesetKind::Embedded(tileset) => tileset,
+        kind => panic!("expected embedded tileset, got {:?}", kind),
+    };
+    assert_eq!(
+        tileset
+            .extra
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>(),
+        vec!["objectalignment"]
+    );
+    assert_eq!(
+        tileset.tiles[0]
+            .extra
+            .elements("properties")
+            .collect::<Vec<_>>(),
+        vec![&serde_json::json!({
+            "property": [{ "name": "solid", "type": "bool", "value": "true" }]
+        })]
+    );
+}
+
+#[cfg(all(feature = "xml", feature = "preserve-unknown"))]
+#[test]
+fn test_xml_preserve_unknown_order() {
+    let map = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" tiledversion="1.3.3" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="4" nextobjectid="1">
+ <tileset firstgid="1" name="test" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+  <tileoffset x="0" y="4"/>
+  <image source="tiles16.png" width="256" height="256"/>
+  <tile id="0" type="Tile"/>
+  <wangsets>
+   <wangset name="Ground" tile="-1"/>
+  </wangsets>
+ </tileset>
+ <layer id="1" name="Ground" width="2" height="2">
+  <properties>
+   <property name="solid" type="bool" value="true"/>
+  </properties>
+  <data encoding="csv">
+1,2,
+3,4
+</data>
+ </layer>
+ <objectgroup id="2" name="Objects"/>
+ <layer id="3" name="Above" width="2" height="2">
+  <data encoding="csv">
+1,2,
+3,4
+</data>
+ </layer>
+</map>
+"##;
+
+    let tmx = tmx::Map::from_xml(map).unwrap();
+    assert_xml_round_trip(&tmx);
+
+    let children = |extra: &tmx::extra::Extra| {
+        extra
+            .children
+            .iter()
+            .map(|child| (child.name.clone(), child.position))
+            .collect::<Vec<_>>()
+    };
+    let tileset = match &tmx.tilesets[0].kind {
+        tmx::map::TilesetKind::Embedded(tileset) => tileset,
+        kind => panic!("expected embedded tileset, got {:?}", kind),
+    };
+
+    assert_eq!(children(&tmx.extra), vec![("objectgroup".to_string(), 2)]);
+    assert_eq!(
+        children(&tileset.extra),
+        vec![("tileoffset".to_string(), 0), ("wangsets".to_string(), 2)]
+    );
+    assert_eq!(
+        children(&tmx.layers[0].extra),
+        vec![("properties".to_string(), 0)]
+    );
+
+    let xml = tmx.to_xml().unwrap();
+    let position = |tag: &str| xml.find(tag).unwrap();
+    assert!(position("<tileoffset") < position("<image"));
+    assert!(position("<tile id") < position("<wangsets"));
+    assert!(position("<properties") < position("<data"));
+    assert!(position("name=\"Ground\" width") < position("<objectgroup"));
+    assert!(position("<objectgroup") < position("name=\"Above\""));
+}
+
+#[cfg(feature = "preserve-unknown")]
+#[test]
+fn test_json_preserve_unknown() {
+    let map = r##"
+    { "class":"Level",
+    "height":4,
+    "infinite":false,
+    "layers":[
+           {
+            "data":[1, 2684354561, 1, 2147483649, 1610612737, 3221225473, 1073741825, 3221225473, 2147483649, 3758096385, 1073741825, 536870913, 536870913, 1073741825, 3758096385, 2147483649],
+            "height":4,
+            "id":1,
+            "name":"Tile Layer 1",
+            "opacity":1,
+            "parallaxx":0.5,
+            "type":"tilelayer",
+            "visible":true,
+            "width":4,
+            "x":0,
+            "y":0
+           }],
+    "nextlayerid":2,
+    "nextobjectid":1,
+    "orientation":"orthogonal",
+    "renderorder":"right-down",
+    "tiledversion":"1.3.3",
+    "tileheight":16,
+    "tilesets":[
+           {
+            "columns":16,
+            "firstgid":1,
+            "image":"tiles16.png",
+            "imageheight":256,
+            "imagewidth":256,
+            "margin":0,
+            "name":"test",
+            "spacing":0,
+            "tilecount":256,
+            "tileheight":16,
+            "tilewidth":16
+           }],
+    "tilewidth":16,
+    "type":"map",
+    "version":1.2,
+    "width":4
+   }
+    "##;
+
+    let tmx = tmx::Map::from_json(map).unwrap();
+    #[cfg(all(feature = "xml", not(feature = "preserve-unknown")))]
+    assert_xml_round_trip(&tmx);
+    assert_json_round_trip(&tmx);
+    println!("json: {:?}", tmx);
+
+    assert_eq!(
+        tmx.extra
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>(),
+        vec!["class"]
+    );
+    assert_eq!(
+        tmx.layers[0].extra.get("parallaxx"),
+        Some(&serde_json::json!(0.5))
+    );
+
+    match &tmx.tilesets[0].kind {
+        tmx::map::TilesetKind::Embedded(tileset) => assert!(tileset.extra.is_empty()),
+        kind => panic!("expected embedded tileset, got {:?}", kind),
+    }
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_load_with() {
+    use std::path::{Path, PathBuf};
+    use tmx::loader::{MemoryLoader, ResourceLoader};
+
+    let map = r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+     <tileset firstgid="1" source="../tilesets/terrain.tsx"/>
+     <tileset firstgid="257" source="../tilesets/props.tsj"/>
+     <tileset firstgid="513" name="embedded" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+      <image source="embedded.png" width="256" height="256"/>
+     </tileset>
+     <layer id="1" name="Tile Layer 1" width="2" height="2">
+      <data encoding="csv">
+    1,257,
+    513,0
+    </data>
+     </layer>
+    </map>
+    "##;
+
+    let terrain = r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <tileset version="1.8" tiledversion="1.8.0" name="terrain" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+     <image source="images/terrain.png" width="256" height="256"/>
+    </tileset>
+    "##;
+
+    let props = r##"
+    { "columns":16,
+    "image":"../images/props.png",
+    "imageheight":256,
+    "imagewidth":256,
+    "margin":0,
+    "name":"props",
+    "spacing":0,
+    "tilecount":256,
+    "tiledversion":"1.8.0",
+    "tileheight":16,
+    "tilewidth":16,
+    "type":"tileset",
+    "version":"1.8"
+   }
+    "##;
+
+    let loader = MemoryLoader::new()
+        .with("assets/maps/level.tmx", map)
+        .with("assets/tilesets/terrain.tsx", terrain)
+        .with("assets/tilesets/props.tsj", props);
+
+    let map_path = Path::new("assets/maps/level.tmx");
+    let tmx = tmx::Map::load_with(&loader, map_path).unwrap();
+    println!("xml: {:?}", tmx);
+
+    let names = tmx
+        .tilesets
+        .iter()
+        .map(|tileset| tileset.tileset().unwrap().name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["terrain", "props", "embedded"]);
+
+    let images = tmx
+        .tilesets
+        .iter()
+        .map(|tileset| tileset.image_path(&loader, map_path).unwrap().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        images,
+        vec![
+            PathBuf::from("assets/tilesets/images/terrain.png"),
+            PathBuf::from("assets/images/props.png"),
+            PathBuf::from("assets/maps/embedded.png"),
+        ]
+    );
+
+    assert_eq!(
+        loader
+            .resolve(Path::new("maps/level.tmx"), "./../tilesets/../x.tsx")
+            .unwrap(),
+        PathBuf::from("x.tsx")
+    );
+
+    let mut loader = loader;
+    loader.remove("assets/tilesets/props.tsj");
+
+    match tmx::Map::load_with(&loader, map_path) {
+        Err(tmx::error::Error::Io { path, .. }) => {
+            assert_eq!(path, PathBuf::from("assets/tilesets/props.tsj"))
+        }
+        result => panic!("expected I/O error, got {:?}", result),
+    }
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_from_path() {
+    let dir = std::env::temp_dir().join(format!("tmx-test-from-path-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" source="tiles16.tsj"/>
+ <layer id="1" name="Tile Layer 1" width="2" height="2">
+  <data encoding="csv">
+1,2,
+3,4
+</data>
+ </layer>
+</map>
+"##;
+
+    let json = r##"{ "height":2,
+ "infinite":false,
+ "layers":[
+        {
+         "data":[1, 2, 3, 4],
+         "height":2,
+         "id":1,
+         "name":"Tile Layer 1",
+         "opacity":1,
+         "type":"tilelayer",
+         "visible":true,
+         "width":2,
+         "x":0,
+         "y":0
+        }],
+ "nextlayerid":2,
+ "nextobjectid":1,
+ "orientation":"orthogonal",
+ "renderorder":"right-down",
+ "tiledversion":"1.8.0",
+ "tileheight":16,
+ "tilesets":[
+        {
+         "firstgid":1,
+         "source":"tiles16.tsj"
+        }],
+ "tilewidth":16,
+ "type":"map",
+ "version":"1.8",
+ "width":2
+}
+"##;
+
+    let tileset = r##"{ "columns":16,
+ "image":"tiles16.png",
+ "imageheight":256,
+ "imagewidth":256,
+ "margin":0,
+ "name":"tiles16",
+ "spacing":0,
+ "tilecount":256,
+ "tiledversion":"1.8.0",
+ "tileheight":16,
+ "tilewidth":16,
+ "type":"tileset",
+ "version":"1.8"
+}
+"##;
+
+    std::fs::write(dir.join("level.tmx"), xml).unwrap();
+    std::fs::write(dir.join("level.tmj"), json).unwrap();
+    std::fs::write(dir.join("level.map"), json).unwrap();
+    std::fs::write(dir.join("tiles16.tsj"), tileset).unwrap();
+
+    let tmx = tmx::Map::from_path(dir.join("level.tmx")).unwrap();
+    println!("xml: {:?}", tmx);
+
+    assert_eq!(tmx::Map::from_path(dir.join("level.tmj")).unwrap(), tmx);
+    assert_eq!(tmx::Map::from_path(dir.join("level.map")).unwrap(), tmx);
+    assert_eq!(
+        tmx::Tileset::from_path(dir.join("tiles16.tsj"))
+            .unwrap()
+            .name,
+        "tiles16"
+    );
+
+    let missing = dir.join("missing.tmx");
+    match tmx::Map::from_path(&missing) {
+        Err(tmx::error::Error::Io { path, source }) => {
+            assert_eq!(path, missing);
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        result => panic!("expected I/O error, got {:?}", result),
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A reader that only hands out a few bytes at a time, like a network or decompression stream.
+#[cfg(feature = "xml")]
+struct TrickleReader<'a>(&'a [u8]);
+
+#[cfg(feature = "xml")]
+impl std::io::Read for TrickleReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.0.len()).min(7);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_from_reader() {
+    let xml = r##"
+
+
+    <?xml version="1.0" encoding="UTF-8"?>
+    <map version="1.2" tiledversion="1.3.3" orientation="orthogonal" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+     <tileset firstgid="1" name="test" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+      <image source="tiles16.png" width="256" height="256"/>
+     </tileset>
+     <layer id="1" name="Tile Layer 1" width="4" height="4">
+      <data encoding="csv">
+    1,2684354561,1,2147483649,
+    1610612737,3221225473,1073741825,3221225473,
+    2147483649,3758096385,1073741825,536870913,
+    536870913,1073741825,3758096385,2147483649
+    </data>
+     </layer>
+    </map>
+    "##;
+
+    let json = r##"
+    { "height":4,
+    "infinite":false,
+    "layers":[
+           {
+            "data":[1, 2684354561, 1, 2147483649, 1610612737, 3221225473, 1073741825, 3221225473, 2147483649, 3758096385, 1073741825, 536870913, 536870913, 1073741825, 3758096385, 2147483649],
+            "height":4,
+            "id":1,
+            "name":"Tile Layer 1",
+            "opacity":1,
+            "type":"tilelayer",
+            "visible":true,
+            "width":4,
+            "x":0,
+            "y":0
+           }],
+    "nextlayerid":2,
+    "nextobjectid":1,
+    "orientation":"orthogonal",
+    "renderorder":"right-down",
+    "tiledversion":"1.3.3",
+    "tileheight":16,
+    "tilesets":[
+           {
+            "columns":16,
+            "firstgid":1,
+            "image":"tiles16.png",
+            "imageheight":256,
+            "imagewidth":256,
+            "margin":0,
+            "name":"test",
+            "spacing":0,
+            "tilecount":256,
+            "tileheight":16,
+            "tilewidth":16
+           }],
+    "tilewidth":16,
+    "type":"map",
+    "version":1.2,
+    "width":4
+   }
+    "##;
+
+    let tmx = tmx::Map::from_xml(xml).unwrap();
+
+    assert_eq!(
+        tmx::Map::from_xml_reader(TrickleReader(xml.as_bytes())).unwrap(),
+        tmx
+    );
+    assert_eq!(
+        tmx::Map::from_reader(TrickleReader(xml.as_bytes())).unwrap(),
+        tmx
+    );
+    assert_eq!(
+        tmx::Map::from_json_reader(TrickleReader(json.as_bytes())).unwrap(),
+        tmx
+    );
+    assert_eq!(
+        tmx::Map::from_reader(TrickleReader(json.as_bytes())).unwrap(),
+        tmx
+    );
+
+    // A byte order mark is skipped like `Format::sniff` skips it.
+    for document in [xml, json] {
+        let bom = format!("\u{feff}{}", document);
+        assert_eq!(
+            tmx::Map::from_reader(TrickleReader(bom.as_bytes())).unwrap(),
+            tmx
+        );
+    }
+
+    // Errors are located by path, as the input isn't kept to find lines and columns in.
+    let invalid = xml.replace("1,2684354561,1,", "1,x,1,");
+    let error = tmx::Map::from_xml_reader(TrickleReader(invalid.as_bytes())).unwrap_err();
+    assert_eq!(error.location().unwrap().path, "map/layer[id=1]/data");
+    assert_eq!(error.location().unwrap().line, None);
+
+    let tileset = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.8" tiledversion="1.8.0" name="tiles" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+ <image source="tiles16.png" width="256" height="256"/>
+ <tile id="3" type="Wall &amp; Door"/>
+</tileset>"##;
+    assert_eq!(
+        tmx::Tileset::from_reader(TrickleReader(tileset.as_bytes())).unwrap(),
+        tmx::Tileset::from_xml(tileset).unwrap()
+    );
+
+    match tmx::Map::from_reader(TrickleReader(b"   \n  ")) {
+        Err(tmx::error::Error::UnknownFormat) => {}
+        result => panic!("expected unknown format, got {:?}", result),
+    }
+}
+
+#[cfg(all(feature = "async", feature = "xml"))]
+#[test]
+fn test_load_async() {
+    use std::{
+        future::Future,
+        path::Path,
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+    use tmx::loader::{AsyncResourceReader, BoxFuture, MemoryLoader, ResourceLoader};
+
+    /// Reads from memory, but only completes each read after it has been polled twice, recording how many reads were in flight at once.
+    struct SlowReader {
+        files: MemoryLoader,
+        reads: AtomicUsize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    struct SlowRead<'a> {
+        reader: &'a SlowReader,
+        path: &'a Path,
+        polled: bool,
+    }
+
+    impl Future for SlowRead<'_> {
+        type Output = Result<Vec<u8>, tmx::error::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if !self.polled {
+                self.polled = true;
+                let in_flight = self.reader.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.reader
+                    .max_in_flight
+                    .fetch_max(in_flight, Ordering::SeqCst);
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
+            self.reader.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Poll::Ready(self.reader.files.load(self.path))
+        }
+    }
+
+    impl AsyncResourceReader for SlowReader {
+        fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, tmx::error::Error>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+
+            Box::pin(SlowRead {
+                reader: self,
+                path,
+                polled: false,
+            })
+        }
+    }
+
+    let map = r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+     <tileset firstgid="1" source="terrain.tsx"/>
+     <tileset firstgid="257" source="props.tsj"/>
+     <tileset firstgid="513" source="./terrain.tsx"/>
+     <layer id="1" name="Tile Layer 1" width="2" height="2">
+      <data encoding="csv">
+    1,257,
+    513,0
+    </data>
+     </layer>
+    </map>
+    "##;
+
+    let terrain = r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <tileset version="1.8" tiledversion="1.8.0" name="terrain" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+     <image source="terrain.png" width="256" height="256"/>
+    </tileset>
+    "##;
+
+    let props = r##"
+    { "columns":16,
+    "image":"props.png",
+    "imageheight":256,
+    "imagewidth":256,
+    "margin":0,
+    "name":"props",
+    "spacing":0,
+    "tilecount":256,
+    "tileheight":16,
+    "tilewidth":16,
+    "type":"tileset"
+   }
+    "##;
+
+    let reader = SlowReader {
+        files: MemoryLoader::new()
+            .with("maps/level.tmx", map)
+            .with("maps/terrain.tsx", terrain)
+            .with("maps/props.tsj", props),
+        reads: AtomicUsize::new(0),
+        in_flight: AtomicUsize::new(0),
+        max_in_flight: AtomicUsize::new(0),
+    };
+
+    let tmx = futures_executor::block_on(tmx::Map::load_async(&reader, "maps/level.tmx")).unwrap();
+    println!("xml: {:?}", tmx);
+
+    assert_eq!(reader.reads.load(Ordering::SeqCst), 3);
+    assert_eq!(reader.max_in_flight.load(Ordering::SeqCst), 2);
+
+    let names = tmx
+        .tilesets
+        .iter()
+        .map(|tileset| tileset.tileset().unwrap().name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["terrain", "props", "terrain"]);
+
+    let sync = tmx::Map::load_with(&reader.files, "maps/level.tmx").unwrap();
+    assert_eq!(tmx, sync);
+
+    let in_memory =
+        futures_executor::block_on(tmx::Map::load_async(&reader.files, "maps/level.tmx")).unwrap();
+    assert_eq!(in_memory, sync);
+}
+
+#[cfg(all(feature = "async", feature = "xml"))]
+#[test]
+fn test_load_async_shared_cache() {
+    use std::{
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tmx::{
+        cache::TilesetCache,
+        loader::{AsyncResourceReader, BoxFuture, MemoryLoader, ResourceLoader},
+    };
+
+    /// Loads files from memory either way, canonicalizing paths into a directory of its own and counting reads.
+    struct Loader {
+        files: MemoryLoader,
+        reads: AtomicUsize,
+    }
+
+    impl ResourceLoader for Loader {
+        fn load(&self, path: &Path) -> Result<Vec<u8>, tmx::error::Error> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.files.load(path)
+        }
+
+        fn canonicalize(&self, path: &Path) -> Result<PathBuf, tmx::error::Error> {
+            Ok(Path::new("/assets").join(ResourceLoader::canonicalize(&self.files, path)?))
+        }
+    }
+
+    impl AsyncResourceReader for Loader {
+        fn read<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<u8>, tmx::error::Error>> {
+            Box::pin(async move { self.load(path) })
+        }
+
+        fn canonicalize(&self, path: &Path) -> Result<PathBuf, tmx::error::Error> {
+            ResourceLoader::canonicalize(self, path)
+        }
+    }
+
+    let map = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" source="./terrain.tsx"/>
+ <layer id="1" name="Ground" width="1" height="1">
+  <data encoding="csv">1</data>
+ </layer>
+</map>"##;
+    let terrain = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.8" tiledversion="1.8.0" name="terrain" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+ <image source="terrain.png" width="256" height="256"/>
+</tileset>"##;
+
+    let loader = Loader {
+        files: MemoryLoader::new()
+            .with("maps/level.tmx", map)
+            .with("maps/terrain.tsx", terrain),
+        reads: AtomicUsize::new(0),
+    };
+    let cache = TilesetCache::new();
+
+    let sync = tmx::Map::load_with_cache(&loader, "maps/level.tmx", &cache).unwrap();
+    assert_eq!(loader.reads.load(Ordering::SeqCst), 2);
+
+    // The tileset loaded synchronously is found under the same key, so only the map is read.
+    let async_map = futures_executor::block_on(tmx::Map::load_async_with_cache(
+        &loader,
+        "maps/level.tmx",
+        &cache,
+    ))
+    .unwrap();
+    assert_eq!(loader.reads.load(Ordering::SeqCst), 3);
+    assert_eq!(cache.len(), 1);
+    assert!(cache.get("/assets/maps/terrain.tsx").is_some());
+    assert!(std::ptr::eq(
+        sync.tilesets[0].tileset().unwrap(),
+        async_map.tilesets[0].tileset().unwrap()
+    ));
+
+    // And the other way around.
+    let cache = TilesetCache::new();
+    futures_executor::block_on(tmx::Map::load_async_with_cache(
+        &loader,
+        "maps/level.tmx",
+        &cache,
+    ))
+    .unwrap();
+    tmx::Map::load_with_cache(&loader, "maps/level.tmx", &cache).unwrap();
+    assert_eq!(loader.reads.load(Ordering::SeqCst), 6);
+    assert_eq!(cache.len(), 1);
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_tileset_cache() {
+    use std::path::Path;
+    use std::sync::Arc;
+    use tmx::{
+        cache::TilesetCache,
+        loader::{MemoryLoader, ResourceLoader},
+    };
+
+    let map = |source: &str| {
+        format!(
+            r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+     <tileset firstgid="1" source="{}"/>
+     <layer id="1" name="Tile Layer 1" width="2" height="2">
+      <data encoding="csv">
+    1,2,
+    3,4
+    </data>
+     </layer>
+    </map>
+    "##,
+            source
+        )
+    };
+
+    let tileset = |name: &str| {
+        format!(
+            r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <tileset version="1.8" tiledversion="1.8.0" name="{}" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+     <image source="terrain.png" width="256" height="256"/>
+    </tileset>
+    "##,
+            name
+        )
+    };
+
+    let external = |map: &tmx::Map| match &map.tilesets[0].kind {
+        tmx::map::TilesetKind::External {
+            tileset: Some(tileset),
+            ..
+        } => tileset.clone(),
+        kind => panic!("expected loaded external tileset, got {:?}", kind),
+    };
+
+    let mut loader = MemoryLoader::new()
+        .with("maps/a.tmx", map("../tilesets/terrain.tsx"))
+        .with("maps/dungeon/b.tmx", map("../../tilesets/terrain.tsx"))
+        .with("tilesets/terrain.tsx", tileset("terrain"));
+
+    let cache = TilesetCache::new();
+    let a = tmx::Map::load_with_cache(&loader, "maps/a.tmx", &cache).unwrap();
+    let b = tmx::Map::load_with_cache(&loader, "maps/dungeon/b.tmx", &cache).unwrap();
+
+    assert_eq!(cache.len(), 1);
+    assert!(Arc::ptr_eq(&external(&a), &external(&b)));
+
+    loader.insert("tilesets/terrain.tsx", tileset("terrain-v2"));
+
+    let c = tmx::Map::load_with_cache(&loader, "maps/a.tmx", &cache).unwrap();
+    assert_eq!(external(&c).name, "terrain");
+
+    let key = loader
+        .canonicalize(Path::new("maps/../tilesets/terrain.tsx"))
+        .unwrap();
+    assert!(cache.invalidate(&key));
+    assert!(!cache.invalidate(&key));
+    assert!(cache.is_empty());
+
+    let d = tmx::Map::load_with_cache(&loader, "maps/a.tmx", &cache).unwrap();
+    assert_eq!(external(&d).name, "terrain-v2");
+    assert_eq!(external(&a).name, "terrain");
+
+    cache.clear();
+    assert!(cache.get("tilesets/terrain.tsx").is_none());
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_sandbox() {
+    use std::path::PathBuf;
+    use tmx::loader::{FileSystemLoader, MemoryLoader, Sandbox};
+
+    let map = |source: &str| {
+        format!(
+            r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+     <tileset firstgid="1" source="{}"/>
+     <layer id="1" name="Tile Layer 1" width="2" height="2">
+      <data encoding="csv">
+    1,2,
+    3,4
+    </data>
+     </layer>
+    </map>
+    "##,
+            source
+        )
+    };
+
+    let tileset = r##"
+    <?xml version="1.0" encoding="UTF-8"?>
+    <tileset version="1.8" tiledversion="1.8.0" name="terrain" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+     <image source="terrain.png" width="256" height="256"/>
+    </tileset>
+    "##;
+
+    let loader = Sandbox::new(
+        MemoryLoader::new()
+            .with("mods/a/maps/ok.tmx", map("../tilesets/terrain.tsx"))
+            .with("mods/a/maps/parent.tmx", map("../../b/terrain.tsx"))
+            .with("mods/a/maps/passwd.tmx", map("../../../../etc/passwd"))
+            .with("mods/a/maps/absolute.tmx", map("/etc/passwd"))
+            .with("mods/a/tilesets/terrain.tsx", tileset)
+            .with("mods/b/terrain.tsx", tileset),
+        "mods/a",
+    );
+
+    let tmx = tmx::Map::load_with(&loader, "mods/a/maps/ok.tmx").unwrap();
+    assert_eq!(tmx.tilesets[0].tileset().unwrap().name, "terrain");
+
+    for (map, escape) in &[
+        ("mods/a/maps/parent.tmx", "mods/b/terrain.tsx"),
+        ("mods/a/maps/passwd.tmx", "../etc/passwd"),
+        ("mods/a/maps/absolute.tmx", "/etc/passwd"),
+        ("mods/b/terrain.tsx", "mods/b/terrain.tsx"),
+    ] {
+        match tmx::Map::load_with(&loader, map) {
+            Err(tmx::error::Error::PathEscape { path }) => {
+                assert_eq!(path, PathBuf::from(escape))
+            }
+            result => panic!("expected path escape, got {:?}", result),
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        let dir = std::env::temp_dir().join(format!("tmx-test-sandbox-{}", std::process::id()));
+        let root = dir.join("root");
+        let outside = dir.join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+
+        std::fs::write(root.join("level.tmx"), map("linked/terrain.tsx")).unwrap();
+        std::fs::write(outside.join("terrain.tsx"), tileset).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("linked")).unwrap();
+
+        let loader = Sandbox::new(FileSystemLoader, &root);
+
+        match tmx::Map::load_with(&loader, root.join("level.tmx")) {
+            Err(tmx::error::Error::PathEscape { path }) => {
+                assert_eq!(path, root.join("linked/terrain.tsx"))
+            }
+            result => panic!("expected path escape, got {:?}", result),
+        }
+
+        assert!(tmx::Map::load_with(&FileSystemLoader, root.join("level.tmx")).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[cfg(feature = "xml")]
+fn watched_map(tiles: &str, extra_layer: bool) -> String {
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" infinite="0" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground" width="4" height="4">
+  <data encoding="csv">
+{}
+</data>
+ </layer>{}
+</map>
+"##,
+        tiles,
+        if extra_layer {
+            r##"
+ <layer id="2" name="Decoration" width="4" height="4">
+  <data encoding="csv">
+0,0,0,0,
+0,0,0,0,
+0,0,0,0,
+0,0,0,0
+</data>
+ </layer>"##
+        } else {
+            ""
+        }
+    )
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_diff() {
+    use tmx::{
+        diff::{diff, LayerChange, Region},
+        loader::MemoryLoader,
+    };
+
+    let tileset = |name: &str| {
+        format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.8" tiledversion="1.8.0" name="{}" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+ <image source="terrain.png" width="256" height="256"/>
+</tileset>
+"##,
+            name
+        )
+    };
+
+    let old = MemoryLoader::new()
+        .with(
+            "level.tmx",
+            watched_map("1,1,1,1,\n1,1,1,1,\n1,1,1,1,\n1,1,1,1", true),
+        )
+        .with("terrain.tsx", tileset("terrain"));
+    let new = MemoryLoader::new()
+        .with(
+            "level.tmx",
+            watched_map("1,1,1,1,\n1,2,1,1,\n1,1,1,3,\n1,1,1,1", false),
+        )
+        .with("terrain.tsx", tileset("terrain-v2"));
+
+    let old = tmx::Map::load_with(&old, "level.tmx").unwrap();
+    let new = tmx::Map::load_with(&new, "level.tmx").unwrap();
+
+    assert!(diff(&old, &old).is_empty());
+
+    let changes = diff(&old, &new);
+    println!("changes: {:?}", changes);
+
+    assert!(!changes.map_changed);
+    assert!(changes.layers_added.is_empty());
+    assert_eq!(changes.layers_removed, vec![2]);
+    assert_eq!(
+        changes.layers_modified,
+        vec![LayerChange {
+            id: 1,
+            attributes_changed: false,
+            regions: vec![Region {
+                x: 1,
+                y: 1,
+                width: 3,
+                height: 2
+            }]
+        }]
+    );
+    assert_eq!(changes.tilesets_updated, vec![1]);
+
+    let changes = diff(&new, &old);
+    assert_eq!(changes.layers_added, vec![2]);
+    assert!(changes.layers_removed.is_empty());
+
+    // Changing only the tile layer format is a change of the layer's attributes.
+    let mut reformatted = old.clone();
+    reformatted.layers[0].format = tmx::layer::DataFormat {
+        encoding: Some(tmx::layer::Encoding::Base64),
+        compression: None,
+    };
+    assert_eq!(
+        diff(&old, &reformatted).layers_modified,
+        vec![LayerChange {
+            id: 1,
+            attributes_changed: true,
+            regions: vec![],
+        }]
+    );
+
+    // Regions at the edge of the coordinate space saturate rather than overflow.
+    let mut far = old.clone();
+    far.layers[0].x = i32::MAX - 1;
+    let mut far_changed = far.clone();
+    far_changed.layers[0].data = new.layers[0].data.clone();
+    assert_eq!(
+        diff(&far, &far_changed).layers_modified[0].regions,
+        vec![Region {
+            x: i32::MAX,
+            y: 1,
+            width: 3,
+            height: 2
+        }]
+    );
+}
+
+#[cfg(all(feature = "watch", feature = "xml"))]
+#[test]
+fn test_watch() {
+    use std::time::Duration;
+    use tmx::watch::MapWatcher;
+
+    let tileset = |name: &str| {
+        format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.8" tiledversion="1.8.0" name="{}" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+ <image source="terrain.png" width="256" height="256"/>
+</tileset>
+"##,
+            name
+        )
+    };
+
+    let dir = std::env::temp_dir().join(format!("tmx-test-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let map_path = dir.join("level.tmx");
+    std::fs::write(
+        &map_path,
+        watched_map("1,1,1,1,\n1,1,1,1,\n1,1,1,1,\n1,1,1,1", false),
+    )
+    .unwrap();
+    std::fs::write(dir.join("terrain.tsx"), tileset("terrain")).unwrap();
+
+    let mut watcher = MapWatcher::new(&map_path).unwrap();
+    assert!(watcher.poll().is_none());
+
+    // Saved the way many editors do, by writing a new file and renaming it over the old one.
+    std::fs::write(
+        dir.join("level.tmx.tmp"),
+        watched_map("1,1,1,1,\n1,1,1,1,\n1,1,5,1,\n1,1,1,1", true),
+    )
+    .unwrap();
+    std::fs::rename(dir.join("level.tmx.tmp"), &map_path).unwrap();
+
+    let changes = watcher.wait(Duration::from_secs(10)).unwrap().unwrap();
+    println!("changes: {:?}", changes);
+
+    assert_eq!(changes.layers_added, vec![2]);
+    assert_eq!(changes.layers_modified.len(), 1);
+    assert_eq!(
+        changes.layers_modified[0].regions,
+        vec![tmx::diff::Region {
+            x: 2,
+            y: 2,
+            width: 1,
+            height: 1
+        }]
+    );
+    assert!(changes.tilesets_updated.is_empty());
+    assert_eq!(watcher.map().layers.len(), 2);
+
+    std::fs::write(dir.join("terrain.tsx"), tileset("terrain-v2")).unwrap();
+
+    // Polling doesn't wait for the change to settle, it is reported by a later call.
+    let start = std::time::Instant::now();
+    let changes = loop {
+        if let Some(result) = watcher.poll() {
+            break result.unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    println!("changes: {:?}", changes);
+
+    assert_eq!(changes.tilesets_updated, vec![1]);
+    assert!(changes.layers_modified.is_empty());
+    assert_eq!(
+        watcher.map().tilesets[0].tileset().unwrap().name,
+        "terrain-v2"
+    );
+
+    // Unrelated files in the same directory are ignored.
+    std::fs::write(dir.join("notes.txt"), "unrelated").unwrap();
+    assert!(watcher.wait(Duration::from_millis(200)).is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_peek_header() {
+    use tmx::map::{LayerHeader, Orientation, TilesetHeader};
+
+    // The layer data is garbage, peeking must not try to decode it.
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="64" height="32" tilewidth="16" tileheight="8" infinite="1" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <tileset firstgid="257" name="items" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="items.png" width="32" height="32"/>
+  <tile id="0" type="Coin"/>
+ </tileset>
+ <layer id="1" name="Ground" width="64" height="32">
+  <data encoding="base64" compression="zlib">
+   <chunk x="0" y="0" width="16" height="16">not base64 at all</chunk>
+  </data>
+ </layer>
+ <layer id="2" name="Walls" width="64" height="32">
+  <data encoding="base64" compression="zstd">!!!</data>
+ </layer>
+</map>
+"##;
+
+    let header = tmx::Map::peek_header(xml.as_bytes()).unwrap();
+    println!("header: {:?}", header);
+
+    assert_eq!(header.metadata.version, "1.8");
+    assert_eq!(header.orientation, Orientation::Orthogonal);
+    assert_eq!((header.width, header.height), (64, 32));
+    assert_eq!((header.tile_width, header.tile_height), (16, 8));
+    assert!(header.infinite);
+    assert_eq!(
+        header.layers,
+        vec![
+            LayerHeader {
+                id: 1,
+                name: "Ground".to_string()
+            },
+            LayerHeader {
+                id: 2,
+                name: "Walls".to_string()
+            }
+        ]
+    );
+    assert_eq!(
+        header.tilesets,
+        vec![
+            TilesetHeader {
+                first_gid: 1,
+                source: Some("terrain.tsx".to_string()),
+                name: None
+            },
+            TilesetHeader {
+                first_gid: 257,
+                source: None,
+                name: Some("items".to_string())
+            }
+        ]
+    );
+    assert!(tmx::Map::from_xml_data(xml.as_bytes()).is_err());
+
+    let json = r##"{ "compressionlevel":-1,
+ "height":32,
+ "infinite":false,
+ "layers":[
+        {
+         "compression":"zlib",
+         "data":"not base64 at all",
+         "encoding":"base64",
+         "height":32,
+         "id":1,
+         "name":"Ground",
+         "opacity":1,
+         "type":"tilelayer",
+         "visible":true,
+         "width":64,
+         "x":0,
+         "y":0
+        }],
+ "nextlayerid":2,
+ "nextobjectid":1,
+ "orientation":"orthogonal",
+ "renderorder":"right-down",
+ "tiledversion":"1.8.0",
+ "tileheight":8,
+ "tilesets":[
+        {
+         "firstgid":1,
+         "source":"terrain.tsj"
+        }],
+ "tilewidth":16,
+ "type":"map",
+ "version":"1.8",
+ "width":64
+}"##;
+
+    let header = tmx::Map::peek_header(json.as_bytes()).unwrap();
+    assert_eq!((header.width, header.height), (64, 32));
+    assert!(!header.infinite);
+    assert_eq!(header.layers[0].name, "Ground");
+    assert_eq!(header.tilesets[0].source.as_deref(), Some("terrain.tsj"));
+
+    // Both formats list the same top-level layers, of every kind.
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="6" nextobjectid="2">
+ <layer id="1" name="Ground" width="2" height="2">
+  <data encoding="csv">0,0,0,0</data>
+ </layer>
+ <objectgroup id="2" name="Objects">
+  <object id="1" x="0" y="0"/>
+ </objectgroup>
+ <imagelayer id="3" name="Sky">
+  <image source="sky.png" width="32" height="32"/>
+ </imagelayer>
+ <group id="4" name="Group">
+  <layer id="5" name="Nested" width="2" height="2">
+   <data encoding="csv">0,0,0,0</data>
+  </layer>
+ </group>
+</map>
+"##;
+    let json = r##"{ "height":2, "width":2, "tilewidth":16, "tileheight":16, "infinite":false,
+ "orientation":"orthogonal", "renderorder":"right-down", "tiledversion":"1.8.0", "version":"1.8",
+ "nextlayerid":6, "nextobjectid":2, "type":"map", "tilesets":[],
+ "layers":[
+  { "id":1, "name":"Ground", "type":"tilelayer", "width":2, "height":2, "data":[0,0,0,0] },
+  { "id":2, "name":"Objects", "type":"objectgroup", "objects":[{ "id":1, "x":0, "y":0 }] },
+  { "id":3, "name":"Sky", "type":"imagelayer", "image":"sky.png" },
+  { "id":4, "name":"Group", "type":"group", "layers":[
+   { "id":5, "name":"Nested", "type":"tilelayer", "width":2, "height":2, "data":[0,0,0,0] }] }]
+}"##;
+
+    let from_xml = tmx::Map::peek_header(xml.as_bytes()).unwrap();
+    let from_json = tmx::Map::peek_header(json.as_bytes()).unwrap();
+    let names = |header: &tmx::map::Header| {
+        header
+            .layers
+            .iter()
+            .map(|layer| (layer.id, layer.name.clone()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(&from_xml),
+        vec![
+            (1, "Ground".to_string()),
+            (2, "Objects".to_string()),
+            (3, "Sky".to_string()),
+            (4, "Group".to_string())
+        ]
+    );
+    assert_eq!(from_xml.layers, from_json.layers);
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_lazy_chunks() {
+    use tmx::{diff::Region, layer::LayerData, ParseOptions};
+
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="30" height="20" tilewidth="16" tileheight="16" infinite="1" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground" width="30" height="20">
+  <data encoding="csv">
+   <chunk x="0" y="0" width="2" height="2">
+1,2,
+3,4
+</chunk>
+   <chunk x="2" y="0" width="2" height="2">
+5,6,
+7,8
+</chunk>
+   <chunk x="0" y="2" width="2" height="2">
+9,10,
+11,12
+</chunk>
+   <chunk x="16" y="16" width="2" height="2">
+not,csv,
+at,all
+</chunk>
+  </data>
+ </layer>
+</map>
+"##;
+
+    // Eager parsing decodes every chunk up front.
+    assert!(tmx::Map::from_xml(xml).is_err());
+
+    let options = ParseOptions {
+        lazy_chunks: Some(2),
+        ..ParseOptions::default()
+    };
+    let map = tmx::Map::from_data_with_options(xml.as_bytes(), &options).unwrap();
+
+    let chunks = match &map.layers[0].data {
+        LayerData::LazyChunks(chunks) => chunks,
+        data => panic!("expected lazy chunks, got {:?}", data),
+    };
+
+    assert_eq!(chunks.len(), 4);
+    assert_eq!(chunks.capacity(), 2);
+    assert_eq!(chunks.decoded_len(), 0);
+
+    let gids =
+        |tiles: Vec<tmx::layer::Tile>| tiles.into_iter().map(|tile| tile.gid()).collect::<Vec<_>>();
+
+    let region = Region {
+        x: 1,
+        y: 1,
+        width: 3,
+        height: 2,
+    };
+    assert_eq!(gids(chunks.tiles(region).unwrap()), vec![4, 7, 8, 10, 0, 0]);
+    assert_eq!(chunks.decoded_len(), 2);
+
+    assert_eq!(chunks.tile(3, 0).unwrap().gid(), 6);
+    assert_eq!(chunks.tile(10, 10).unwrap().gid(), 0);
+    assert_eq!(chunks.decoded_len(), 2);
+
+    // The broken chunk only fails once it is needed.
+    assert!(chunks.tile(17, 17).is_err());
+    assert!(chunks.to_chunks().is_err());
+
+    // The options only apply to the parse they were given to.
+    assert!(tmx::Map::from_xml(xml).is_err());
+}
+
+#[cfg(all(feature = "xml", feature = "zlib-data"))]
+#[test]
+fn test_parse_limits() {
+    use std::io::Write;
+    use tmx::{
+        error::Error,
+        options::{Limit, ParseLimits},
+        ParseOptions,
+    };
+
+    let map = |width: u32, height: u32, layers: &str| {
+        format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="16" tileheight="16" infinite="0" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+{}
+</map>
+"##,
+            width, height, layers
+        )
+    };
+    let layer = |id: u32, width: u32, height: u32, data: &str| {
+        format!(
+            r#"<layer id="{}" name="Layer {}" width="{}" height="{}">{}</layer>"#,
+            id, id, width, height, data
+        )
+    };
+    let parse = |xml: &str, limits: ParseLimits| {
+        let options = ParseOptions {
+            limits,
+            ..ParseOptions::default()
+        };
+        tmx::Map::from_data_with_options(xml.as_bytes(), &options)
+    };
+
+    // A megabyte of zeros compresses to about a kilobyte.
+    let mut encoder = libflate::zlib::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(&vec![0; 1 << 20]).unwrap();
+    let bomb = base64::encode(encoder.finish().into_result().unwrap());
+    let bomb = map(
+        512,
+        512,
+        &layer(
+            1,
+            512,
+            512,
+            &format!(
+                r#"<data encoding="base64" compression="zlib">{}</data>"#,
+                bomb
+            ),
+        ),
+    );
+
+    let limits = ParseLimits {
+        max_decompressed_bytes: Some(1 << 16),
+        ..ParseLimits::default()
+    };
+    match parse(&bomb, limits).as_ref().map_err(Error::kind) {
+        Err(Error::LimitExceeded(Limit::DecompressedBytes(max))) => assert_eq!(*max, 1 << 16),
+        result => panic!("expected the decompressed bytes limit, got {:?}", result),
+    }
+    assert!(parse(&bomb, ParseLimits::default()).is_ok());
+
+    let limits = ParseLimits {
+        max_tiles: Some(1000),
+        ..ParseLimits::default()
+    };
+    let csv = r#"<data encoding="csv">1,1,1,1</data>"#;
+    assert!(parse(&map(2, 2, &layer(1, 2, 2, csv)), limits).is_ok());
+    // Peeking at a header doesn't leave its size behind for the next map parsed.
+    tmx::Map::peek_header(map(100_000, 100_000, "").as_bytes()).unwrap();
+    assert!(parse(&map(2, 2, &layer(1, 2, 2, csv)), limits).is_ok());
+    for xml in &[
+        map(100_000, 100_000, &layer(1, 2, 2, csv)),
+        map(2, 2, &layer(1, 100_000, 100_000, csv)),
+        map(
+            2,
+            2,
+            &layer(
+                1,
+                2,
+                2,
+                &format!(r#"<data encoding="csv">{}1</data>"#, "1,".repeat(1000)),
+            ),
+        ),
+    ] {
+        match parse(xml, limits).as_ref().map_err(Error::kind) {
+            Err(Error::LimitExceeded(Limit::Tiles(1000))) => {}
+            result => panic!("expected the tiles limit, got {:?}", result),
+        }
+    }
+    // The size of the map is checked before its layers are parsed.
+    let invalid = r#"<data encoding="csv">x</data>"#;
+    match parse(&map(100_000, 100_000, &layer(1, 2, 2, invalid)), limits)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::LimitExceeded(Limit::Tiles(1000))) => {}
+        result => panic!("expected the tiles limit, got {:?}", result),
+    }
+
+    let limits = ParseLimits {
+        max_layers: Some(2),
+        ..ParseLimits::default()
+    };
+    let layers = (1..=3).map(|id| layer(id, 2, 2, csv)).collect::<String>();
+    match parse(&map(2, 2, &layers), limits)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::LimitExceeded(Limit::Layers(2))) => {}
+        result => panic!("expected the layers limit, got {:?}", result),
+    }
+    // Layers past the limit are skipped over without being parsed.
+    let layers = (1..=2).map(|id| layer(id, 2, 2, csv)).collect::<String>();
+    assert!(parse(&map(2, 2, &layers), limits).is_ok());
+    let layers = format!("{}{}", layers, layer(3, 2, 2, invalid));
+    match parse(&map(2, 2, &layers), limits)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::LimitExceeded(Limit::Layers(2))) => {}
+        result => panic!("expected the layers limit, got {:?}", result),
+    }
+
+    let limits = ParseLimits {
+        max_depth: Some(8),
+        ..ParseLimits::default()
+    };
+    let nested = format!(
+        "{}{}{}",
+        "<properties>".repeat(10),
+        "</properties>".repeat(10),
+        layer(1, 2, 2, csv)
+    );
+    match parse(&map(2, 2, &nested), limits)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::LimitExceeded(Limit::Depth(8))) => {}
+        result => panic!("expected the depth limit, got {:?}", result),
+    }
+    let json = format!(r#"{{"layers":{}{}}}"#, "[".repeat(10), "]".repeat(10));
+    match parse(&json, limits).as_ref().map_err(Error::kind) {
+        Err(Error::LimitExceeded(Limit::Depth(8))) => {}
+        result => panic!("expected the depth limit, got {:?}", result),
+    }
+    let options = ParseOptions {
+        limits,
+        ..ParseOptions::default()
+    };
+    match tmx::Map::from_reader_with_options(json.as_bytes(), &options)
+        .as_ref()
+        .map_err(Error::kind)
+    {
+        Err(Error::LimitExceeded(Limit::Depth(8))) => {}
+        result => panic!("expected the depth limit, got {:?}", result),
+    }
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_lenient() {
+    use tmx::{
+        layer::LayerData,
+        map::{Orientation, RenderOrder},
+        options::Warning,
+    };
+
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="diagonal" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Short" width="2" height="2">
+  <data encoding="csv">1,2,3</data>
+ </layer>
+ <layer id="2" name="Long" width="2" height="2">
+  <data encoding="csv">1,2,3,4,5</data>
+ </layer>
+</map>
+"##;
+
+    assert!(tmx::Map::from_xml(xml).is_err());
+
+    let (map, warnings) = tmx::Map::from_data_lenient(xml.as_bytes()).unwrap();
+    println!("warnings: {:?}", warnings);
+
+    assert_eq!(map.render_order, RenderOrder::RightDown);
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::InvalidValue {
+                attribute: "renderorder".to_string(),
+                value: "diagonal".to_string()
+            },
+            Warning::TileCount {
+                layer: 1,
+                expected: 4,
+                found: 3
+            },
+            Warning::TileCount {
+                layer: 2,
+                expected: 4,
+                found: 5
+            },
+        ]
+    );
+
+    let gids = |data: &LayerData| match data {
+        LayerData::Tiles(tiles) => tiles.iter().map(|tile| tile.gid()).collect::<Vec<_>>(),
+        data => panic!("expected tiles, got {:?}", data),
+    };
+    assert_eq!(gids(&map.layers[0].data), vec![1, 2, 3, 0]);
+    assert_eq!(gids(&map.layers[1].data), vec![1, 2, 3, 4]);
+
+    let json = r##"{ "height":1,
+ "infinite":false,
+ "layers":[
+        {
+         "draworder":"topdown",
+         "id":1,
+         "name":"Spawns",
+         "objects":[],
+         "type":"objectgroup"
+        },
+        {
+         "data":[1],
+         "height":1,
+         "id":2,
+         "name":"Ground",
+         "type":"tilelayer",
+         "width":1
+        }],
+ "nextlayerid":3,
+ "nextobjectid":1,
+ "orientation":"spherical",
+ "renderorder":"right-down",
+ "tiledversion":"1.8.0",
+ "tileheight":16,
+ "tilesets":[],
+ "tilewidth":16,
+ "type":"map",
+ "version":"1.8",
+ "width":1
+}"##;
+
+    assert!(tmx::Map::from_json(json).is_err());
+
+    let (map, warnings) = tmx::Map::from_data_lenient(json.as_bytes()).unwrap();
+    println!("warnings: {:?}", warnings);
+
+    assert_eq!(map.orientation, Orientation::Orthogonal);
+    assert_eq!(map.layers.len(), 1);
+    assert_eq!(map.layers[0].name, "Ground");
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings.contains(&Warning::InvalidValue {
+        attribute: "orientation".to_string(),
+        value: "spherical".to_string()
+    }));
+    assert!(warnings.contains(&Warning::SkippedLayer {
+        index: 0,
+        id: Some(1),
+        name: Some("Spawns".to_string()),
+        reason: "unsupported layer type \"objectgroup\"".to_string()
+    }));
+
+    // Parsing strictly reports no warnings.
+    let xml = xml.replace("diagonal", "right-down");
+    let (_, warnings) =
+        tmx::Map::from_data_with_warnings(xml.as_bytes(), &tmx::ParseOptions::default()).unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn test_malformed_input() {
+    // An empty `data` array used to be treated as a missing XML element and panic.
+    let map = r#"{"height":2,"width":2,"infinite":false,"layers":[{"data":[],"height":2,"width":2,"id":1,"name":"Empty","type":"tilelayer"}],"nextlayerid":2,"nextobjectid":1,"orientation":"orthogonal","renderorder":"right-down","tiledversion":"1.8.0","tileheight":16,"tilewidth":16,"tilesets":[],"type":"map","version":"1.8"}"#;
+    let map = tmx::Map::from_json(map).unwrap();
+    assert_eq!(map.layers[0].data, tmx::layer::LayerData::Tiles(vec![]));
+
+    // Likewise an empty `image` array.
+    let tileset = r#"{"columns":1,"image":[],"imageheight":16,"imagewidth":16,"margin":0,"name":"Empty","spacing":0,"tilecount":1,"tiledversion":"1.8.0","tileheight":16,"tilewidth":16,"type":"tileset","version":"1.8"}"#;
+    assert!(tmx::Tileset::from_json(tileset).is_err());
+
+    let deep_json = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    assert!(tmx::Map::from_data(deep_json.as_bytes()).is_err());
+
+    #[cfg(feature = "xml")]
+    {
+        let deep_xml = format!("{}{}", "<map>".repeat(100_000), "</map>".repeat(100_000));
+        assert!(tmx::Map::from_data(deep_xml.as_bytes()).is_err());
+        assert!(tmx::Tileset::from_data(deep_xml.as_bytes()).is_err());
+
+        // Every prefix of a valid map fails cleanly.
+        let map = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="1" tilewidth="16" tileheight="16" infinite="1" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+  <tile id="0"><animation><frame tileid="0" duration="100"/></animation></tile>
+ </tileset>
+ <layer id="1" name="Ground" width="2" height="1">
+  <data encoding="base64" compression="zlib"><chunk x="0" y="0" width="2" height="1">eJxjZGBgAAAACAAC</chunk></data>
+ </layer>
+</map>"##;
+
+        for end in 0..map.len() {
+            let prefix = &map.as_bytes()[..end];
+            let _ = tmx::Map::from_data(prefix);
+            let _ = tmx::Map::peek_header(prefix);
+        }
+    }
+
+    #[cfg(feature = "lua")]
+    {
+        let deep_lua = format!("return {}{}", "{".repeat(100_000), "}".repeat(100_000));
+        assert!(tmx::Map::from_data(deep_lua.as_bytes()).is_err());
+    }
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_error_location() {
+    use tmx::error::{DataError, Error};
+
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="1" nextlayerid="4" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground" width="2" height="2">
+  <data encoding="csv"><chunk x="0" y="0" width="2" height="2">1,1,1,1</chunk></data>
+ </layer>
+ <layer id="3" name="Walls" width="2" height="2">
+  <data encoding="csv">
+   <chunk x="0" y="0" width="2" height="2">1,1,1,1</chunk>
+   <chunk x="2" y="0" width="2" height="2">1,1,1,1</chunk>
+   <chunk x="4" y="0" width="2" height="2">1,1,x,1</chunk>
+  </data>
+ </layer>
+</map>"##;
+
+    let error = tmx::Map::from_xml(xml).unwrap_err();
+    let location = error.location().unwrap();
+    assert_eq!(location.path, "map/layer[id=3]/data/chunk[2]");
+    assert_eq!((location.line, location.column), (Some(11), Some(4)));
+    assert!(matches!(error.kind(), Error::TileData(DataError::Csv(_))));
+    assert_eq!(
+        error.to_string(),
+        "map/layer[id=3]/data/chunk[2] (line 11, column 4): invalid tile data: invalid CSV: invalid digit found in string"
+    );
+
+    // Errors found after invalid tile data don't hide it, whether or not tile data is decoded once all layers are read (with `rayon`).
+    let later = xml.replace(
+        "</map>",
+        " <layer id=\"4\" name=\"Invalid\" width=\"x\" height=\"2\"/>\n</map>",
+    );
+    assert_eq!(
+        tmx::Map::from_xml(&later).unwrap_err().to_string(),
+        error.to_string()
+    );
+
+    // JSON tile data is located by its path, as serde_json only knows how far it has read.
+    #[cfg(feature = "base64-data")]
+    {
+        let json = r#"{"height":2,"width":2,"infinite":true,"layers":[
+  {"id":1,"name":"Ground","type":"tilelayer","width":2,"height":2,"encoding":"base64","chunks":[
+    {"x":0,"y":0,"width":2,"height":2,"data":"AQAAAAEAAAABAAAAAQAAAA=="},
+    {"x":2,"y":0,"width":2,"height":2,"data":"not base64"}]},
+  {"id":2,"name":"Walls","type":"tilelayer","width":"x","height":2,"data":[1,1,1,1]}],
+ "nextlayerid":3,"nextobjectid":1,"orientation":"orthogonal","renderorder":"right-down","tiledversion":"1.8.0","tileheight":16,"tilewidth":16,"tilesets":[],"type":"map","version":"1.8"}"#;
+        let error = tmx::Map::from_json(json).unwrap_err();
+        assert!(matches!(error, Error::At { .. }));
+        assert!(matches!(error.kind(), Error::TileData(_)));
+        assert_eq!(
+            error.to_string(),
+            "map/layer[id=1]/data/chunk[1]: invalid tile data: invalid base64: Invalid byte 32, offset 3."
+        );
+    }
+
+    // Chunks decoded on demand report the chunk that failed.
+    let options = tmx::ParseOptions {
+        lazy_chunks: Some(1),
+        ..tmx::ParseOptions::default()
+    };
+    let map = tmx::Map::from_data_with_options(xml.as_bytes(), &options).unwrap();
+    match &map.layers[1].data {
+        tmx::layer::LayerData::LazyChunks(chunks) => {
+            let error = chunks.to_chunks().unwrap_err();
+            assert_eq!(error.location().unwrap().path, "chunk[2]");
+        }
+        data => panic!("expected lazy chunks, got {:?}", data),
+    }
+
+    // Syntax errors are reported where they occur.
+    let error =
+        tmx::Map::from_xml(&xml.replace("</chunk>\n  </data>", "</chunk>\n  </dat>")).unwrap_err();
+    assert_eq!(error.location().unwrap().line, Some(12));
+
+    let json = "{\n  \"width\": 2,\n  \"height\": [\n}";
+    let error = tmx::Map::from_json(json).unwrap_err();
+    let location = error.location().unwrap();
+    assert_eq!((location.line, location.column), (Some(4), Some(1)));
+    assert!(matches!(error.kind(), Error::Deserialization(_)));
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_xml_interleaved_elements() {
+    use tmx::layer::LayerData;
+
+    // Layers separated by other elements, with escaped attributes and text.
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="4" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground &amp; Water" width="2" height="2">
+  <data encoding="csv">1,2,3,4</data>
+ </layer>
+ <objectgroup id="2" name="Spawns">
+  <object id="1" x="0" y="0"/>
+ </objectgroup>
+ <!-- <layer id="5"/> -->
+ <tileset firstgid="5" source="walls.tsx"/>
+ <layer id="3" name="Walls" width="2" height="2">
+  <data encoding="csv">&#53;,6,
+7,8</data>
+ </layer>
+</map>"##;
+
+    let map = tmx::Map::from_xml(xml).unwrap();
+    let names = map
+        .layers
+        .iter()
+        .map(|layer| (layer.id, layer.name.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![(1, "Ground & Water"), (3, "Walls")]);
+    assert_eq!(map.tilesets.len(), 2);
+
+    match &map.layers[1].data {
+        LayerData::Tiles(tiles) => assert_eq!(
+            tiles.iter().map(|tile| tile.gid()).collect::<Vec<_>>(),
+            vec![5, 6, 7, 8]
+        ),
+        data => panic!("expected tiles, got {:?}", data),
+    }
+
+    assert_eq!(tmx::Map::from_xml_reader(xml.as_bytes()).unwrap(), map);
+    assert_eq!(
+        tmx::Map::from_data_lenient(xml.as_bytes()).unwrap(),
+        (map.clone(), vec![])
+    );
+
+    let borrowed = tmx::borrowed::Map::from_xml(xml).unwrap();
+    assert_eq!(borrowed.layers.len(), 2);
+    assert_eq!(borrowed.tilesets.len(), 2);
+
+    // Tiles separated by other elements.
+    let tileset = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.8" tiledversion="1.8.0" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <image source="tiles.png" width="32" height="32"/>
+ <tile id="0" type="Wall"/>
+ <wangsets>
+  <wangset name="Ground" tile="-1"/>
+ </wangsets>
+ <tile id="1" type="Door"/>
+</tileset>"##;
+
+    let tiles = tmx::Tileset::from_xml(tileset).unwrap();
+    assert_eq!(
+        tiles
+            .tiles
+            .iter()
+            .map(|tile| tile.r#type.as_str())
+            .collect::<Vec<_>>(),
+        vec!["Wall", "Door"]
+    );
+    assert_eq!(
+        tmx::Tileset::from_xml_reader(tileset.as_bytes()).unwrap(),
+        tiles
+    );
+    assert_eq!(
+        tmx::borrowed::Tileset::from_xml(tileset)
+            .unwrap()
+            .tiles
+            .len(),
+        2
+    );
+}
+
+#[cfg(all(feature = "xml", feature = "base64-data"))]
+#[test]
+fn test_borrowed() {
+    use std::borrow::Cow;
+    use tmx::borrowed::{Map, TilesetKind};
+
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" backgroundcolor="#ff00ff" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" name="Embedded" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="tiles.png" width="32" height="32"/>
+  <tile id="0" type="Wall"/>
+  <tile id="1">
+   <animation>
+    <frame tileid="1" duration="100"/>
+   </animation>
+  </tile>
+ </tileset>
+ <tileset firstgid="5" source="walls.tsx"/>
+ <layer id="1" name="Ground" width="2" height="2">
+  <data encoding="csv">1,2,3,4</data>
+ </layer>
+ <layer id="2" name="Ground &amp; Water" width="2" height="2">
+  <data encoding="base64">BQAAAAYAAAAHAAAACAAAAA==</data>
+ </layer>
+</map>"##;
+
+    let map = Map::from_xml(xml).unwrap();
+    let owned = tmx::Map::from_xml(xml).unwrap();
+
+    assert!(matches!(map.metadata.tiled_version, Cow::Borrowed("1.8.0")));
+    assert!(matches!(
+        map.background_color,
+        Some(Cow::Borrowed("#ff00ff"))
+    ));
+    assert!(matches!(map.layers[0].name, Cow::Borrowed("Ground")));
+    // Escaped strings can't be borrowed.
+    assert!(matches!(&map.layers[1].name, Cow::Owned(name) if name == "Ground & Water"));
+
+    for (layer, owned) in map.layers.iter().zip(&owned.layers) {
+        assert_eq!((layer.id, &layer.data), (owned.id, &owned.data));
+    }
+
+    match &map.tilesets[0].kind {
+        TilesetKind::Embedded(tileset) => {
+            assert!(matches!(tileset.name, Cow::Borrowed("Embedded")));
+            assert!(matches!(tileset.image.source, Cow::Borrowed("tiles.png")));
+            assert!(matches!(tileset.tiles[0].r#type, Cow::Borrowed("Wall")));
+            // Tiles without a type have an empty one.
+            assert_eq!(tileset.tiles[1].r#type, "");
+            assert_eq!(tileset.tiles[1].animation.len(), 1);
+        }
+        kind => panic!("expected an embedded tileset, got {:?}", kind),
+    }
+    match &map.tilesets[1].kind {
+        TilesetKind::External { source } => assert!(matches!(source, Cow::Borrowed("walls.tsx"))),
+        kind => panic!("expected an external tileset, got {:?}", kind),
+    }
+
+    let json = r##"{ "version": 1.8, "tiledversion": "1.8.0", "orientation": "orthogonal", "renderorder": "right-down", "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16, "infinite": false,
+        "layers": [
+            { "id": 1, "name": "Ground", "width": 2, "height": 2, "data": [1, 2, 3, 4] },
+            { "id": 2, "name": "Ground\nWater", "width": 2, "height": 2, "data": [5, 6, 7, 8] }
+        ],
+        "tilesets": [{ "firstgid": 1, "source": "terrain.tsj" }]
+    }"##;
+
+    let map = Map::from_json(json).unwrap();
+    assert!(matches!(map.metadata.version, Cow::Owned(ref version) if version == "1.8"));
+    assert!(matches!(map.layers[0].name, Cow::Borrowed("Ground")));
+    assert!(matches!(map.layers[1].name, Cow::Owned(_)));
+    assert!(matches!(
+        &map.tilesets[0].kind,
+        TilesetKind::External {
+            source: Cow::Borrowed("terrain.tsj")
+        }
+    ));
+
+    let tileset = tmx::borrowed::Tileset::from_json(
+        r#"{ "name": "Terrain", "tilewidth": 16, "tileheight": 16, "tilecount": 4, "columns": 2, "image": "terrain.png", "imagewidth": 32, "imageheight": 32, "tiles": [{ "id": 0, "type": "Grass" }] }"#,
+    )
+    .unwrap();
+    assert!(matches!(tileset.image.source, Cow::Borrowed("terrain.png")));
+    assert!(matches!(tileset.tiles[0].r#type, Cow::Borrowed("Grass")));
+}
+
+#[cfg(all(feature = "rayon", feature = "xml", feature = "base64-data"))]
+#[test]
+fn test_parallel() {
+    use tmx::{options::ParseLimits, ParseOptions};
+
+    let layer = |id: u32, chunks: &[String]| {
+        let chunks = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, data)| {
+                format!(
+                    "   <chunk x=\"{}\" y=\"0\" width=\"4\" height=\"4\">{}</chunk>\n",
+                    i * 4,
+                    data
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            " <layer id=\"{}\" name=\"Layer {}\" width=\"4\" height=\"4\">\n  <data encoding=\"base64\">\n{}  </data>\n </layer>\n",
+            id, id, chunks
+        )
+    };
+    let chunk = |gid: u32| base64::encode(gid.to_le_bytes().repeat(16));
+    let map = |layers: &[Vec<String>]| {
+        let layers = layers
+            .iter()
+            .enumerate()
+            .map(|(i, chunks)| layer(i as u32 + 1, chunks))
+            .collect::<String>();
+
+        format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" infinite="1" nextlayerid="5" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+{}</map>"##,
+            layers
+        )
+    };
+
+    let layers = (0..4)
+        .map(|layer| (0..8).map(|i| chunk(layer * 8 + i + 1)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let sequential = ParseOptions {
+        sequential: true,
+        ..ParseOptions::default()
+    };
+    let parse = |xml: &str, options: &ParseOptions| {
+        tmx::Map::from_data_with_options(xml.as_bytes(), options).map_err(|e| e.to_string())
+    };
+
+    let xml = map(&layers);
+    let map_data = parse(&xml, &ParseOptions::default()).unwrap();
+    assert_eq!(map_data, parse(&xml, &sequential).unwrap());
+    match &map_data.layers[3].data {
+        tmx::layer::LayerData::Chunks(chunks) => {
+            assert_eq!(chunks.len(), 8);
+            assert_eq!(chunks[7].data[0].gid(), 32);
+        }
+        data => panic!("expected chunks, got {:?}", data),
+    }
+
+    // The chunks of a layer share its limits, they are exceeded by the 5th chunk.
+    let limits = ParseLimits {
+        max_decompressed_bytes: Some(4 * 16 * 4 + 10),
+        ..ParseLimits::default()
+    };
+    let limited = |options: &ParseOptions| ParseOptions {
+        limits,
+        ..options.clone()
+    };
+    let error = parse(&xml, &limited(&ParseOptions::default())).unwrap_err();
+    assert_eq!(error, parse(&xml, &limited(&sequential)).unwrap_err());
+    assert!(
+        error.starts_with("map/layer[id=1]/data/chunk[4]"),
+        "{}",
+        error
+    );
+
+    // Each chunk decoded in parallel is limited to the tiles it covers, one holding more is decoded again with what is left of the layer's limits.
+    let mut oversized = layers.clone();
+    oversized[0][2] = base64::encode(3u32.to_le_bytes().repeat(32));
+    let xml = map(&oversized);
+    let limits = ParseLimits {
+        max_decompressed_bytes: Some(9 * 16 * 4),
+        ..ParseLimits::default()
+    };
+    let limited = |options: &ParseOptions| ParseOptions {
+        limits,
+        ..options.clone()
+    };
+    let map_data = parse(&xml, &limited(&ParseOptions::default())).unwrap();
+    assert_eq!(map_data, parse(&xml, &limited(&sequential)).unwrap());
+    match &map_data.layers[0].data {
+        tmx::layer::LayerData::Chunks(chunks) => assert_eq!(chunks[2].data.len(), 32),
+        data => panic!("expected chunks, got {:?}", data),
+    }
+
+    // Errors in a later layer are reported as they would be sequentially.
+    let mut invalid = layers;
+    invalid[2][3] = "not base64".to_string();
+    let xml = map(&invalid);
+    let error = parse(&xml, &ParseOptions::default()).unwrap_err();
+    assert_eq!(error, parse(&xml, &sequential).unwrap_err());
+    assert!(
+        error.starts_with("map/layer[id=3]/data/chunk[3] (line 33,"),
+        "{}",
+        error
+    );
+
+    // Even when parsing fails in a later layer, before the tile data is decoded.
+    let xml = xml.replace(
+        "</map>",
+        " <layer id=\"5\" name=\"Invalid\" width=\"x\" height=\"4\"/>\n</map>",
+    );
+    assert_eq!(error, parse(&xml, &ParseOptions::default()).unwrap_err());
+    assert_eq!(error, parse(&xml, &sequential).unwrap_err());
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn test_compact_tiles() {
+    use tmx::layer::{CompactTiles, LayerData};
+
+    let map = |gids: &str| {
+        format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground" width="2" height="2">
+  <data encoding="csv">{}</data>
+ </layer>
+</map>"##,
+            gids
+        )
+    };
+    let options = tmx::ParseOptions {
+        compact_tiles: true,
+        ..tmx::ParseOptions::default()
+    };
+
+    // Flipped tiles keep their flags in the top bits of each cell.
+    let flipped = 0x8000_0000u32 | 0x2000_0000 | 12;
+    for (gids, cell_size) in [
+        (vec![0, 1, 200, 255], 1),
+        (vec![0, 1, flipped, 31], 1),
+        (vec![0, 256, 1000, 65535], 2),
+        (vec![0, flipped, 100, 8191], 2),
+        (vec![0, flipped, 8192, 1], 4),
+        (vec![0, 1, 65536, 3], 4),
+    ] {
+        let csv = gids
+            .iter()
+            .map(|gid| gid.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let xml = map(&csv);
+        let tiles = match tmx::Map::from_xml(&xml).unwrap().layers[0].data.clone() {
+            LayerData::Tiles(tiles) => tiles,
+            data => panic!("expected tiles, got {:?}", data),
+        };
+
+        let compact = tmx::Map::from_data_with_options(xml.as_bytes(), &options).unwrap();
+        match &compact.layers[0].data {
+            LayerData::CompactTiles(compact) => {
+                assert_eq!(compact.cell_size(), cell_size, "{:?}", gids);
+                assert_eq!(compact.len(), 4);
+                assert_eq!(compact.to_vec(), tiles);
+                assert_eq!(compact.get(1), Some(tiles[1]));
+                assert_eq!(compact.get(4), None);
+                assert_eq!(
+                    compact
+                        .iter()
+                        .map(|tile| tile.flipped_diagonally())
+                        .collect::<Vec<_>>(),
+                    tiles
+                        .iter()
+                        .map(|tile| tile.flipped_diagonally())
+                        .collect::<Vec<_>>()
+                );
+            }
+            data => panic!("expected compact tiles, got {:?}", data),
+        }
+
+        assert_eq!(CompactTiles::new(&tiles), CompactTiles::from(tiles));
+    }
+
+    // Maps are compared by their tiles, however they are stored.
+    let old = tmx::Map::from_xml(&map("1,2,3,4")).unwrap();
+    let new = tmx::Map::from_data_with_options(map("1,2,3,5").as_bytes(), &options).unwrap();
+    let changes = tmx::diff::diff(&old, &new);
+    assert_eq!(changes.layers_modified.len(), 1);
+    assert_eq!(
+        changes.layers_modified[0].regions,
+        vec![tmx::diff::Region {
+            x: 1,
+            y: 1,
+            width: 1,
+            height: 1
+        }]
+    );
+}
+
+#[cfg(all(feature = "binary", feature = "xml", feature = "base64-data"))]
+#[test]
+fn test_cache_bytes() {
+    use tmx::error::{CacheError, Error};
+    use tmx::loader::MemoryLoader;
+
+    let map = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="hexagonal" renderorder="left-up" width="4" height="4" tilewidth="16" tileheight="16" hexsidelength="6" staggeraxis="y" staggerindex="even" infinite="1" backgroundcolor="#ff00ff" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <tileset firstgid="257" name="embedded" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+  <image source="embedded.png" trans="ff00ff" width="256" height="256"/>
+  <tile id="0" type="Water">
+   <animation>
+    <frame tileid="0" duration="100"/>
+    <frame tileid="1" duration="250"/>
+   </animation>
+  </tile>
+ </tileset>
+ <layer id="1" name="Ground" width="4" height="4" opacity="0.5" offsetx="1.5" visible="0">
+  <data encoding="base64">
+   <chunk x="0" y="0" width="2" height="2">AQAAAAIAAIADAAAABAAAAA==</chunk>
+   <chunk x="2" y="0" width="2" height="2">AAAAAAAAAAAAAAAAAQEAAA==</chunk>
+  </data>
+ </layer>
+ <layer id="2" name="Csv" width="4" height="4">
+  <data encoding="csv">
+   <chunk x="0" y="2" width="2" height="1">5,6</chunk>
+  </data>
+ </layer>
+</map>"##;
+    let terrain = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.8" tiledversion="1.8.0" name="terrain" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+ <image source="terrain.png" width="256" height="256"/>
+</tileset>"##;
+
+    let loader = MemoryLoader::new()
+        .with("level.tmx", map)
+        .with("terrain.tsx", terrain);
+    let tmx = tmx::Map::load_with(&loader, "level.tmx").unwrap();
+
+    let bytes = tmx.to_cache_bytes().unwrap();
+    assert_eq!(&bytes[..4], b"TMXC");
+    assert_eq!(tmx::Map::from_cache_bytes(&bytes).unwrap(), tmx);
+
+    // External tilesets are kept along with their contents.
+    let cached = tmx::Map::from_cache_bytes(&bytes).unwrap();
+    assert_eq!(cached.tilesets[0].tileset().unwrap().name, "terrain");
+
+    let tileset = cached.tilesets[1].tileset().unwrap();
+    let tileset_bytes = tileset.to_cache_bytes().unwrap();
+    assert_eq!(
+        &tmx::Tileset::from_cache_bytes(&tileset_bytes).unwrap(),
+        tileset
+    );
+
+    // Fixed-size layers, with and without compact tiles.
+    let options = tmx::ParseOptions {
+        compact_tiles: true,
+        ..tmx::ParseOptions::default()
+    };
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.0" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="4" nextobjectid="1">
+ <tileset firstgid="1" name="Tiles" tilewidth="16" tileheight="16" tilecount="1" columns="1">
+  <image source="tiles.png" width="16" height="16"/>
+ </tileset>
+ <layer id="1" name="U8" width="2" height="2"><data encoding="csv">1,2,3,2147483652</data></layer>
+ <layer id="2" name="U16" width="2" height="2"><data encoding="csv">1,2,3,4000</data></layer>
+ <layer id="3" name="U32" width="2" height="2"><data encoding="csv">1,2,3,70000</data></layer>
+</map>"##;
+    let fixed = tmx::Map::from_xml(xml).unwrap();
+    assert_eq!(
+        tmx::Map::from_cache_bytes(&fixed.to_cache_bytes().unwrap()).unwrap(),
+        fixed
+    );
+
+    let compact = tmx::Map::from_data_with_options(xml.as_bytes(), &options).unwrap();
+    assert_eq!(
+        tmx::Map::from_cache_bytes(&compact.to_cache_bytes().unwrap()).unwrap(),
+        compact
+    );
+
+    // Corrupt, truncated and mismatched bytes are rejected.
+    let mut corrupt = bytes.clone();
+    let last = corrupt.len() - 1;
+    corrupt[last] ^= 1;
+    assert!(matches!(
+        tmx::Map::from_cache_bytes(&corrupt),
+        Err(Error::Cache(CacheError::Checksum { .. }))
+    ));
+
+    assert!(matches!(
+        tmx::Map::from_cache_bytes(&bytes[..bytes.len() - 1]),
+        Err(Error::Cache(CacheError::Length { .. }))
+    ));
+
+    let mut version = bytes.clone();
+    version[4] = 0xff;
+    assert!(matches!(
+        tmx::Map::from_cache_bytes(&version),
+        Err(Error::Cache(CacheError::UnsupportedVersion(0xff)))
+    ));
+
+    assert!(matches!(
+        tmx::Tileset::from_cache_bytes(&bytes),
+        Err(Error::Cache(CacheError::WrongKind {
+            expected: "tileset"
+        }))
+    ));
+
+    assert!(matches!(
+        tmx::Map::from_cache_bytes(map.as_bytes()),
+        Err(Error::Cache(CacheError::InvalidMagic))
+    ));
+}
+
+#[test]
+fn test_version() {
+    use tmx::metadata::Version;
+
+    assert_eq!("1.2".parse::<Version>().unwrap(), Version::new(1, 2, 0));
+    assert_eq!("1.10.2".parse::<Version>().unwrap(), Version::new(1, 10, 2));
+    assert_eq!(
+        "1.4.0-beta".parse::<Version>().unwrap(),
+        Version::new(1, 4, 0)
+    );
+    assert!("1.10".parse::<Version>().unwrap() > "1.9.2".parse().unwrap());
+    assert!("".parse::<Version>().is_err());
+    assert!("1..2".parse::<Version>().is_err());
+    assert!("1.2.3.4".parse::<Version>().is_err());
+
+    assert_eq!(Version::new(1, 10, 0).to_string(), "1.10");
+    assert_eq!(Version::new(1, 3, 3).to_string(), "1.3.3");
+    assert_eq!(
+        Version::new(1, 3, 3).format_version(),
+        Version::new(1, 2, 0)
+    );
+    assert_eq!(
+        Version::new(1, 9, 2).format_version(),
+        Version::new(1, 9, 0)
+    );
+}
+
+#[cfg(all(feature = "xml", feature = "zstd-data"))]
+#[test]
+fn test_compatibility() {
+    use tmx::{
+        compat::Feature,
+        error::Error,
+        layer::{Compression, DataFormat, Encoding},
+        metadata::Version,
+    };
+
+    let map = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" tiledversion="1.3.3" orientation="hexagonal" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" infinite="1" hexsidelength="8" staggeraxis="y" staggerindex="odd" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" name="test" tilewidth="16" tileheight="16" tilecount="256" columns="16">
+  <image source="tiles16.png" width="256" height="256"/>
+  <tile id="1" type="">
+   <animation>
+    <frame tileid="1" duration="100"/>
+    <frame tileid="2" duration="100"/>
+   </animation>
+  </tile>
+ </tileset>
+ <layer id="1" name="Zstd" width="4" height="4">
+  <data encoding="base64" compression="zstd">
+   <chunk x="0" y="0" width="4" height="4">
+   KLUv/SBAVQEAyAEAAAABAACggAEAAGABAADAAQAAQOAgQIAGADez7PLNTL5pLZD/ssIF
+   </chunk>
+  </data>
+ </layer>
+ <layer id="2" name="Rotated" width="4" height="4">
+  <data encoding="csv">
+   <chunk x="0" y="0" width="2" height="1">1,268435457</chunk>
+  </data>
+ </layer>
+</map>"##;
+
+    let map = tmx::Map::from_xml(map).unwrap();
+    assert_eq!(
+        map.layers[0].format,
+        DataFormat {
+            encoding: Some(Encoding::Base64),
+            compression: Some(Compression::Zstd),
+        }
+    );
+    assert_eq!(
+        map.layers[1].format,
+        DataFormat {
+            encoding: Some(Encoding::Csv),
+            compression: None,
+        }
+    );
+
+    let compatibility = map.compatibility();
+    assert_eq!(
+        compatibility.features,
+        vec![
+            Feature::TileAnimations,
+            Feature::HexagonalMaps,
+            Feature::HexagonalRotation,
+            Feature::InfiniteMaps,
+            Feature::ZstdCompression,
+        ]
+    );
+    assert_eq!(compatibility.tiled_version(), Some(Version::new(1, 3, 0)));
+    assert_eq!(compatibility.format_version(), Some(Version::new(1, 2, 0)));
+    assert_eq!(
+        map.metadata.editor_version().unwrap(),
+        Version::new(1, 3, 3)
+    );
+    assert_eq!(
+        map.metadata.format_version().unwrap(),
+        Version::new(1, 2, 0)
+    );
+
+    assert!(compatibility.check(Version::new(1, 3, 0)).is_ok());
+    assert_eq!(
+        compatibility
+            .unsupported(Version::new(1, 2, 3))
+            .collect::<Vec<_>>(),
+        vec![Feature::ZstdCompression]
+    );
+    assert!(matches!(
+        compatibility.check(Version::new(1, 0, 0)),
+        Err(Error::UnsupportedFeature {
+            feature: Feature::InfiniteMaps,
+            required,
+        }) if required == Version::new(1, 1, 0)
+    ));
+    assert_eq!(
+        compatibility
+            .check(Version::new(1, 0, 0))
+            .unwrap_err()
+            .to_string(),
+        "infinite maps requires Tiled 1.1"
+    );
+
+    // JSON keeps the format on the layer itself, and arrays of GIDs are CSV.
+    let json = tmx::Map::from_json(
+        r##"{ "compressionlevel":-1, "height":2, "infinite":false, "nextlayerid":2, "nextobjectid":1, "orientation":"orthogonal", "renderorder":"right-down", "tiledversion":"1.3.3", "tileheight":16, "tilewidth":16, "type":"map", "version":1.2, "width":2,
+          "layers":[{ "data":[1, 2, 3, 4], "height":2, "id":1, "name":"Plain", "opacity":1, "type":"tilelayer", "visible":true, "width":2, "x":0, "y":0 }],
+          "tilesets":[{ "firstgid":1, "source":"terrain.tsx" }] }"##,
+    )
+    .unwrap();
+    assert_eq!(
+        json.layers[0].format,
+        DataFormat {
+            encoding: Some(Encoding::Csv),
+            compression: None,
+        }
+    );
+    assert!(json.compatibility().features.is_empty());
+    assert_eq!(json.compatibility().tiled_version(), None);
+}
+
+#[cfg(all(feature = "xml", feature = "preserve-unknown"))]
+#[test]
+fn test_compatibility_extra() {
+    use tmx::compat::Feature;
+
+    let map = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.9" tiledversion="1.9.2" class="Level" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" parallaxoriginx="8" nextlayerid="2" nextobjectid="1">
+ <properties>
+  <property name="spawn" type="class" propertytype="Point">
+   <properties>
+    <property name="x" type="int" value="1"/>
+   </properties>
+  </property>
+ </properties>
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Background" width="2" height="2" offsetx="4" parallaxx="0.5">
+  <data encoding="csv">1,2,3,4</data>
+ </layer>
+</map>"##;
+
+    assert_eq!(
+        tmx::Map::from_xml(map).unwrap().compatibility().features,
+        vec![
+            Feature::LayerOffsets,
+            Feature::Parallax,
+            Feature::ClassProperties,
+            Feature::ParallaxOrigin,
+            Feature::ClassAttribute,
+        ]
+    );
+}
+
+#[cfg(all(feature = "xml", feature = "zlib-data"))]
+#[test]
+fn test_to_xml() {
+    use tmx::{
+        error::Error,
+        layer::{Compression, DataFormat, Encoding},
+    };
+
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" tiledversion="1.3.3" orientation="staggered" renderorder="right-down" width="3" height="2" tilewidth="16" tileheight="16" infinite="0" staggeraxis="x" staggerindex="even" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <tileset firstgid="5" name="Tiles &amp; Things" tilewidth="16" tileheight="16" spacing="1" tilecount="4" columns="2">
+  <image source="tiles.png" trans="ff00ff" width="33" height="33"/>
+  <tile id="1">
+   <animation>
+    <frame tileid="1" duration="100"/>
+    <frame tileid="2" duration="250"/>
+   </animation>
+  </tile>
+  <tile id="2" type="Solid"/>
+ </tileset>
+ <layer id="1" name="Ground" width="3" height="2">
+  <data encoding="csv">
+1,2,3,
+2147483652,0,6
+</data>
+ </layer>
+ <layer id="2" name="Hidden" width="3" height="2" visible="0" opacity="0.5" offsetx="4" offsety="-2">
+  <data>
+   <tile gid="1"/>
+   <tile/>
+   <tile/>
+   <tile/>
+   <tile/>
+   <tile gid="2"/>
+  </data>
+ </layer>
+</map>
+"##;
+
+    // Maps saved by Tiled are written back unchanged.
+    let mut map = tmx::Map::from_xml(xml).unwrap();
+    assert_eq!(map.to_xml().unwrap(), xml);
+
+    // The tile layer format is chosen per layer.
+    map.layers[0].format = DataFormat {
+        encoding: Some(Encoding::Base64),
+        compression: Some(Compression::Zlib),
+    };
+    let written = map.to_xml().unwrap();
+    assert!(written.contains(
+        " <layer id=\"1\" name=\"Ground\" width=\"3\" height=\"2\">\n  <data encoding=\"base64\" compression=\"zlib\">\n   "
+    ));
+    assert!(written.contains("\n  </data>\n </layer>\n <layer id=\"2\""));
+    assert_eq!(tmx::Map::from_xml(&written).unwrap(), map);
+
+    map.layers[0].format = DataFormat {
+        encoding: Some(Encoding::Csv),
+        compression: Some(Compression::Zlib),
+    };
+    assert!(matches!(map.to_xml(), Err(Error::UnsupportedDataFormat(_))));
+
+    // Infinite maps are written as chunks.
+    let chunks = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" tiledversion="1.3.3" orientation="orthogonal" renderorder="right-down" width="4" height="4" tilewidth="16" tileheight="16" infinite="1" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Tile Layer 1" width="4" height="4">
+  <data encoding="csv">
+   <chunk x="0" y="0" width="2" height="2">
+1,2,
+3,4
+</chunk>
+   <chunk x="2" y="0" width="2" height="1">
+5,6
+</chunk>
+  </data>
+ </layer>
+</map>
+"##;
+    assert_eq!(
+        tmx::Map::from_xml(chunks).unwrap().to_xml().unwrap(),
+        chunks
+    );
+
+    let tileset = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.2" tiledversion="1.3.3" name="terrain" tilewidth="16" tileheight="16" tilecount="4" columns="2" backgroundcolor="#202020">
+ <image source="terrain.png" width="32" height="32"/>
+</tileset>
+"##;
+    assert_eq!(
+        tmx::Tileset::from_xml(tileset).unwrap().to_xml().unwrap(),
+        tileset
+    );
+}
+
+#[test]
+fn test_to_json() {
+    use tmx::{
+        error::Error,
+        layer::{Compression, DataFormat, Encoding},
+    };
+
+    let json = r##"{ "compressionlevel":-1,
+ "height":2,
+ "infinite":false,
+ "layers":[
+        {
+         "data":[1, 2, 3, 2147483652, 0, 6],
+         "height":2,
+         "id":1,
+         "name":"Ground",
+         "opacity":1,
+         "type":"tilelayer",
+         "visible":true,
+         "width":3,
+         "x":0,
+         "y":0
+        }, 
+        {
+         "data":[1, 0, 0, 0, 0, 2],
+         "height":2,
+         "id":2,
+         "name":"Hidden",
+         "offsetx":4,
+         "offsety":-2.5,
+         "opacity":0.5,
+         "type":"tilelayer",
+         "visible":false,
+         "width":3,
+         "x":0,
+         "y":0
+        }],
+ "nextlayerid":3,
+ "nextobjectid":1,
+ "orientation":"staggered",
+ "renderorder":"right-down",
+ "staggeraxis":"x",
+ "staggerindex":"even",
+ "tiledversion":"1.8.2",
+ "tileheight":16,
+ "tilesets":[
+        {
+         "firstgid":1,
+         "source":"terrain.tsj"
+        }, 
+        {
+         "columns":2,
+         "firstgid":5,
+         "image":"tiles.png",
+         "imageheight":33,
+         "imagewidth":33,
+         "margin":0,
+         "name":"Tiles \"&\" Things",
+         "spacing":1,
+         "tilecount":4,
+         "tileheight":16,
+         "tiles":[
+                {
+                 "animation":[
+                        {
+                         "duration":100,
+                         "tileid":1
+                        }, 
+                        {
+                         "duration":250,
+                         "tileid":2
+                        }],
+                 "id":1
+                }, 
+                {
+                 "id":2,
+                 "type":"Solid"
+                }],
+         "tilewidth":16,
+         "transparentcolor":"#ff00ff"
+        }],
+ "tilewidth":16,
+ "type":"map",
+ "version":"1.8",
+ "width":3
+}"##;
+
+    // Maps saved by Tiled are written back unchanged.
+    let mut map = tmx::Map::from_json(json).unwrap();
+    assert_eq!(map.to_json().unwrap(), json);
+
+    // Layers in base64 have their data as a string, and name their encoding and compression.
+    #[cfg(feature = "zlib-data")]
+    {
+        map.layers[0].format = DataFormat {
+            encoding: Some(Encoding::Base64),
+            compression: Some(Compression::Zlib),
+        };
+        let written = map.to_json().unwrap();
+        assert!(written.contains("\n         \"compression\":\"zlib\",\n         \"data\":\""));
+        assert!(written.contains("\",\n         \"encoding\":\"base64\",\n         \"height\":2,"));
+        assert_eq!(tmx::Map::from_json(&written).unwrap(), map);
+    }
+
+    map.layers[0].format = DataFormat {
+        encoding: Some(Encoding::Csv),
+        compression: Some(Compression::Zlib),
+    };
+    assert!(matches!(
+        map.to_json(),
+        Err(Error::UnsupportedDataFormat(_))
+    ));
+
+    // Infinite maps are written as chunks, starting at the first of them.
+    let chunks = r##"{ "compressionlevel":-1,
+ "height":4,
+ "infinite":true,
+ "layers":[
+        {
+         "chunks":[
+                {
+                 "data":[1, 2, 3, 4],
+                 "height":2,
+                 "width":2,
+                 "x":0,
+                 "y":0
+                }, 
+                {
+                 "data":[5, 6],
+                 "height":1,
+                 "width":2,
+                 "x":2,
+                 "y":0
+                }],
+         "height":4,
+         "id":1,
+         "name":"Tile Layer 1",
+         "opacity":1,
+         "startx":0,
+         "starty":0,
+         "type":"tilelayer",
+         "visible":true,
+         "width":4,
+         "x":0,
+         "y":0
+        }],
+ "nextlayerid":2,
+ "nextobjectid":1,
+ "orientation":"orthogonal",
+ "renderorder":"right-down",
+ "tiledversion":"1.8.2",
+ "tileheight":16,
+ "tilesets":[
+        {
+         "firstgid":1,
+         "source":"terrain.tsj"
+        }],
+ "tilewidth":16,
+ "type":"map",
+ "version":"1.8",
+ "width":4
+}"##;
+    assert_eq!(
+        tmx::Map::from_json(chunks).unwrap().to_json().unwrap(),
+        chunks
+    );
+
+    let tileset = r##"{ "backgroundcolor":"#202020",
+ "columns":2,
+ "image":"terrain.png",
+ "imageheight":32,
+ "imagewidth":32,
+ "margin":0,
+ "name":"terrain",
+ "spacing":0,
+ "tilecount":4,
+ "tiledversion":"1.8.2",
+ "tileheight":16,
+ "tilewidth":16,
+ "type":"tileset",
+ "version":"1.8"
+}"##;
+    assert_eq!(
+        tmx::Tileset::from_json(tileset).unwrap().to_json().unwrap(),
+        tileset
+    );
+}
+
+#[cfg(all(feature = "xml", feature = "preserve-unknown"))]
+#[test]
+fn test_to_json_properties() {
+    let xml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" tiledversion="1.8.2" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <properties>
+  <property name="count" type="int" value="3"/>
+  <property name="hard" type="bool" value="true"/>
+  <property name="notes">two
+lines</property>
+  <property name="spawn" type="class" propertytype="Point">
+   <properties>
+    <property name="x" type="float" value="1.5"/>
+   </properties>
+  </property>
+  <property name="title" value="Level 1"/>
+ </properties>
+ <tileset firstgid="1" source="terrain.tsx"/>
+ <layer id="1" name="Ground" width="1" height="1">
+  <data encoding="csv">
+1
+</data>
+ </layer>
+</map>
+"##;
+
+    let map = tmx::Map::from_xml(xml).unwrap();
+    let json = map.to_json().unwrap();
+    assert!(
+        json.contains(
+            r##" "properties":[
+        {
+         "name":"count",
+         "type":"int",
+         "value":3
+        }, 
+        {
+         "name":"hard",
+         "type":"bool",
+         "value":true
+        }, 
+        {
+         "name":"notes",
+         "type":"string",
+         "value":"two\nlines"
+        }, 
+        {
+         "name":"spawn",
+         "propertytype":"Point",
+         "type":"class",
+         "value":
+            {
+             "x":1.5
+            }
+        }, 
+        {
+         "name":"title",
+         "type":"string",
+         "value":"Level 1"
+        }],
+"##
+        ),
+        "{}",
+        json
+    );
+
+    // Properties read from JSON are written back as they were.
+    let map = tmx::Map::from_json(&json).unwrap();
+    assert_eq!(map.to_json().unwrap(), json);
 }